@@ -1,9 +1,43 @@
-use clap::Parser;
-use ising_montecarlo::field::initialisation::Initialisation;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use ising_montecarlo::field::initialisation::{Initialisation, write_configuration};
 use ising_montecarlo::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
+use ising_montecarlo::geometry::lattice_geometry::interactions::Interactions;
 use ising_montecarlo::geometry::lattice_geometry::lattice::Lattice;
-use ising_montecarlo::settings::SettingsBuilder;
-use rayon;
+use ising_montecarlo::geometry::lattice_geometry::long_range::{LongRange, LongRangeSum};
+use ising_montecarlo::geometry::lattice_geometry::multispin::{
+    MultispinLattice, REPLICAS, UpdateRule,
+};
+use ising_montecarlo::montecarlo::multicanonical::{
+    Multicanonical, MulticanonicalWeights, interface_tension,
+};
+use ising_montecarlo::montecarlo::nfold_way::NFoldWay;
+use ising_montecarlo::montecarlo::parallel_tempering::ParallelTempering;
+use ising_montecarlo::montecarlo::random::random_stream;
+use ising_montecarlo::montecarlo::schedule::{Ramp, RampKind, Schedule};
+use ising_montecarlo::montecarlo::wang_landau::{ModificationSchedule, WangLandau};
+use ising_montecarlo::output::animation::{Animation, AnimationFormat, FrameField, FrameStyle};
+use ising_montecarlo::output::checkpoint::SimulationCheckpoint;
+use ising_montecarlo::output::image::{Colour, ColourMap, Image, ImageFormat, Slice};
+use ising_montecarlo::output::terminal::{
+    RawMode, WatchCommand, half_blocks, key_reader, parse_keys,
+};
+use ising_montecarlo::output::vtk::{VtkField, VtkFormat, VtkSeries};
+use ising_montecarlo::output::writer::{MeasurementWriter, OutputFormat, RunMetadata};
+use ising_montecarlo::settings::{DIMENSIONS, LATTICE_SIZE, Settings, SettingsBuilder};
+use ising_montecarlo::statistics::autocorrelation::{Windowing, integrated_autocorrelation_time};
+use ising_montecarlo::statistics::correlations::{CorrelationRow, Correlations};
+use ising_montecarlo::statistics::derived::{ErrorMethod, Resampling};
+use ising_montecarlo::statistics::domain_walls::{DomainWalls, Interface};
+use ising_montecarlo::statistics::percolation::{ClusterKind, Percolation};
+use ising_montecarlo::statistics::reweighting::Reweighting;
+use ising_montecarlo::statistics::structure_factor::StructureFactor;
+use ising_montecarlo::statistics::summary::Summary;
+use ising_montecarlo::statistics::thermalization::EquilibrationDetector;
+use ising_montecarlo::statistics::time_series::TimeSeries;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -12,32 +46,420 @@ struct Args {
     #[arg(long, default_value_t = 1.0)]
     beta: f64,
 
+    /// Magnetic field h
+    #[arg(long, default_value_t = 0.0)]
+    field: f64,
+
+    /// Ramp of beta from --beta to --beta-final over the sweeps
+    #[arg(long, default_value = "constant")]
+    beta_ramp: RampKind,
+
+    /// Final beta of the beta ramp
+    #[arg(long)]
+    beta_final: Option<f64>,
+
+    /// Ramp of h from --field to --field-final over the sweeps
+    #[arg(long, default_value = "constant")]
+    field_ramp: RampKind,
+
+    /// Final h of the field ramp
+    #[arg(long)]
+    field_final: Option<f64>,
+
+    /// Table of "sweep beta h" rows applied sweep by sweep, replaces the ramps
+    #[arg(long)]
+    schedule_table: Option<PathBuf>,
+
+    /// Rejection-free continuous-time dynamics (n-fold way), one sweep per unit time
+    #[arg(long)]
+    nfold_way: bool,
+
+    /// Multispin-coded engine updating 64 independent replicas of the lattice at once
+    #[arg(long)]
+    multispin: bool,
+
+    /// Update rule of the multispin-coded engine
+    #[arg(long, default_value = "metropolis")]
+    update_rule: UpdateRule,
+
+    /// Ladder of betas for parallel tempering (comma separated), replaces --beta
+    #[arg(long, value_delimiter = ',')]
+    betas: Vec<f64>,
+
+    /// Optimise the parallel tempering ladder by feedback every N sweeps
+    #[arg(long)]
+    feedback_interval: Option<u32>,
+
+    /// Estimate the density of states with Wang-Landau sampling
+    #[arg(long)]
+    wang_landau: bool,
+
+    /// Wang-Landau modification factor schedule
+    #[arg(long, default_value = "inverse-time")]
+    schedule: ModificationSchedule,
+
+    /// Wang-Landau final ln f
+    #[arg(long, default_value_t = 1e-8)]
+    ln_f_final: f64,
+
+    /// Wang-Landau histogram flatness criterion
+    #[arg(long, default_value_t = 0.8)]
+    flatness: f64,
+
+    /// Sweeps between two Wang-Landau flatness checks
+    #[arg(long, default_value_t = 1000)]
+    flatness_interval: u32,
+
+    /// Multicanonical sampling with iterated weights, reweighted to --beta/--betas
+    #[arg(long)]
+    multicanonical: bool,
+
+    /// Sweeps per multicanonical weight iteration
+    #[arg(long, default_value_t = 1000)]
+    iteration_sweeps: u32,
+
+    /// Energy range the multicanonical histogram has to cover (min,max)
+    #[arg(long, value_delimiter = ',')]
+    energy_range: Vec<f64>,
+
+    /// Maximum number of multicanonical weight iterations
+    #[arg(long, default_value_t = 100)]
+    max_iterations: u32,
+
+    /// Width of the energy bins
+    #[arg(long, default_value_t = 1.0)]
+    energy_resolution: f64,
+
+    /// Measure the two-point correlation function G(r) every sweep
+    #[arg(long)]
+    correlations: bool,
+
+    /// Measure the structure factor S(k) and the second-moment correlation length
+    #[arg(long)]
+    structure_factor: bool,
+
+    /// Measure cluster statistics and percolation observables every sweep
+    #[arg(long)]
+    percolation: Option<ClusterKind>,
+
+    /// Print the broken-bond densities and the domain size L(t) every sweep
+    #[arg(long)]
+    domain_walls: bool,
+
+    /// Print the position and roughness of the interface perpendicular to this axis,
+    /// along which the antiperiodic or fixed boundaries then act
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(..DIMENSIONS as u64))]
+    interface_axis: Option<usize>,
+
+    /// Write one record per measurement to this file instead of printing them, for
+    /// single-lattice and parallel tempering runs
+    #[arg(long, conflicts_with_all = ["multispin", "wang_landau", "multicanonical"])]
+    output: Option<PathBuf>,
+
+    /// Format of the output file
+    #[arg(long, default_value = "csv")]
+    format: OutputFormat,
+
+    /// Save an image of the spin configuration every this many sweeps
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    snapshot_interval: Option<u32>,
+
+    /// Directory of the snapshot images
+    #[arg(long, default_value = ".")]
+    snapshot_dir: PathBuf,
+
+    /// Image format of the snapshots
+    #[arg(long, default_value = "png")]
+    snapshot_format: ImageFormat,
+
+    /// Side of the square of pixels drawn for every site
+    #[arg(long, default_value_t = 4)]
+    snapshot_scale: usize,
+
+    /// Colour of the up spins, as #rrggbb
+    #[arg(long, default_value = "#ffffff")]
+    up_colour: Colour,
+
+    /// Colour of the down spins, as #rrggbb
+    #[arg(long, default_value = "#000000")]
+    down_colour: Colour,
+
+    /// Axis normal to the plane shown in the snapshots of 3D lattices
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(..DIMENSIONS as u64))]
+    slice_axis: Option<usize>,
+
+    /// Coordinate of the shown plane along the remaining axes
+    #[arg(long, default_value_t = 0, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(..LATTICE_SIZE as u64))]
+    slice_position: usize,
+
+    /// Record the evolution of the lattice as an animated GIF or a directory of PPM frames
+    #[arg(long, conflicts_with = "resume")]
+    animation: Option<PathBuf>,
+
+    /// Format of the animation
+    #[arg(long, default_value = "gif")]
+    animation_format: AnimationFormat,
+
+    /// Number of sweeps between two frames
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    frame_stride: u32,
+
+    /// Field drawn in the frames
+    #[arg(long, default_value = "spins")]
+    frame_field: FrameField,
+
+    /// Delay between two GIF frames in hundredths of a second
+    #[arg(long, default_value_t = 5)]
+    frame_delay: u16,
+
+    /// Colour map of the averaged spins
+    #[arg(long, default_value = "coolwarm")]
+    colour_map: ColourMap,
+
+    /// Directory of VTK files of the spin field, indexed by sweep in lattice.pvd
+    #[arg(long, conflicts_with = "resume")]
+    vtk: Option<PathBuf>,
+
+    /// VTK file format
+    #[arg(long, default_value = "xml")]
+    vtk_format: VtkFormat,
+
+    /// Number of sweeps between two VTK files
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    vtk_interval: u32,
+
+    /// Render the lattice live in the terminal; arrows or +/- change beta, [/] change h,
+    /// space pauses and q quits
+    #[arg(
+        long,
+        conflicts_with_all = ["betas", "multispin", "wang_landau", "multicanonical", "checkpoint"]
+    )]
+    watch: bool,
+
+    /// Number of sweeps between two refreshes of the watch mode
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    watch_interval: u32,
+
+    /// Pause between two refreshes of the watch mode in milliseconds
+    #[arg(long, default_value_t = 50)]
+    watch_delay: u64,
+
+    /// Change of beta per key press in the watch mode
+    #[arg(long, default_value_t = 0.01)]
+    beta_step: f64,
+
+    /// Change of h per key press in the watch mode
+    #[arg(long, default_value_t = 0.05)]
+    field_step: f64,
+
+    /// Seed of the random numbers, drawn at random if not given
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Checkpoint file written every --checkpoint-interval sweeps and on SIGINT/SIGTERM
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Number of sweeps between two checkpoints
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    checkpoint_interval: u32,
+
+    /// Continue bit-identically from the --checkpoint file
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Reweight the recorded time series and locate the peaks of chi and C
+    #[arg(long)]
+    reweight: bool,
+
+    /// Extrapolate the reweighting this far beyond the simulated betas
+    #[arg(long, default_value_t = 0.02)]
+    reweight_window: f64,
+
+    /// Number of points of the reweighted curves
+    #[arg(long, default_value_t = 21)]
+    reweight_points: usize,
+
+    /// Window selection for the integrated autocorrelation times
+    #[arg(long, default_value = "sokal")]
+    windowing: Windowing,
+
+    /// Number of blocks for the jackknife and bootstrap errors
+    #[arg(long, default_value_t = 20)]
+    jackknife_blocks: usize,
+
+    /// Resampling of the derived quantities chi, C, U4 and xi
+    #[arg(long, default_value = "jackknife")]
+    error_method: ErrorMethod,
+
+    /// Number of bootstrap samples
+    #[arg(long, default_value_t = 200)]
+    bootstrap_samples: usize,
+
     /// Number of Monte Carlo sweeps
     #[arg(long, default_value_t = 100000)]
     sweeps: u32,
 
-    /// Boundary conditions (Periodic or Fixed)
+    /// Number of thermalization sweeps discarded before measuring
+    #[arg(long, default_value_t = 0)]
+    thermalization: u32,
+
+    /// Keep thermalizing until the MSER rule detects equilibration
+    #[arg(long)]
+    auto_thermalization: bool,
+
+    /// Maximum number of sweeps of the automatic thermalization
+    #[arg(long, default_value_t = 100000)]
+    max_thermalization: u32,
+
+    /// Boundary conditions; antiperiodic and fixed ones act along the first axis unless
+    /// an interface axis is given
     #[arg(long, default_value = "periodic")]
     boundary: BoundaryConditions,
 
-    /// Initial state (Random or Uniform)
+    /// Initial state: random, uniform, checkerboard, stripes:<width>, droplet:<radius>,
+    /// magnetization:<m> or file:<path> (plain text, PGM or binary dump)
     #[arg(long, default_value = "random")]
     init: Initialisation,
+
+    /// Save the final configuration as a binary dump, readable with --init file:<path>
+    #[arg(long)]
+    save_configuration: Option<PathBuf>,
+
+    /// Diagonal next-nearest-neighbour coupling J2 (J1 = 1)
+    #[arg(long, default_value_t = 0.0)]
+    j2: f64,
+
+    /// Axial third-neighbour coupling J3 at distance 2 (J1 = 1)
+    #[arg(long, default_value_t = 0.0)]
+    j3: f64,
+
+    /// Restrict the axial coupling to one dimension (ANNNI model)
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(..DIMENSIONS as u64))]
+    axial_dimension: Option<usize>,
+
+    /// Long-range couplings J(r) ~ 1/r^(d+sigma) between all pairs
+    #[arg(long)]
+    sigma: Option<f64>,
+
+    /// Periodic summation of the long-range couplings
+    #[arg(long, default_value = "minimum-image")]
+    long_range_sum: LongRangeSum,
+}
+
+// Exits with a usage error for flags that cannot be combined
+fn argument_conflict(message: &str) -> ! {
+    Args::command()
+        .error(ErrorKind::ArgumentConflict, message)
+        .exit()
+}
+
+// Exits with the error when the initial configuration cannot be read
+fn initial_state<T>(result: io::Result<T>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("Failed to read the initial configuration: {}", error);
+        std::process::exit(1)
+    })
 }
 
 fn main() {
     let args = Args::parse();
+    if args.correlations && args.boundary != BoundaryConditions::Periodic {
+        argument_conflict("--correlations needs periodic boundary conditions");
+    }
+    if args.sigma.is_some() && args.boundary != BoundaryConditions::Periodic {
+        argument_conflict("--sigma needs periodic boundary conditions");
+    }
+    let twisted = matches!(
+        args.boundary,
+        BoundaryConditions::Antiperiodic | BoundaryConditions::Fixed
+    );
+    if args.interface_axis.is_some() && !twisted {
+        argument_conflict("--interface-axis needs antiperiodic or fixed boundary conditions");
+    }
+
+    // The images of 2D lattices show the whole lattice
+    if args.slice_axis.is_some() && DIMENSIONS < 3 {
+        argument_conflict("--slice-axis needs a lattice of three or more dimensions");
+    }
+
+    let nearest_neighbour = args.j2 == 0.0 && args.j3 == 0.0 && args.sigma.is_none();
+    if args.multispin && !nearest_neighbour {
+        argument_conflict("--multispin needs nearest-neighbour interactions only");
+    }
+    if args.multispin && twisted {
+        argument_conflict("--multispin needs periodic or open boundary conditions");
+    }
+
+    // Only single-lattice runs keep all of their state in a checkpoint
+    let single_lattice =
+        !(args.multicanonical || args.wang_landau || args.multispin) && args.betas.is_empty();
+    if args.checkpoint.is_some() && !single_lattice {
+        argument_conflict("--checkpoint is only supported for single-lattice runs");
+    }
 
     println!("Number of threads: {}", rayon::current_num_threads());
 
     let settings = SettingsBuilder {
         beta: args.beta,
         boundary_conditions: args.boundary,
-        site_initialisation: args.init,
+        boundary_axis: args.interface_axis.unwrap_or(0),
+        site_initialisation: args.init.clone(),
+        interactions: Interactions {
+            diagonal: args.j2,
+            axial: args.j3,
+            axial_dimension: args.axial_dimension,
+            long_range: args.sigma.map(|sigma| LongRange {
+                sum: args.long_range_sum,
+                ..LongRange::new(sigma)
+            }),
+        },
+        magnetic_field: args.field,
     }
     .build();
 
-    let mut lattice = Box::new(Lattice::new(settings));
+    if args.multicanonical {
+        run_multicanonical(&args, settings);
+        return;
+    }
+
+    if args.wang_landau {
+        run_wang_landau(&args, settings);
+        return;
+    }
+
+    if args.multispin {
+        run_multispin(&args, settings);
+        return;
+    }
+
+    if !args.betas.is_empty() {
+        run_parallel_tempering(&args, settings);
+        return;
+    }
+
+    if args.watch {
+        run_watch(&args, settings);
+        return;
+    }
+
+    let checkpoint = args.resume.then(|| {
+        let path = args
+            .checkpoint
+            .as_ref()
+            .expect("--resume needs --checkpoint");
+        SimulationCheckpoint::load(path).expect("Failed to read the checkpoint")
+    });
+    let mut lattice = Box::new(match &checkpoint {
+        Some(checkpoint) => checkpoint.lattice(),
+        None => initial_state(Lattice::with_seed(
+            settings,
+            args.seed.unwrap_or_else(rand::random),
+        )),
+    });
+    if args.checkpoint.is_some() {
+        install_signal_handlers();
+    }
 
     println!("Running simulation...");
     println!("Lattice size: {}", lattice.settings.lattice_size);
@@ -50,11 +472,804 @@ fn main() {
         "Site initialisation: {:?}",
         lattice.settings.site_initialisation
     );
+    println!("Interactions: {:?}", lattice.settings.interactions);
+    println!("Magnetic field: {}", lattice.settings.magnetic_field);
     println!("Dimensions: {}", lattice.settings.dimensions);
     println!("Lattice size: {}", lattice.settings.lattice_size);
+    println!("Seed: {}", lattice.seed());
+
+    let schedule = match &args.schedule_table {
+        Some(path) => Schedule::from_file(path).expect("Failed to read the schedule table"),
+        None => Schedule::Ramps {
+            beta: Ramp::new(
+                args.beta_ramp,
+                args.beta,
+                args.beta_final.unwrap_or(args.beta),
+            ),
+            magnetic_field: Ramp::new(
+                args.field_ramp,
+                args.field,
+                args.field_final.unwrap_or(args.field),
+            ),
+        },
+    };
+    let annealing = schedule != Schedule::constant(args.beta, args.field);
+
+    // Thermalize at the start of the schedule
+    if annealing && checkpoint.is_none() {
+        let (beta, magnetic_field) = schedule.at(0, args.sweeps);
+        lattice.set_beta(beta);
+        lattice.set_magnetic_field(magnetic_field);
+    }
+
+    let mut nfold_way = args.nfold_way.then(|| {
+        match checkpoint
+            .as_ref()
+            .and_then(|checkpoint| checkpoint.nfold_way.as_ref())
+        {
+            Some(state) => NFoldWay::restore(&lattice, state),
+            None => NFoldWay::new(&lattice),
+        }
+    });
+    // Random numbers drawn outside of the lattice, one stream per sweep
+    let sweep_rng = |lattice: &Lattice| random_stream(lattice.seed(), lattice.sweeps(), u64::MAX);
+
+    // A resumed run is past the thermalization
+    if checkpoint.is_none() {
+        thermalize(&args, || {
+            let mut rng = sweep_rng(&lattice);
+            match nfold_way.as_mut() {
+                Some(nfold_way) => nfold_way.montecarlo_sweep(&mut lattice, &mut rng),
+                None => lattice.montecarlo_sweep(),
+            };
+            vec![lattice.get_energy(), lattice.get_magnetization().abs()]
+        });
+    }
+
+    let (start, mut time_series, mut correlations, mut structure_factor, mut percolation) =
+        match checkpoint.as_ref() {
+            Some(checkpoint) => {
+                println!("Resuming from sweep {}", checkpoint.sweep);
+                (
+                    checkpoint.sweep as u32,
+                    checkpoint.time_series.clone(),
+                    checkpoint.correlations.clone(),
+                    checkpoint.structure_factor.clone(),
+                    checkpoint.percolation.clone(),
+                )
+            }
+            None => (
+                0,
+                TimeSeries::new(lattice.settings.beta, lattice.len()),
+                args.correlations.then(Correlations::new),
+                args.structure_factor.then(StructureFactor::new),
+                args.percolation.map(Percolation::new),
+            ),
+        };
+
+    // Extra columns of the records, in the order they are measured
+    let mut extras = Vec::new();
+    if annealing {
+        extras.extend(["beta", "magnetic_field"]);
+    }
+    if args.domain_walls {
+        extras.extend(["domain_wall_length", "domain_size"]);
+    }
+    if args.interface_axis.is_some() {
+        extras.extend(["interface_position", "interface_roughness"]);
+    }
+    let algorithm = if args.nfold_way {
+        "n-fold way"
+    } else if args.sigma.is_some() {
+        "Luijten-Blöte cluster"
+    } else {
+        "Metropolis"
+    };
+    let mut writer = args.output.as_ref().map(|path| {
+        match checkpoint
+            .as_ref()
+            .and_then(|checkpoint| checkpoint.output_length)
+        {
+            Some(length) => MeasurementWriter::resume(path, args.format, &extras, length),
+            None => {
+                let metadata = RunMetadata::new(&lattice.settings, Some(lattice.seed()), algorithm);
+                MeasurementWriter::create(path, args.format, &metadata, &extras)
+            }
+        }
+        .expect("Failed to open the output file")
+    });
+
+    let slice = (args.snapshot_interval.is_some() || args.animation.is_some())
+        .then(|| Slice::new(args.slice_axis, args.slice_position));
+    if args.snapshot_interval.is_some() {
+        std::fs::create_dir_all(&args.snapshot_dir)
+            .expect("Failed to create the snapshot directory");
+    }
+    let mut animation = args.animation.as_ref().map(|path| {
+        let style = FrameStyle {
+            slice: slice.unwrap(),
+            scale: args.snapshot_scale,
+            colours: [args.up_colour, args.down_colour],
+            colour_map: args.colour_map,
+        };
+        Animation::create(
+            path,
+            args.animation_format,
+            args.frame_field,
+            args.frame_stride,
+            args.frame_delay,
+            style,
+        )
+        .expect("Failed to create the animation")
+    });
+    let mut vtk = args.vtk.as_ref().map(|directory| {
+        VtkSeries::create(directory, args.vtk_format).expect("Failed to create the VTK series")
+    });
+
+    for sweep in start..args.sweeps {
+        if let Some(path) = args.checkpoint.as_ref() {
+            let interrupted = INTERRUPTED.load(Ordering::SeqCst);
+            if interrupted || (sweep > start && sweep % args.checkpoint_interval == 0) {
+                let output_length = writer.as_mut().map(|writer| {
+                    writer.flush().expect("Failed to write the output file");
+                    output_length(args.output.as_ref().unwrap())
+                });
+                SimulationCheckpoint {
+                    settings: (*lattice.settings).clone(),
+                    fields: lattice.get_fields(),
+                    energy: lattice.get_energy(),
+                    magnetization: lattice.get_magnetization(),
+                    seed: lattice.seed(),
+                    lattice_sweeps: lattice.sweeps(),
+                    sweep: sweep as u64,
+                    nfold_way: nfold_way.as_ref().map(NFoldWay::state),
+                    time_series: time_series.clone(),
+                    correlations: correlations.clone(),
+                    structure_factor: structure_factor.clone(),
+                    percolation: percolation.clone(),
+                    output_length,
+                }
+                .save(path)
+                .expect("Failed to write the checkpoint");
+                if interrupted {
+                    println!("Interrupted, checkpoint written at sweep {}", sweep);
+                    std::process::exit(130);
+                }
+            }
+        }
+
+        // Apply the schedule before every sweep
+        if annealing {
+            let (beta, magnetic_field) = schedule.at(sweep, args.sweeps);
+            lattice.set_beta(beta);
+            lattice.set_magnetic_field(magnetic_field);
+            if let Some(nfold_way) = nfold_way.as_mut() {
+                nfold_way.refresh(&lattice);
+            }
+        }
+
+        let mut rng = sweep_rng(&lattice);
+        match nfold_way.as_mut() {
+            Some(nfold_way) => nfold_way.montecarlo_sweep(&mut lattice, &mut rng),
+            None => lattice.montecarlo_sweep(),
+        };
+        // Running totals kept by the lattice, no recomputation needed
+        let energy = lattice.get_energy();
+        let magnetization = lattice.get_magnetization();
+        time_series.push(energy, magnetization);
+
+        if let Some(correlations) = correlations.as_mut() {
+            // Cluster updates at zero field provide improved estimators
+            let clusters = lattice.last_clusters();
+            if !clusters.is_empty() && lattice.settings.magnetic_field == 0.0 {
+                clusters
+                    .iter()
+                    .for_each(|cluster| correlations.measure_cluster(cluster));
+            } else {
+                correlations.measure(&lattice);
+            }
+        }
+        if let Some(structure_factor) = structure_factor.as_mut() {
+            structure_factor.measure(&lattice);
+        }
+        if let Some(percolation) = percolation.as_mut() {
+            percolation.measure(&lattice, &mut rng);
+        }
+        if let (Some(interval), Some(slice)) = (args.snapshot_interval, slice.as_ref())
+            && sweep % interval == 0
+        {
+            let path = args.snapshot_dir.join(format!(
+                "snapshot_{:06}.{}",
+                sweep,
+                args.snapshot_format.extension()
+            ));
+            Image::from_lattice(
+                &lattice,
+                slice,
+                args.snapshot_scale,
+                [args.up_colour, args.down_colour],
+            )
+            .save(path, args.snapshot_format)
+            .expect("Failed to write the snapshot");
+        }
+        if let Some(vtk) = vtk.as_mut()
+            && sweep % args.vtk_interval == 0
+        {
+            vtk.write(sweep as u64, &[VtkField::spins(&lattice)])
+                .expect("Failed to write the VTK file");
+        }
+        if let Some(animation) = animation.as_mut() {
+            animation
+                .record(&lattice)
+                .expect("Failed to write the animation");
+        }
+        let mut values = Vec::new();
+        if annealing {
+            values.extend([lattice.settings.beta, lattice.settings.magnetic_field]);
+        }
+        let walls = args.domain_walls.then(|| DomainWalls::new(&lattice));
+        if let Some(walls) = walls {
+            values.extend([walls.length() as f64, walls.domain_size()]);
+        }
+        let interface = args
+            .interface_axis
+            .map(|axis| Interface::new(&lattice.get_fields(), axis));
+        if let Some(interface) = interface {
+            values.extend([interface.position, interface.roughness]);
+        }
+
+        if let Some(writer) = writer.as_mut() {
+            writer
+                .write(sweep as u64, energy, magnetization, &values)
+                .expect("Failed to write the measurement");
+            continue;
+        }
+        if annealing {
+            println!(
+                "Sweep: {}, Beta: {}, Field: {}, Energy: {}, Magnetization: {}",
+                sweep,
+                lattice.settings.beta,
+                lattice.settings.magnetic_field,
+                energy,
+                magnetization
+            );
+        } else {
+            println!("Energy: {}", energy);
+        }
+        if let Some(walls) = walls {
+            println!(
+                "Sweep: {}, Broken bonds: {:?}, Domain-wall length: {}, Domain size: {}",
+                sweep,
+                walls.densities(),
+                walls.length(),
+                walls.domain_size()
+            );
+        }
+        if let Some(interface) = interface {
+            println!(
+                "Sweep: {}, Interface position: {}, Roughness: {}",
+                sweep, interface.position, interface.roughness
+            );
+        }
+    }
+    if let Some(writer) = writer.as_mut() {
+        writer.flush().expect("Failed to write the output file");
+    }
+    if let Some(animation) = animation {
+        println!("Animation: {} frames", animation.frames());
+        animation.finish().expect("Failed to write the animation");
+    }
+    if let Some(path) = args.save_configuration.as_ref() {
+        write_configuration(path, &lattice.get_fields()).expect("Failed to save the configuration");
+    }
+
+    if let Some(correlations) = correlations {
+        println!("Correlations along the axes:");
+        print_correlations(&correlations.axis_rows(args.jackknife_blocks));
+        println!("Correlations by distance:");
+        print_correlations(&correlations.distance_rows(args.jackknife_blocks));
+    }
+
+    if let Some(structure_factor) = structure_factor.as_ref() {
+        println!("Structure factor:");
+        for (momentum, s) in structure_factor.structure_factor() {
+            let momentum: Vec<String> = momentum.iter().map(|k| k.to_string()).collect();
+            println!("{} {}", momentum.join(" "), s);
+        }
+    }
+
+    if let Some(percolation) = percolation {
+        println!("{:?} cluster-size distribution:", percolation.kind);
+        for (size, density) in percolation.size_distribution() {
+            println!("{} {}", size, density);
+        }
+        let largest = percolation.largest_cluster_fraction(args.jackknife_blocks);
+        println!(
+            "Largest cluster fraction: {} ± {}",
+            largest.value, largest.error
+        );
+        for (d, probability) in percolation
+            .percolation_probabilities(args.jackknife_blocks)
+            .iter()
+            .enumerate()
+        {
+            println!(
+                "Percolation probability along axis {}: {} ± {}",
+                d, probability.value, probability.error
+            );
+        }
+        let strength = percolation.percolation_strength(args.jackknife_blocks);
+        println!(
+            "Percolation strength: {} ± {}",
+            strength.value, strength.error
+        );
+    }
+
+    // Averages and autocorrelations need stationary time series
+    if !annealing {
+        print_summary(&args, &time_series, structure_factor.as_ref());
+        print_autocorrelation_times(&args, &time_series);
+    }
+
+    // Reweighting needs all measurements at the same beta
+    if args.reweight && !annealing {
+        print_reweighting(&args, Reweighting::new(vec![time_series]));
+    }
+}
+
+// Set by SIGINT and SIGTERM, so that the run can write a checkpoint before exiting
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn interrupt(_signal: i32) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn install_signal_handlers() {
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    unsafe extern "C" {
+        fn signal(signal: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    // Only an atomic store happens in the handler, which is async-signal-safe
+    unsafe {
+        signal(SIGINT, interrupt);
+        signal(SIGTERM, interrupt);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_signal_handlers() {}
+
+fn output_length(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .expect("Failed to read the output file")
+        .len()
+}
+
+// Sweeps checked between two runs of the equilibration detector
+const THERMALIZATION_CHECK: usize = 100;
+
+// Runs the fixed thermalization sweeps, then with --auto-thermalization keeps sweeping
+// until the MSER rule finds the end of the transient in every observable. The sweep
+// returns the observables to watch.
+fn thermalize<F: FnMut() -> Vec<f64>>(args: &Args, mut sweep: F) {
+    // No checkpoint is written before the measurements start
+    let check_interrupted = || {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            println!("Interrupted during the thermalization");
+            std::process::exit(130);
+        }
+    };
+    for _ in 0..args.thermalization {
+        sweep();
+        check_interrupted();
+    }
+    if !args.auto_thermalization {
+        if args.thermalization > 0 {
+            println!("Thermalized for {} sweeps", args.thermalization);
+        }
+        return;
+    }
+
+    let mut detector = EquilibrationDetector::default();
+    while detector.len() < args.max_thermalization as usize {
+        let observables = sweep();
+        check_interrupted();
+        if detector.is_empty() {
+            detector = EquilibrationDetector::new(observables.len());
+        }
+        detector.push(&observables);
+        if detector.len() % THERMALIZATION_CHECK == 0
+            && let Some(truncation) = detector.truncation()
+        {
+            println!(
+                "Thermalized after {} sweeps (MSER truncation at {})",
+                args.thermalization as usize + detector.len(),
+                args.thermalization as usize + truncation
+            );
+            return;
+        }
+    }
+    println!(
+        "No equilibration detected within {} sweeps",
+        args.thermalization as usize + detector.len()
+    );
+}
+
+fn resampling(args: &Args) -> Resampling {
+    Resampling {
+        bootstrap_samples: args.bootstrap_samples,
+        ..Resampling::new(args.error_method, args.jackknife_blocks)
+    }
+}
+
+fn print_summary(
+    args: &Args,
+    time_series: &TimeSeries,
+    structure_factor: Option<&StructureFactor>,
+) {
+    let summary = Summary::new(time_series, structure_factor, &resampling(args));
+    for (name, estimate) in [
+        ("Energy per site", summary.energy),
+        ("Magnetization per site", summary.magnetization),
+        ("|Magnetization| per site", summary.absolute_magnetization),
+        ("Susceptibility", summary.susceptibility),
+        ("Specific heat", summary.specific_heat),
+        ("Binder cumulant", summary.binder_cumulant),
+    ] {
+        println!("{}: {} ± {}", name, estimate.value, estimate.error);
+    }
+    if let Some(xi) = summary.correlation_length {
+        let size = LATTICE_SIZE as f64;
+        println!(
+            "Second-moment correlation length: {} ± {}",
+            xi.value, xi.error
+        );
+        println!("xi / L: {} ± {}", xi.value / size, xi.error / size);
+    }
+}
+
+fn print_autocorrelation_times(args: &Args, time_series: &TimeSeries) {
+    let absolute_magnetizations: Vec<f64> =
+        time_series.magnetizations.iter().map(|m| m.abs()).collect();
+    for (name, series) in [
+        ("energy", &time_series.energies),
+        ("|magnetization|", &absolute_magnetizations),
+    ] {
+        let tau = integrated_autocorrelation_time(series, args.windowing);
+        println!(
+            "Integrated autocorrelation time ({}): {} ± {} (window {})",
+            name, tau.tau, tau.error, tau.window
+        );
+    }
+}
+
+// One row per distance: r, then G(r) and its error for every column
+fn print_correlations(rows: &[CorrelationRow]) {
+    for row in rows {
+        let columns: Vec<String> = row
+            .correlations
+            .iter()
+            .map(|g| format!("{} {}", g.value, g.error))
+            .collect();
+        println!("{} {}", row.distance, columns.join(" "));
+    }
+}
+
+fn print_reweighting(args: &Args, reweighting: Reweighting) {
+    let (min, max) = reweighting.beta_range();
+    let (from, to) = (min - args.reweight_window, max + args.reweight_window);
+
+    println!("Reweighting to beta in [{}, {}]...", from, to);
+    for i in 0..args.reweight_points {
+        let beta = from + (to - from) * i as f64 / (args.reweight_points - 1).max(1) as f64;
+        println!("{:?}", reweighting.averages(beta));
+    }
+
+    let susceptibility = reweighting.peak_with_errors(
+        |averages| averages.susceptibility,
+        from,
+        to,
+        args.jackknife_blocks,
+    );
+    println!(
+        "Susceptibility peak: beta = {} ± {}, chi = {} ± {}",
+        susceptibility.beta.value,
+        susceptibility.beta.error,
+        susceptibility.height.value,
+        susceptibility.height.error
+    );
+    let specific_heat = reweighting.peak_with_errors(
+        |averages| averages.specific_heat,
+        from,
+        to,
+        args.jackknife_blocks,
+    );
+    println!(
+        "Specific heat peak: beta = {} ± {}, C = {} ± {}",
+        specific_heat.beta.value,
+        specific_heat.beta.error,
+        specific_heat.height.value,
+        specific_heat.height.error
+    );
+}
+
+fn run_watch(args: &Args, settings: Settings) {
+    let mut lattice = Box::new(initial_state(Lattice::with_seed(
+        settings,
+        args.seed.unwrap_or_else(rand::random),
+    )));
+    let slice = Slice::new(args.slice_axis, args.slice_position);
+    let colours = [args.up_colour, args.down_colour];
+    let sites = lattice.len() as f64;
+
+    // Ctrl-C leaves the loop so that the terminal is restored
+    install_signal_handlers();
+    let raw_mode = RawMode::enable().expect("The watch mode needs a terminal");
+    let keys = key_reader();
+    // Alternate screen without a cursor
+    print!("\x1b[?1049h\x1b[?25l");
+
+    let mut paused = false;
+    let mut sweep = 0;
+    while sweep < args.sweeps && !INTERRUPTED.load(Ordering::SeqCst) {
+        let mut quit = false;
+        for command in keys.try_iter().flat_map(|bytes| parse_keys(&bytes)) {
+            let (beta, magnetic_field) = (lattice.settings.beta, lattice.settings.magnetic_field);
+            match command {
+                WatchCommand::BetaUp => lattice.set_beta(beta + args.beta_step),
+                WatchCommand::BetaDown => lattice.set_beta((beta - args.beta_step).max(0.0)),
+                WatchCommand::FieldUp => {
+                    lattice.set_magnetic_field(magnetic_field + args.field_step)
+                }
+                WatchCommand::FieldDown => {
+                    lattice.set_magnetic_field(magnetic_field - args.field_step)
+                }
+                WatchCommand::Pause => paused = !paused,
+                WatchCommand::Quit => quit = true,
+            }
+        }
+        if quit {
+            break;
+        }
+        if !paused {
+            for _ in 0..args.watch_interval.min(args.sweeps - sweep) {
+                lattice.montecarlo_sweep();
+                sweep += 1;
+            }
+        }
+
+        let image = Image::from_lattice(&lattice, &slice, 1, colours);
+        print!("\x1b[H{}", half_blocks(&image));
+        println!(
+            "Sweep: {}  Beta: {:.4}  Field: {:.4}\x1b[K",
+            sweep, lattice.settings.beta, lattice.settings.magnetic_field
+        );
+        println!(
+            "Energy: {:.4}  Magnetization: {:.4}  (per site)\x1b[K",
+            lattice.get_energy() / sites,
+            lattice.get_magnetization() / sites
+        );
+        println!(
+            "Arrows or +/- beta, [ ] field, space {}, q quit\x1b[K",
+            if paused { "resume" } else { "pause" }
+        );
+        std::thread::sleep(std::time::Duration::from_millis(args.watch_delay));
+    }
+
+    print!("\x1b[?25h\x1b[?1049l");
+    drop(raw_mode);
+    println!(
+        "Stopped after {} sweeps at beta {} and field {}",
+        sweep, lattice.settings.beta, lattice.settings.magnetic_field
+    );
+    println!("Energy: {}", lattice.get_energy());
+    println!("Magnetization: {}", lattice.get_magnetization());
+}
+
+fn run_multispin(args: &Args, settings: Settings) {
+    let mut lattice = initial_state(MultispinLattice::new(settings, args.update_rule));
+
+    println!(
+        "Running multispin-coded simulation of {} replicas...",
+        REPLICAS
+    );
+    println!("Beta: {}", lattice.settings.beta);
+    println!("Update rule: {:?}", args.update_rule);
 
     for _ in 0..args.sweeps {
         lattice.montecarlo_sweep();
         println!("Energy: {}", lattice.get_energy());
     }
+    println!("Energies: {:?}", lattice.get_energies());
+    println!("Magnetizations: {:?}", lattice.get_magnetizations());
+}
+
+fn run_parallel_tempering(args: &Args, settings: Settings) {
+    let mut parallel_tempering = initial_state(ParallelTempering::with_seed(
+        settings,
+        &args.betas,
+        args.seed.unwrap_or_else(rand::random),
+    ));
+
+    println!("Running parallel tempering...");
+    println!("Betas: {:?}", parallel_tempering.betas);
+
+    let new_time_series = |parallel_tempering: &ParallelTempering| -> Vec<TimeSeries> {
+        let sites = parallel_tempering.replicas[0].len();
+        parallel_tempering
+            .betas
+            .iter()
+            .map(|beta| TimeSeries::new(*beta, sites))
+            .collect()
+    };
+    thermalize(args, || {
+        parallel_tempering.montecarlo_sweep();
+        // Every beta has to be equilibrated
+        let mut observables = parallel_tempering.get_energies();
+        observables.extend(
+            (0..parallel_tempering.betas.len())
+                .map(|i| parallel_tempering.replica_at(i).get_magnetization().abs()),
+        );
+        observables
+    });
+
+    let mut time_series = new_time_series(&parallel_tempering);
+
+    // One record per beta and sweep
+    let mut writer = args.output.as_ref().map(|path| {
+        let mut metadata = RunMetadata::new(
+            &parallel_tempering.replicas[0].settings,
+            Some(parallel_tempering.seed()),
+            "parallel tempering",
+        );
+        metadata.add("betas", format!("{:?}", parallel_tempering.betas).as_str());
+        MeasurementWriter::create(path, args.format, &metadata, &["beta"])
+            .expect("Failed to create the output file")
+    });
+
+    for sweep in 1..=args.sweeps {
+        parallel_tempering.montecarlo_sweep();
+        let energies = parallel_tempering.get_energies();
+        if writer.is_none() {
+            println!("Energies: {:?}", energies);
+        }
+        for (i, energy) in energies.iter().enumerate() {
+            let magnetization = parallel_tempering.replica_at(i).get_magnetization();
+            time_series[i].push(*energy, magnetization);
+            if let Some(writer) = writer.as_mut() {
+                writer
+                    .write(
+                        sweep as u64,
+                        *energy,
+                        magnetization,
+                        &[parallel_tempering.betas[i]],
+                    )
+                    .expect("Failed to write the measurement");
+            }
+        }
+
+        if let Some(interval) = args.feedback_interval
+            && sweep % interval == 0
+            && sweep < args.sweeps
+        {
+            parallel_tempering.optimise_betas();
+            println!("Optimised betas: {:?}", parallel_tempering.betas);
+
+            // Only measurements at the final betas are reweighted
+            time_series = new_time_series(&parallel_tempering);
+        }
+    }
+
+    if let Some(writer) = writer.as_mut() {
+        writer.flush().expect("Failed to write the output file");
+    }
+
+    for series in time_series.iter() {
+        println!("Beta: {}", series.beta);
+        print_summary(args, series, None);
+        print_autocorrelation_times(args, series);
+    }
+
+    println!(
+        "Swap acceptance rates: {:?}",
+        parallel_tempering.swap_acceptance_rates()
+    );
+    match parallel_tempering.mean_round_trip_time() {
+        Some(time) => println!(
+            "Mean round-trip time: {} sweeps over {} round trips",
+            time,
+            parallel_tempering.round_trips().len()
+        ),
+        None => println!("Mean round-trip time: no round trip completed"),
+    }
+
+    if args.reweight {
+        print_reweighting(args, Reweighting::new(time_series));
+    }
+}
+
+fn run_wang_landau(args: &Args, settings: Settings) {
+    let mut wang_landau = initial_state(WangLandau::new(
+        settings,
+        args.energy_resolution,
+        args.schedule,
+    ));
+    wang_landau.ln_f_final = args.ln_f_final;
+    wang_landau.flatness = args.flatness;
+
+    println!("Running Wang-Landau sampling...");
+    let mut rng = rand::rng();
+    while !wang_landau.is_converged() {
+        for _ in 0..args.flatness_interval {
+            wang_landau.montecarlo_sweep(&mut rng);
+        }
+        wang_landau.update_modification_factor();
+        println!("ln f: {}", wang_landau.ln_f);
+    }
+
+    let density_of_states = wang_landau.density_of_states();
+    for (energy, ln_g) in density_of_states.levels.iter() {
+        println!("ln g({}): {}", energy, ln_g);
+    }
+
+    // Canonical averages at the requested betas
+    let betas = match args.betas.is_empty() {
+        true => vec![args.beta],
+        false => args.betas.clone(),
+    };
+    for beta in betas {
+        println!("{:?}", density_of_states.canonical(beta));
+    }
+}
+
+fn run_multicanonical(args: &Args, settings: Settings) {
+    // Interfaces span the lattice orthogonally to one axis
+    let area = usize::pow(settings.lattice_size, settings.dimensions as u32 - 1) as f64;
+    let weights = MulticanonicalWeights::canonical(settings.beta, args.energy_resolution);
+    let mut multicanonical = initial_state(Multicanonical::new(settings, weights));
+    multicanonical.flatness = args.flatness;
+    if let [min, max] = args.energy_range[..] {
+        multicanonical.range = Some((min, max));
+    }
+
+    println!("Iterating multicanonical weights...");
+    let mut rng = rand::rng();
+    match multicanonical.iterate_weights(args.iteration_sweeps, args.max_iterations, &mut rng) {
+        Some(iterations) => println!("Flat histogram after {} iterations", iterations),
+        None => println!(
+            "Histogram not flat after {} iterations",
+            args.max_iterations
+        ),
+    }
+
+    println!("Running multicanonical simulation...");
+    for _ in 0..args.sweeps {
+        multicanonical.measure(&mut rng);
+    }
+
+    let sites = multicanonical.lattice.len() as f64;
+    let betas = match args.betas.is_empty() {
+        true => vec![args.beta],
+        false => args.betas.clone(),
+    };
+    for beta in betas {
+        let energy = multicanonical.reweight(beta, |energy, _| energy);
+        let magnetization = multicanonical.reweight(beta, |_, magnetization| magnetization.abs());
+        println!(
+            "Beta: {}, Energy: {}, |Magnetization|: {}",
+            beta,
+            energy / sites,
+            magnetization / sites
+        );
+        match interface_tension(&multicanonical.energy_histogram(beta), area) {
+            Some(tension) => println!("Interface tension (energy histogram): {}", tension),
+            None => println!("Interface tension (energy histogram): no double peak"),
+        }
+        match interface_tension(&multicanonical.magnetization_histogram(beta), area) {
+            Some(tension) => println!("Interface tension (magnetization histogram): {}", tension),
+            None => println!("Interface tension (magnetization histogram): no double peak"),
+        }
+    }
 }
@@ -1,5 +1,417 @@
-#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+use crate::field::ising::IsingField;
+use crate::geometry::utils::position_to_lattice;
+use crate::montecarlo::random::random_stream;
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+use rand::Rng;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Header of the compact binary dump: the magic, the number of sites as a little-endian
+// u64, then one bit per site, least significant first, set for up spins
+const MAGIC: &[u8; 8] = b"ISINGCFG";
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Initialisation {
     Random,
     Uniform,
+    // Neel state, alternating spins on the two sublattices
+    Checkerboard,
+    // Stripes of the given width perpendicular to the first axis, starting up
+    Stripes(usize),
+    // Ball of down spins of the given radius at the centre, in an up background
+    Droplet(f64),
+    // Random state with exactly the given magnetization per site
+    FixedMagnetization(f64),
+    // Configuration saved as plain text, PGM image or binary dump
+    FromFile(PathBuf),
+}
+
+// Parsed from "random", "uniform", "checkerboard", "stripes:<width>",
+// "droplet:<radius>", "magnetization:<m>" or "file:<path>"
+impl FromStr for Initialisation {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (kind, argument) = match text.split_once(':') {
+            Some((kind, argument)) => (kind, Some(argument)),
+            None => (text, None),
+        };
+        let number = |name: &str| {
+            argument
+                .ok_or(format!("{} needs a value, as {}:<value>", kind, kind))?
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid {} in {}", name, text))
+        };
+        match kind.to_lowercase().as_str() {
+            "random" => Ok(Initialisation::Random),
+            "uniform" => Ok(Initialisation::Uniform),
+            "checkerboard" | "neel" => Ok(Initialisation::Checkerboard),
+            "stripes" => match argument.map(str::parse::<usize>) {
+                None => Ok(Initialisation::Stripes(1)),
+                Some(Ok(width)) if width > 0 => Ok(Initialisation::Stripes(width)),
+                Some(_) => Err(format!("Invalid stripe width in {}", text)),
+            },
+            "droplet" => Ok(Initialisation::Droplet(number("radius")?)),
+            "magnetization" => match number("magnetization")? {
+                m if (-1.0..=1.0).contains(&m) => Ok(Initialisation::FixedMagnetization(m)),
+                _ => Err("The magnetization per site lies in [-1, 1]".to_string()),
+            },
+            "file" => match argument {
+                Some(path) if !path.is_empty() => Ok(Initialisation::FromFile(path.into())),
+                _ => Err("file needs a path, as file:<path>".to_string()),
+            },
+            _ => Err(format!(
+                "Unknown initialisation {}, expected random, uniform, checkerboard, \
+                 stripes:<width>, droplet:<radius>, magnetization:<m> or file:<path>",
+                text
+            )),
+        }
+    }
+}
+
+fn spin(up: bool) -> IsingField {
+    match up {
+        true => IsingField::Up,
+        false => IsingField::Down,
+    }
+}
+
+impl Initialisation {
+    // Whether the state depends on the seed
+    pub fn is_random(&self) -> bool {
+        matches!(
+            self,
+            Initialisation::Random | Initialisation::FixedMagnetization(_)
+        )
+    }
+
+    // Initial field of every site, in the order of the positions. Random states use the
+    // counter reserved for the initial state, sweeps count up from 0.
+    pub fn fields(&self, seed: u64) -> io::Result<Vec<IsingField>> {
+        let sites = usize::pow(LATTICE_SIZE, DIMENSIONS as u32);
+        let mut rng = random_stream(seed, u64::MAX, 0);
+        let fields = match self {
+            Initialisation::Random => (0..sites).map(|_| spin(rng.random())).collect(),
+            Initialisation::Uniform => vec![IsingField::Up; sites],
+            Initialisation::Checkerboard => (0..sites)
+                .map(|position| {
+                    spin(
+                        position_to_lattice(position)
+                            .iter()
+                            .sum::<usize>()
+                            .is_multiple_of(2),
+                    )
+                })
+                .collect(),
+            Initialisation::Stripes(width) => (0..sites)
+                .map(|position| spin((position_to_lattice(position)[0] / width).is_multiple_of(2)))
+                .collect(),
+            Initialisation::Droplet(radius) => {
+                let centre = (LATTICE_SIZE - 1) as f64 / 2.0;
+                (0..sites)
+                    .map(|position| {
+                        let distance = position_to_lattice(position)
+                            .iter()
+                            .map(|x| (*x as f64 - centre).powi(2))
+                            .sum::<f64>()
+                            .sqrt();
+                        spin(distance > *radius)
+                    })
+                    .collect()
+            }
+            Initialisation::FixedMagnetization(magnetization) => {
+                let up = (sites as f64 * (1.0 + magnetization) / 2.0).round() as usize;
+                // The first `up` sites of a partial Fisher-Yates shuffle
+                let mut positions: Vec<usize> = (0..sites).collect();
+                for i in 0..up {
+                    let j = rng.random_range(i..sites);
+                    positions.swap(i, j);
+                }
+                let mut fields = vec![IsingField::Down; sites];
+                positions[..up]
+                    .iter()
+                    .for_each(|position| fields[*position] = IsingField::Up);
+                fields
+            }
+            Initialisation::FromFile(path) => read_configuration(path)?,
+        };
+        Ok(fields)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Configuration in one of the three formats, told apart by their first bytes
+pub fn read_configuration<P: AsRef<Path>>(path: P) -> io::Result<Vec<IsingField>> {
+    let bytes = fs::read(path)?;
+    let sites = usize::pow(LATTICE_SIZE, DIMENSIONS as u32);
+    let fields = if bytes.starts_with(MAGIC) {
+        parse_binary(&bytes)?
+    } else if bytes.starts_with(b"P2") || bytes.starts_with(b"P5") {
+        parse_pgm(&bytes)?
+    } else {
+        parse_text(&String::from_utf8_lossy(&bytes))?
+    };
+    if fields.len() != sites {
+        return Err(invalid(format!(
+            "The configuration has {} sites, the lattice {}",
+            fields.len(),
+            sites
+        )));
+    }
+    Ok(fields)
+}
+
+// Compact binary dump of a configuration
+pub fn write_configuration<P: AsRef<Path>>(path: P, fields: &[IsingField]) -> io::Result<()> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend((fields.len() as u64).to_le_bytes());
+    for chunk in fields.chunks(8) {
+        let byte = chunk
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| **field == IsingField::Up)
+            .fold(0u8, |byte, (i, _)| byte | 1 << i);
+        bytes.push(byte);
+    }
+    fs::write(path, bytes)
+}
+
+fn parse_binary(bytes: &[u8]) -> io::Result<Vec<IsingField>> {
+    let truncated = || invalid("Truncated configuration dump".to_string());
+    let length = bytes.get(8..16).ok_or_else(truncated)?;
+    let sites = u64::from_le_bytes(length.try_into().unwrap()) as usize;
+    let bits = &bytes[16..];
+    if bits.len() < sites.div_ceil(8) {
+        return Err(truncated());
+    }
+    Ok((0..sites)
+        .map(|i| spin(bits[i / 8] >> (i % 8) & 1 == 1))
+        .collect())
+}
+
+// Spins as whitespace-separated tokens in the order of the positions, with '#' comments:
+// 1, +1 or + for up and -1, 0 or - for down
+fn parse_text(text: &str) -> io::Result<Vec<IsingField>> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap())
+        .flat_map(str::split_whitespace)
+        .map(|token| match token {
+            "1" | "+1" | "+" => Ok(IsingField::Up),
+            "-1" | "0" | "-" => Ok(IsingField::Down),
+            _ => Err(invalid(format!("Invalid spin {} in configuration", token))),
+        })
+        .collect()
+}
+
+// Greyscale image with the first axis horizontal and the others stacked vertically,
+// light pixels up. Images scaled up by an integer factor, as the snapshots, are read
+// back at one pixel per site.
+fn parse_pgm(bytes: &[u8]) -> io::Result<Vec<IsingField>> {
+    // Header tokens, skipping comments, and the offset of the pixels after them
+    let mut tokens = Vec::new();
+    let mut i = 2;
+    while tokens.len() < 3 {
+        match bytes.get(i) {
+            None => return Err(invalid("Truncated PGM header".to_string())),
+            Some(b'#') => {
+                while bytes.get(i).is_some_and(|byte| *byte != b'\n') {
+                    i += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => i += 1,
+            Some(_) => {
+                let start = i;
+                while bytes.get(i).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+                    i += 1;
+                }
+                let token = String::from_utf8_lossy(&bytes[start..i]);
+                let value = token
+                    .parse::<usize>()
+                    .map_err(|_| invalid(format!("Invalid PGM header entry {}", token)))?;
+                tokens.push(value);
+            }
+        }
+    }
+    let (width, height, maximum) = (tokens[0], tokens[1], tokens[2]);
+    let pixels: Vec<usize> = match bytes[1] {
+        // A single whitespace character separates the header from binary pixels
+        b'5' => {
+            let start = i + 1;
+            match maximum < 256 {
+                true => bytes
+                    .get(start..)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|p| *p as usize)
+                    .collect(),
+                false => bytes
+                    .get(start..)
+                    .unwrap_or_default()
+                    .chunks_exact(2)
+                    .map(|p| u16::from_be_bytes([p[0], p[1]]) as usize)
+                    .collect(),
+            }
+        }
+        _ => String::from_utf8_lossy(&bytes[i..])
+            .split_whitespace()
+            .map(|token| {
+                token
+                    .parse()
+                    .map_err(|_| invalid(format!("Invalid PGM pixel {}", token)))
+            })
+            .collect::<io::Result<_>>()?,
+    };
+
+    let scale = width / LATTICE_SIZE;
+    let rows = usize::pow(LATTICE_SIZE, DIMENSIONS as u32 - 1);
+    if scale == 0 || width != LATTICE_SIZE * scale || height != rows * scale {
+        return Err(invalid(format!(
+            "A {}x{} image does not match the lattice",
+            width, height
+        )));
+    }
+    if pixels.len() < width * height {
+        return Err(invalid("Truncated PGM pixels".to_string()));
+    }
+    Ok((0..LATTICE_SIZE * rows)
+        .map(|position| {
+            let (x, y) = (position % LATTICE_SIZE, position / LATTICE_SIZE);
+            spin(2 * pixels[y * scale * width + x * scale] > maximum)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::utils::lattice_to_position;
+
+    const SITES: usize = 64;
+
+    fn magnetization(fields: &[IsingField]) -> f64 {
+        fields.iter().map(|field| field.value()).sum()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("random".parse(), Ok(Initialisation::Random));
+        assert_eq!("Neel".parse(), Ok(Initialisation::Checkerboard));
+        assert_eq!("stripes".parse(), Ok(Initialisation::Stripes(1)));
+        assert_eq!("stripes:2".parse(), Ok(Initialisation::Stripes(2)));
+        assert_eq!("droplet:1.5".parse(), Ok(Initialisation::Droplet(1.5)));
+        assert_eq!(
+            "magnetization:-0.5".parse(),
+            Ok(Initialisation::FixedMagnetization(-0.5))
+        );
+        assert_eq!(
+            "file:a:b.txt".parse(),
+            Ok(Initialisation::FromFile("a:b.txt".into()))
+        );
+        assert!("stripes:0".parse::<Initialisation>().is_err());
+        assert!("droplet".parse::<Initialisation>().is_err());
+        assert!("magnetization:2".parse::<Initialisation>().is_err());
+        assert!("spiral".parse::<Initialisation>().is_err());
+    }
+
+    #[test]
+    fn test_fields() {
+        let uniform = Initialisation::Uniform.fields(0).unwrap();
+        assert_eq!(uniform, vec![IsingField::Up; SITES]);
+        let random = Initialisation::Random.fields(7).unwrap();
+        assert_eq!(random, Initialisation::Random.fields(7).unwrap());
+        assert_ne!(random, Initialisation::Random.fields(8).unwrap());
+
+        let checkerboard = Initialisation::Checkerboard.fields(0).unwrap();
+        assert_eq!(magnetization(&checkerboard), 0.0);
+        assert_eq!(checkerboard[..2], [IsingField::Up, IsingField::Down]);
+        // Site (0, 1, 0) is a neighbour of the origin
+        assert_eq!(checkerboard[4], IsingField::Down);
+
+        let stripes = Initialisation::Stripes(2).fields(0).unwrap();
+        assert_eq!(
+            stripes[..4],
+            [
+                IsingField::Up,
+                IsingField::Up,
+                IsingField::Down,
+                IsingField::Down
+            ]
+        );
+
+        // The centre of the test lattice is (1.5, 1.5, 1.5), the 8 sites around it lie at
+        // a distance sqrt(3) / 2
+        let droplet = Initialisation::Droplet(1.0).fields(0).unwrap();
+        assert_eq!(magnetization(&droplet), 64.0 - 2.0 * 8.0);
+        assert_eq!(droplet[lattice_to_position([1, 2, 1])], IsingField::Down);
+        assert_eq!(droplet[0], IsingField::Up);
+
+        for seed in 0..10 {
+            let fields = Initialisation::FixedMagnetization(0.25)
+                .fields(seed)
+                .unwrap();
+            assert_eq!(magnetization(&fields), 16.0);
+        }
+        assert!(Initialisation::FixedMagnetization(0.25).is_random());
+    }
+
+    #[test]
+    fn test_from_file() {
+        let directory = std::env::temp_dir().join(format!("configuration-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let fields = Initialisation::Random.fields(3).unwrap();
+        let load = |name: &str, bytes: &[u8]| {
+            let path = directory.join(name);
+            fs::write(&path, bytes).unwrap();
+            Initialisation::FromFile(path).fields(0)
+        };
+
+        let path = directory.join("dump.bin");
+        write_configuration(&path, &fields).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 16 + 8);
+        assert_eq!(Initialisation::FromFile(path).fields(0).unwrap(), fields);
+
+        let text: Vec<&str> = fields
+            .iter()
+            .map(|field| match field {
+                IsingField::Up => "+1",
+                IsingField::Down => "-1",
+            })
+            .collect();
+        let text = format!("# Saved state\n{}\n", text.join(" "));
+        assert_eq!(load("state.txt", text.as_bytes()).unwrap(), fields);
+        assert!(load("short.txt", b"1 -1 1").is_err());
+        assert!(load("bad.txt", b"x").is_err());
+
+        // Binary PGM scaled by 2, with the 3D lattice stacked into a 4x16 image
+        let mut pgm = b"P5\n# snapshot\n8 32\n255\n".to_vec();
+        for y in 0..32 {
+            for x in 0..8 {
+                let up = fields[(y / 2) * 4 + x / 2] == IsingField::Up;
+                pgm.push(if up { 255 } else { 0 });
+            }
+        }
+        assert_eq!(load("state.pgm", &pgm).unwrap(), fields);
+        let ascii: Vec<&str> = fields
+            .iter()
+            .map(|field| match field {
+                IsingField::Up => "15",
+                IsingField::Down => "0",
+            })
+            .collect();
+        let ascii = format!("P2 4 16 15\n{}", ascii.join(" "));
+        assert_eq!(load("ascii.pgm", ascii.as_bytes()).unwrap(), fields);
+        assert!(load("wrong.pgm", b"P5 3 3 255\n123456789").is_err());
+
+        assert!(
+            Initialisation::FromFile(directory.join("missing"))
+                .fields(0)
+                .is_err()
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
 }
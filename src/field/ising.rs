@@ -1,4 +1,3 @@
-use crate::field::initialisation::Initialisation;
 use crate::field::schema::Field;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -8,16 +7,10 @@ pub enum IsingField {
 }
 
 impl IsingField {
-    pub fn new(initialisation: Initialisation) -> Self {
-        match initialisation {
-            Initialisation::Random => {
-                if rand::random::<f64>() < 0.5 {
-                    IsingField::Up
-                } else {
-                    IsingField::Down
-                }
-            }
-            Initialisation::Uniform => IsingField::Up,
+    pub fn value(&self) -> f64 {
+        match self {
+            IsingField::Up => 1.0,
+            IsingField::Down => -1.0,
         }
     }
 }
@@ -52,10 +45,8 @@ mod tests {
     }
 
     #[test]
-    fn test_ising_field_new() {
-        let random = IsingField::new(Initialisation::Random);
-        let uniform = IsingField::new(Initialisation::Uniform);
-        assert!(random == IsingField::Up || random == IsingField::Down);
-        assert_eq!(uniform, IsingField::Up);
+    fn test_ising_field_value() {
+        assert_eq!(IsingField::Up.value(), 1.0);
+        assert_eq!(IsingField::Down.value(), -1.0);
     }
 }
@@ -1,5 +1,99 @@
+use crate::geometry::utils::{position_to_lattice, shift_position};
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+
+// Antiperiodic and fixed boundaries act along the boundary axis of the settings, and
+// are periodic along the other axes
 #[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
 pub enum BoundaryConditions {
     Periodic,
     Open,
+    // Periodic with the bonds across the boundary reversed, which forces an interface
+    Antiperiodic,
+    // Up spins fixed beyond the first layer and down spins beyond the last one
+    Fixed,
+}
+
+// Partner of a site at some offset
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Partner {
+    // Site of the lattice, with the sign of the coupling to it
+    Site(usize, f64),
+    // Fixed spin beyond the boundary
+    Wall(f64),
+    Outside,
+}
+
+impl BoundaryConditions {
+    // Whether the lattice wraps around along the dimension
+    pub fn wraps(&self, dimension: usize, axis: usize) -> bool {
+        match self {
+            BoundaryConditions::Periodic | BoundaryConditions::Antiperiodic => true,
+            BoundaryConditions::Open => false,
+            BoundaryConditions::Fixed => dimension != axis,
+        }
+    }
+
+    pub fn partner(&self, position: usize, offset: [isize; DIMENSIONS], axis: usize) -> Partner {
+        let shifted = position_to_lattice(position)[axis] as isize + offset[axis];
+        let crossed = !(0..LATTICE_SIZE as isize).contains(&shifted);
+        match (self, crossed) {
+            (BoundaryConditions::Open, _) => match shift_position(position, offset, false) {
+                Some(partner) => Partner::Site(partner, 1.0),
+                None => Partner::Outside,
+            },
+            (BoundaryConditions::Antiperiodic, true) => {
+                Partner::Site(shift_position(position, offset, true).unwrap(), -1.0)
+            }
+            (BoundaryConditions::Fixed, true) => {
+                Partner::Wall(if shifted < 0 { 1.0 } else { -1.0 })
+            }
+            _ => Partner::Site(shift_position(position, offset, true).unwrap(), 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::utils::lattice_to_position;
+
+    #[test]
+    fn test_partner() {
+        let corner = lattice_to_position([3, 0, 0]);
+        let mut next = [0; DIMENSIONS];
+        next[0] = 1;
+        assert_eq!(
+            BoundaryConditions::Periodic.partner(corner, next, 0),
+            Partner::Site(0, 1.0)
+        );
+        assert_eq!(
+            BoundaryConditions::Open.partner(corner, next, 0),
+            Partner::Outside
+        );
+        assert_eq!(
+            BoundaryConditions::Antiperiodic.partner(corner, next, 0),
+            Partner::Site(0, -1.0)
+        );
+        assert_eq!(
+            BoundaryConditions::Fixed.partner(corner, next, 0),
+            Partner::Wall(-1.0)
+        );
+        assert_eq!(
+            BoundaryConditions::Fixed.partner(0, next.map(|x| -x), 0),
+            Partner::Wall(1.0)
+        );
+
+        // Other axes stay periodic
+        let mut previous = [0; DIMENSIONS];
+        previous[1] = -1;
+        assert_eq!(
+            BoundaryConditions::Fixed.partner(0, previous, 0),
+            Partner::Site(lattice_to_position([0, 3, 0]), 1.0)
+        );
+        assert_eq!(
+            BoundaryConditions::Antiperiodic.partner(0, previous, 0),
+            Partner::Site(lattice_to_position([0, 3, 0]), 1.0)
+        );
+        assert!(BoundaryConditions::Fixed.wraps(1, 0) && !BoundaryConditions::Fixed.wraps(0, 0));
+    }
 }
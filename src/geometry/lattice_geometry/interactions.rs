@@ -0,0 +1,160 @@
+use crate::geometry::lattice_geometry::long_range::LongRange;
+use crate::settings::DIMENSIONS;
+
+// Further-neighbour couplings, in units of the nearest-neighbour coupling J1 = 1.
+// A positive coupling is ferromagnetic, a negative one antiferromagnetic.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Interactions {
+    // Diagonal next-nearest neighbours, e.g. (±1, ±1, 0) (J2)
+    pub diagonal: f64,
+    // Axial neighbours at distance 2, e.g. (±2, 0, 0) (J3)
+    pub axial: f64,
+    // Restrict the axial shell to a single dimension (ANNNI model)
+    pub axial_dimension: Option<usize>,
+    // Power-law coupling between all pairs, replacing the neighbour shells
+    pub long_range: Option<LongRange>,
+}
+
+impl Default for Interactions {
+    fn default() -> Self {
+        Self::nearest_neighbour()
+    }
+}
+
+impl Interactions {
+    pub fn nearest_neighbour() -> Self {
+        Self {
+            diagonal: 0.0,
+            axial: 0.0,
+            axial_dimension: None,
+            long_range: None,
+        }
+    }
+
+    pub fn j1_j2(diagonal: f64) -> Self {
+        Self {
+            diagonal,
+            ..Self::nearest_neighbour()
+        }
+    }
+
+    // ANNNI model with frustration parameter kappa = -J2/J1 along the given axis
+    pub fn annni(kappa: f64, axial_dimension: usize) -> Self {
+        Self {
+            axial: -kappa,
+            axial_dimension: Some(axial_dimension),
+            ..Self::nearest_neighbour()
+        }
+    }
+
+    pub fn long_range(sigma: f64) -> Self {
+        Self {
+            long_range: Some(LongRange::new(sigma)),
+            ..Self::nearest_neighbour()
+        }
+    }
+
+    pub fn has_diagonal(&self) -> bool {
+        self.diagonal != 0.0
+    }
+
+    pub fn has_axial(&self) -> bool {
+        self.axial != 0.0
+    }
+
+    pub fn axial_dimensions(&self) -> Vec<usize> {
+        match self.axial_dimension {
+            Some(dimension) => vec![dimension],
+            None => (0..DIMENSIONS).collect(),
+        }
+    }
+
+    // Period of the colouring along every axis: sites closer than the period along any
+    // axis never share a colour. Only the axes of the axial shell need 4.
+    pub fn colour_periods(&self) -> [usize; DIMENSIONS] {
+        let mut periods = [2; DIMENSIONS];
+        if self.has_axial() {
+            for d in self.axial_dimensions() {
+                periods[d] = 4;
+            }
+        }
+        periods
+    }
+
+    pub fn colours(&self) -> usize {
+        if self.has_diagonal() || self.has_axial() {
+            self.colour_periods().iter().product()
+        } else {
+            2
+        }
+    }
+
+    // Offsets of the diagonal shell, each pair counted once per direction
+    pub fn diagonal_offsets(&self) -> Vec<[isize; DIMENSIONS]> {
+        let mut offsets = Vec::new();
+        if !self.has_diagonal() {
+            return offsets;
+        }
+        for a in 0..DIMENSIONS {
+            for b in (a + 1)..DIMENSIONS {
+                for (sign_a, sign_b) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                    let mut offset = [0; DIMENSIONS];
+                    offset[a] = sign_a;
+                    offset[b] = sign_b;
+                    offsets.push(offset);
+                }
+            }
+        }
+        offsets
+    }
+
+    pub fn axial_offsets(&self) -> Vec<[isize; DIMENSIONS]> {
+        let mut offsets = Vec::new();
+        if !self.has_axial() {
+            return offsets;
+        }
+        for d in self.axial_dimensions() {
+            for sign in [2, -2] {
+                let mut offset = [0; DIMENSIONS];
+                offset[d] = sign;
+                offsets.push(offset);
+            }
+        }
+        offsets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interactions_offsets() {
+        let interactions = Interactions::nearest_neighbour();
+        assert!(interactions.diagonal_offsets().is_empty());
+        assert!(interactions.axial_offsets().is_empty());
+        assert_eq!(interactions.colours(), 2);
+
+        let interactions = Interactions::j1_j2(-0.5);
+        assert_eq!(
+            interactions.diagonal_offsets().len(),
+            2 * DIMENSIONS * (DIMENSIONS - 1)
+        );
+        assert_eq!(interactions.colours(), usize::pow(2, DIMENSIONS as u32));
+
+        let interactions = Interactions::annni(0.5, 0);
+        assert_eq!(interactions.axial, -0.5);
+        assert_eq!(interactions.axial_offsets(), vec![[2, 0, 0], [-2, 0, 0]]);
+        assert_eq!(interactions.colour_periods(), [4, 2, 2]);
+        assert_eq!(
+            interactions.colours(),
+            4 * usize::pow(2, DIMENSIONS as u32 - 1)
+        );
+
+        let interactions = Interactions {
+            axial: 0.5,
+            ..Interactions::nearest_neighbour()
+        };
+        assert_eq!(interactions.colours(), usize::pow(4, DIMENSIONS as u32));
+    }
+}
@@ -1,21 +1,50 @@
-use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
-use crate::geometry::site::Site;
-use crate::geometry::utils::{next_position, previous_position};
+use crate::field::ising::IsingField;
+use crate::geometry::lattice_geometry::boundary_conditions::{BoundaryConditions, Partner};
+use crate::geometry::lattice_geometry::long_range::LongRangeCouplings;
+use crate::geometry::site::{NeighbourShell, Site, metropolis};
+use crate::geometry::utils::{colour, position_to_lattice};
+use crate::montecarlo::boltzmann::BoltzmannTable;
+use crate::montecarlo::random::random_stream;
 use crate::settings::{DIMENSIONS, LATTICE_SIZE, Settings};
+use rand::Rng;
 use rayon::prelude::*;
+use std::io;
 use std::sync::Arc;
 use std::sync::RwLock;
 
 pub struct Lattice {
     sites: Vec<Arc<RwLock<Site>>>,
     pub settings: Arc<Settings>,
+    boltzmann: BoltzmannTable,
+    long_range: Option<LongRangeCouplings>,
+    // Wolff clusters grown during the last long-range sweep
+    clusters: Vec<Vec<usize>>,
+    // Running totals updated by every accepted move
+    energy: f64,
+    magnetization: f64,
+    // Seed and number of sweeps done, which determine the random numbers of the next
+    // sweep
+    seed: u64,
+    sweeps: u64,
 }
 
 impl Lattice {
-    pub fn new(settings: Settings) -> Self {
+    pub fn new(settings: Settings) -> io::Result<Self> {
+        Self::with_seed(settings, rand::random())
+    }
+
+    // Lattice whose initial state and sweeps are all determined by the seed. Reading
+    // the initial configuration from a file can fail.
+    pub fn with_seed(settings: Settings, seed: u64) -> io::Result<Self> {
+        let fields = settings.site_initialisation.fields(seed)?;
+        Ok(Self::with_fields(settings, seed, &fields))
+    }
+
+    // Lattice in the given state, as saved in a checkpoint
+    pub fn with_fields(settings: Settings, seed: u64, fields: &[IsingField]) -> Self {
         // Initialise the lattice sites using Vec instead of array
         let sites: Vec<Site> = (0..usize::pow(LATTICE_SIZE, DIMENSIONS as u32))
-            .map(|i| Site::new(i, settings.site_initialisation))
+            .map(|i| Site::new(i, fields[i]))
             .collect();
 
         // Create the Arc references to the sites
@@ -28,21 +57,61 @@ impl Lattice {
         let mut lattice = Self {
             sites: site_refs.clone(),
             settings: Arc::new(settings.clone()),
+            boltzmann: BoltzmannTable::new(&settings),
+            long_range: settings.interactions.long_range.map(|long_range| {
+                assert_eq!(
+                    settings.boundary_conditions,
+                    BoundaryConditions::Periodic,
+                    "Long-range interactions require periodic boundary conditions"
+                );
+                LongRangeCouplings::new(&long_range)
+            }),
+            clusters: Vec::new(),
+            energy: 0.0,
+            magnetization: 0.0,
+            seed,
+            sweeps: 0,
         };
 
-        // Create the lattice according to the boundary conditions
-        match settings.boundary_conditions {
-            BoundaryConditions::Periodic => {
-                initalise_periodic_boundary_conditions(&mut lattice, &site_refs)
-            }
-            BoundaryConditions::Open => {
-                initalise_open_boundary_conditions(&mut lattice, &site_refs)
-            }
+        // Sites of the same colour only stay apart across the boundary when the colours
+        // repeat around the lattice
+        for (d, period) in settings.interactions.colour_periods().iter().enumerate() {
+            assert!(
+                !settings
+                    .boundary_conditions
+                    .wraps(d, settings.boundary_axis)
+                    || LATTICE_SIZE.is_multiple_of(*period),
+                "The lattice size must be a multiple of {} along axis {}",
+                period,
+                d
+            );
         }
 
+        // Create the lattice according to the boundary conditions
+        initialise_boundary_conditions(&mut lattice, &site_refs);
+
+        // Connect the further-neighbour shells and colour the lattice accordingly
+        initialise_neighbour_shells(&mut lattice, &site_refs);
+
+        lattice.refresh_totals();
         lattice
     }
 
+    pub fn set_beta(&mut self, beta: f64) {
+        let mut settings = (*self.settings).clone();
+        settings.beta = beta;
+        self.boltzmann = BoltzmannTable::new(&settings);
+        self.settings = Arc::new(settings);
+    }
+
+    pub fn set_magnetic_field(&mut self, magnetic_field: f64) {
+        let mut settings = (*self.settings).clone();
+        self.energy -= (magnetic_field - settings.magnetic_field) * self.magnetization;
+        settings.magnetic_field = magnetic_field;
+        self.boltzmann = BoltzmannTable::new(&settings);
+        self.settings = Arc::new(settings);
+    }
+
     pub fn get(&self, position: usize) -> Arc<RwLock<Site>> {
         self.sites[position].clone()
     }
@@ -55,92 +124,309 @@ impl Lattice {
         self.sites[position].read().unwrap().clone()
     }
 
-    pub fn get_energy(&self) -> f64 {
+    pub fn get_fields(&self) -> Vec<IsingField> {
         self.sites
             .par_iter()
-            .map(|site| site.read().unwrap().local_energy())
-            .sum::<f64>()
-            / 2.0 // Divide by 2 because each interaction is counted twice
+            .map(|site| site.read().unwrap().field)
+            .collect()
     }
 
-    pub fn montecarlo_sweep(&mut self) {
-        // Monte Carlo sweep for the chessboard sites
-        self.sites
-            .par_iter_mut()
-            .filter(|site| site.read().unwrap().chessboard)
-            .for_each(|site| {
-                let mut rng = rand::rng();
-                site.write()
-                    .unwrap()
-                    .montecarlo_single_site(&self.settings, &mut rng);
-            });
+    // Set every spin, then recompute the running totals
+    pub fn set_fields(&mut self, fields: &[IsingField]) {
+        assert_eq!(
+            fields.len(),
+            self.sites.len(),
+            "One field per site is needed"
+        );
+        for (site, field) in self.sites.iter().zip(fields) {
+            site.write().unwrap().field = *field;
+        }
+        self.refresh_totals();
+    }
 
-        // Monte Carlo sweep for the non-chessboard sites
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn sweeps(&self) -> u64 {
+        self.sweeps
+    }
+
+    pub fn set_sweeps(&mut self, sweeps: u64) {
+        self.sweeps = sweeps;
+    }
+
+    // Running totals saved earlier, which recomputation could round differently
+    pub(crate) fn restore_totals(&mut self, energy: f64, magnetization: f64) {
+        self.energy = energy;
+        self.magnetization = magnetization;
+        self.debug_check_totals();
+    }
+
+    pub fn get_energy(&self) -> f64 {
+        self.energy
+    }
+
+    pub fn get_magnetization(&self) -> f64 {
+        self.magnetization
+    }
+
+    // Full recomputation of the energy, O(N) against the O(1) running total
+    pub fn compute_energy(&self) -> f64 {
+        // Energy of the spins in the magnetic field
+        let field_energy = if self.settings.magnetic_field == 0.0 {
+            0.0
+        } else {
+            -self.settings.magnetic_field * self.compute_magnetization()
+        };
+
+        // Long-range couplings are not stored on the sites
+        if let Some(long_range) = &self.long_range {
+            return long_range.energy(&self.get_fields()) + field_energy;
+        }
+
+        // Summed in order, so that the rounding does not depend on the threads, and
+        // divided by 2 because each interaction is counted twice, which the bonds to
+        // fixed boundaries are once more
+        let local_energies: Vec<f64> = self
+            .sites
+            .par_iter()
+            .map(|site| {
+                let site = site.read().unwrap();
+                site.local_energy() + site.boundary_energy()
+            })
+            .collect();
+        local_energies.iter().sum::<f64>() / 2.0 + field_energy
+    }
+
+    pub fn compute_magnetization(&self) -> f64 {
         self.sites
-            .par_iter_mut()
-            .filter(|site| !site.read().unwrap().chessboard)
-            .for_each(|site| {
-                let mut rng = rand::rng();
-                site.write()
-                    .unwrap()
-                    .montecarlo_single_site(&self.settings, &mut rng);
-            });
+            .par_iter()
+            .map(|site| site.read().unwrap().field.value())
+            .sum()
+    }
+
+    // Recompute the running totals, needed after changing sites directly
+    pub fn refresh_totals(&mut self) {
+        self.energy = self.compute_energy();
+        self.magnetization = self.compute_magnetization();
+    }
+
+    // Check the running totals against the full recomputation in debug builds
+    fn debug_check_totals(&self) {
+        if cfg!(debug_assertions) {
+            let (energy, magnetization) = (self.compute_energy(), self.compute_magnetization());
+            debug_assert!(
+                (self.energy - energy).abs() <= 1e-6 * energy.abs().max(1.0),
+                "Running energy {} differs from {}",
+                self.energy,
+                energy
+            );
+            debug_assert_eq!(self.magnetization, magnetization);
+        }
+    }
+
+    // Clusters of the last sweep, for improved estimators at zero field
+    pub fn last_clusters(&self) -> &[Vec<usize>] {
+        &self.clusters
+    }
+
+    pub fn len(&self) -> usize {
+        self.sites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+
+    // Energy change of flipping a single site: every bond of the site changes sign,
+    // and so does its energy in the magnetic field
+    pub fn flip_energy_change(&self, position: usize) -> f64 {
+        let site = self.sites[position].read().unwrap();
+        let field_energy = 2.0 * self.settings.magnetic_field * site.field.value();
+        match &self.long_range {
+            Some(long_range) => {
+                -2.0 * long_range.local_energy(&self.get_fields(), position) + field_energy
+            }
+            None => -2.0 * site.local_energy() + field_energy,
+        }
+    }
+
+    pub fn flip(&mut self, position: usize) {
+        self.energy += self.flip_energy_change(position);
+        let mut site = self.sites[position].write().unwrap();
+        site.flip();
+        self.magnetization += 2.0 * site.field.value();
+    }
+
+    // Sweep over the lattice, returns the changes of the energy and magnetization
+    pub fn montecarlo_sweep(&mut self) -> (f64, f64) {
+        // Long-range interactions are updated with clusters only
+        let (energy_change, magnetization_change) = if self.long_range.is_some() {
+            self.long_range_sweep()
+        } else {
+            self.short_range_sweep()
+        };
+        self.energy += energy_change;
+        self.magnetization += magnetization_change;
+        self.sweeps += 1;
+        self.debug_check_totals();
+        (energy_change, magnetization_change)
+    }
+
+    fn short_range_sweep(&mut self) -> (f64, f64) {
+        // Monte Carlo sweep one colour at a time: sites of the same colour do not
+        // interact, so they can be updated in parallel
+        let mut changes = (0.0, 0.0);
+        for colour in 0..self.settings.interactions.colours() {
+            // Every site draws from its own stream, and the changes are summed in order,
+            // so the sweep does not depend on the threads
+            let site_changes: Vec<(f64, f64)> = self
+                .sites
+                .par_iter_mut()
+                .filter(|site| site.read().unwrap().colour == colour)
+                .map(|site| {
+                    let mut site = site.write().unwrap();
+                    let mut rng = random_stream(self.seed, self.sweeps, site.position as u64);
+                    match site.montecarlo_single_site(&self.boltzmann, &mut rng) {
+                        Some(energy_change) => (energy_change, 2.0 * site.field.value()),
+                        None => (0.0, 0.0),
+                    }
+                })
+                .collect();
+            for (energy_change, magnetization_change) in site_changes {
+                changes.0 += energy_change;
+                changes.1 += magnetization_change;
+            }
+        }
+        changes
+    }
+
+    // Luijten-Blöte cluster flips until clusters as large as the lattice have been
+    // attempted. Rejected clusters count too, or the sweep never ends in the ordered
+    // phase of a field.
+    fn long_range_sweep(&mut self) -> (f64, f64) {
+        let long_range = self.long_range.as_ref().unwrap();
+        // The streams of the sites are numbered below the number of sites
+        let mut rng = random_stream(self.seed, self.sweeps, self.sites.len() as u64);
+        let mut fields = self.get_fields();
+        let mut attempted = 0;
+        let mut energy_change = 0.0;
+        let mut magnetization_change = 0.0;
+        let mut clusters = Vec::new();
+
+        while attempted < fields.len() {
+            let seed = rng.random_range(0..fields.len());
+            let cluster = long_range.wolff_cluster(&fields, seed, self.settings.beta, &mut rng);
+            attempted += cluster.len();
+            clusters.push(cluster.clone());
+
+            // The cluster is built from the couplings only: the magnetic field enters
+            // through a Metropolis test on the whole cluster
+            let field_energy =
+                2.0 * self.settings.magnetic_field * fields[seed].value() * cluster.len() as f64;
+            if !metropolis(-self.settings.beta * field_energy, &mut rng) {
+                continue;
+            }
+
+            energy_change += long_range.cluster_energy_change(&fields, &cluster) + field_energy;
+            magnetization_change -= 2.0 * fields[seed].value() * cluster.len() as f64;
+            for position in cluster.iter() {
+                self.sites[*position].write().unwrap().flip();
+                fields[*position] = self.sites[*position].read().unwrap().field;
+            }
+        }
+
+        self.clusters = clusters;
+        (energy_change, magnetization_change)
     }
 }
 
-fn initalise_periodic_boundary_conditions(lattice: &mut Lattice, site_refs: &[Arc<RwLock<Site>>]) {
+fn initialise_boundary_conditions(lattice: &mut Lattice, site_refs: &[Arc<RwLock<Site>>]) {
+    let (boundary, axis) = (
+        lattice.settings.boundary_conditions,
+        lattice.settings.boundary_axis,
+    );
     for i in 0..lattice.sites.len() {
+        let site = lattice.get_mut(i);
+        let mut site = site.write().unwrap();
         for d in 0..DIMENSIONS {
-            // Next site
-            let next_pos = next_position(i, d);
-            lattice
-                .get_mut(i)
-                .write()
-                .unwrap()
-                .update_next(d, Some(site_refs[next_pos].clone()));
-
-            // Previous site
-            let prev_pos = previous_position(i, d);
-            lattice
-                .get_mut(i)
-                .write()
-                .unwrap()
-                .update_previous(d, Some(site_refs[prev_pos].clone()));
+            for step in [1, -1] {
+                let mut offset = [0; DIMENSIONS];
+                offset[d] = step;
+
+                // Sites at open and fixed boundaries have no neighbour beyond them
+                let (neighbour, coupling) = match boundary.partner(i, offset, axis) {
+                    Partner::Site(position, sign) => (Some(site_refs[position].clone()), sign),
+                    Partner::Wall(spin) => {
+                        site.boundary_field += spin;
+                        (None, 1.0)
+                    }
+                    Partner::Outside => (None, 1.0),
+                };
+                if step == 1 {
+                    site.update_next(d, neighbour);
+                    site.next_coupling[d] = coupling;
+                } else {
+                    site.update_previous(d, neighbour);
+                    site.previous_coupling[d] = coupling;
+                }
+            }
         }
     }
 }
 
-fn initalise_open_boundary_conditions(lattice: &mut Lattice, site_refs: &[Arc<RwLock<Site>>]) {
+fn initialise_neighbour_shells(lattice: &mut Lattice, site_refs: &[Arc<RwLock<Site>>]) {
+    let interactions = lattice.settings.interactions;
+    let (boundary, axis) = (
+        lattice.settings.boundary_conditions,
+        lattice.settings.boundary_axis,
+    );
+    let shells = [
+        (interactions.diagonal, interactions.diagonal_offsets()),
+        (interactions.axial, interactions.axial_offsets()),
+    ];
+
     for i in 0..lattice.sites.len() {
-        for d in 0..DIMENSIONS {
-            // Next site
-            let next_pos = next_position(i, d);
-
-            // If position at the boundary of the lattice, set the next site to None
-            if next_pos < i {
-                lattice.get_mut(i).write().unwrap().update_next(d, None);
-            } else {
-                lattice
-                    .get_mut(i)
-                    .write()
-                    .unwrap()
-                    .update_next(d, Some(site_refs[next_pos].clone()));
+        for (coupling, offsets) in shells.iter() {
+            if offsets.is_empty() {
+                continue;
             }
 
-            // Previous site
-            let prev_pos = previous_position(i, d);
-
-            // If position at the boundary of the lattice, set the previous site to None
-            if prev_pos > i {
-                lattice.get_mut(i).write().unwrap().update_previous(d, None);
-            } else {
-                lattice
-                    .get_mut(i)
-                    .write()
-                    .unwrap()
-                    .update_previous(d, Some(site_refs[prev_pos].clone()));
+            // Neighbours falling outside of open boundaries are left out, the bonds
+            // across antiperiodic ones form a shell of their own
+            let site = lattice.get_mut(i);
+            let mut site = site.write().unwrap();
+            let (mut sites, mut reversed) = (Vec::new(), Vec::new());
+            for offset in offsets.iter() {
+                match boundary.partner(i, *offset, axis) {
+                    Partner::Site(position, sign) if sign > 0.0 => {
+                        sites.push(site_refs[position].clone())
+                    }
+                    Partner::Site(position, _) => reversed.push(site_refs[position].clone()),
+                    Partner::Wall(spin) => site.boundary_field += coupling * spin,
+                    Partner::Outside => {}
+                }
+            }
+            site.add_shell(NeighbourShell {
+                coupling: *coupling,
+                sites,
+            });
+            if !reversed.is_empty() {
+                site.add_shell(NeighbourShell {
+                    coupling: -coupling,
+                    sites: reversed,
+                });
             }
         }
+
+        // Colour the site so that no two interacting sites share a colour
+        let lattice_position = position_to_lattice(i);
+        lattice
+            .get_mut(i)
+            .write()
+            .unwrap()
+            .update_colour(colour(lattice_position, &interactions));
     }
 }
 
@@ -148,8 +434,9 @@ fn initalise_open_boundary_conditions(lattice: &mut Lattice, site_refs: &[Arc<Rw
 mod tests {
     use super::*;
     use crate::field::initialisation::Initialisation;
-    use crate::field::ising::IsingField;
     use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
+    use crate::geometry::lattice_geometry::interactions::Interactions;
+    use crate::geometry::utils::{next_position, previous_position};
     use crate::settings::SettingsBuilder;
 
     #[test]
@@ -159,7 +446,7 @@ mod tests {
             .add_boundary_conditions(BoundaryConditions::Periodic)
             .add_site_initialisation(Initialisation::Uniform)
             .build();
-        let lattice = Lattice::new(settings);
+        let lattice = Lattice::new(settings).unwrap();
         assert_eq!(
             lattice.sites.len(),
             usize::pow(LATTICE_SIZE, DIMENSIONS as u32)
@@ -169,6 +456,12 @@ mod tests {
             assert_eq!(site.read().unwrap().position, i);
             assert_eq!(site.read().unwrap().field, IsingField::Up);
         }
+
+        // A configuration that cannot be read is an error, not a panic
+        let settings = SettingsBuilder::new()
+            .add_site_initialisation(Initialisation::FromFile("missing/configuration".into()))
+            .build();
+        assert!(Lattice::new(settings).is_err());
     }
 
     #[test]
@@ -178,7 +471,7 @@ mod tests {
             .add_boundary_conditions(BoundaryConditions::Periodic)
             .add_site_initialisation(Initialisation::Uniform)
             .build();
-        let lattice = Lattice::new(settings);
+        let lattice = Lattice::new(settings).unwrap();
         for i in 0..lattice.sites.len() {
             let site = lattice.get(i);
             assert_eq!(site.read().unwrap().position, i);
@@ -192,9 +485,10 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Periodic,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let mut lattice = Lattice::new(settings);
+        let mut lattice = Lattice::new(settings).unwrap();
         for i in 0..lattice.sites.len() {
             let site = lattice.get_mut(i);
             site.write().unwrap().flip();
@@ -207,9 +501,10 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Periodic,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let lattice = Lattice::new(settings);
+        let lattice = Lattice::new(settings).unwrap();
         assert_eq!(
             lattice.get(0).read().unwrap().next[0]
                 .as_ref()
@@ -242,9 +537,10 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Open,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let lattice = Lattice::new(settings);
+        let lattice = Lattice::new(settings).unwrap();
         assert_eq!(
             lattice.get(0).read().unwrap().next[0]
                 .as_ref()
@@ -254,8 +550,52 @@ mod tests {
                 .position,
             1
         );
-        assert_eq!(lattice.get(63).read().unwrap().next[0].is_none(), true);
-        assert_eq!(lattice.get(0).read().unwrap().previous[0].is_none(), true);
+        assert!(lattice.get(63).read().unwrap().next[0].is_none());
+        assert!(lattice.get(0).read().unwrap().previous[0].is_none());
+    }
+
+    #[test]
+    fn test_antiperiodic_and_fixed_boundary_conditions() {
+        let layer = usize::pow(LATTICE_SIZE, DIMENSIONS as u32 - 1) as f64;
+        let sites = layer * LATTICE_SIZE as f64;
+        let bonds = DIMENSIONS as f64 * sites;
+
+        // The bonds across the boundary of the second axis are reversed
+        let settings = SettingsBuilder::new()
+            .add_beta(0.3)
+            .add_boundary_conditions(BoundaryConditions::Antiperiodic)
+            .add_boundary_axis(1)
+            .add_interactions(Interactions::j1_j2(0.5))
+            .build();
+        let mut lattice = Lattice::new(settings).unwrap();
+        let diagonal = 2.0 * DIMENSIONS as f64 * (DIMENSIONS as f64 - 1.0) / 2.0;
+        let expected = -bonds + 2.0 * layer - 0.5 * diagonal * sites
+            + 2.0 * 0.5 * 2.0 * (DIMENSIONS as f64 - 1.0) * layer;
+        assert!((lattice.get_energy() - expected).abs() < 1e-9);
+        assert_eq!(
+            lattice.get(0).read().unwrap().previous_coupling,
+            [1.0, -1.0, 1.0]
+        );
+
+        // Up spins below the first layer and down spins above the last one
+        let settings = SettingsBuilder::new()
+            .add_beta(0.3)
+            .add_boundary_conditions(BoundaryConditions::Fixed)
+            .build();
+        let mut fixed = Lattice::new(settings).unwrap();
+        assert_eq!(fixed.get_energy(), -(bonds - layer));
+        assert!(fixed.get(0).read().unwrap().previous[0].is_none());
+        assert_eq!(fixed.get(0).read().unwrap().boundary_field, 1.0);
+        assert_eq!(fixed.get(63).read().unwrap().boundary_field, -1.0);
+
+        // The running totals follow the recomputation in both
+        for lattice in [&mut lattice, &mut fixed] {
+            for position in [0, 3, 21, 63] {
+                lattice.flip(position);
+                assert!((lattice.get_energy() - lattice.compute_energy()).abs() < 1e-9);
+            }
+            lattice.montecarlo_sweep();
+        }
     }
 
     #[test]
@@ -264,9 +604,10 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Periodic,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let lattice = Lattice::new(settings);
+        let lattice = Lattice::new(settings).unwrap();
         let mut energy = 0.0;
         for i in 0..lattice.sites.len() {
             let site = lattice.get(i);
@@ -278,9 +619,10 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Open,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let lattice = Lattice::new(settings);
+        let lattice = Lattice::new(settings).unwrap();
         let mut energy = 0.0;
         for i in 0..lattice.sites.len() {
             let site = lattice.get(i);
@@ -295,9 +637,10 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Periodic,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let lattice = Lattice::new(settings);
+        let lattice = Lattice::new(settings).unwrap();
 
         for i in 0..lattice.sites.len() {
             for d in 0..DIMENSIONS {
@@ -329,42 +672,35 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Periodic,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let lattice = Lattice::new(settings);
+        let lattice = Lattice::new(settings).unwrap();
         for i in 0..lattice.sites.len() {
             // Get current bool for the chessboard
-            let current_chessboard = lattice.get(i).read().unwrap().chessboard;
+            let current_chessboard = lattice.get(i).read().unwrap().colour;
 
             // Verify that all neighbors have the opposite chessboard value
             for d in 0..DIMENSIONS {
                 assert_eq!(
-                    lattice.get(next_position(i, d)).read().unwrap().chessboard,
-                    !current_chessboard,
+                    lattice.get(next_position(i, d)).read().unwrap().colour,
+                    1 - current_chessboard,
                     "Site {} is {} and its next neighbor {} along dimension {} is {}",
                     i,
                     current_chessboard,
                     next_position(i, d),
                     d,
-                    lattice.get(next_position(i, d)).read().unwrap().chessboard
+                    lattice.get(next_position(i, d)).read().unwrap().colour
                 );
                 assert_eq!(
-                    lattice
-                        .get(previous_position(i, d))
-                        .read()
-                        .unwrap()
-                        .chessboard,
-                    !current_chessboard,
+                    lattice.get(previous_position(i, d)).read().unwrap().colour,
+                    1 - current_chessboard,
                     "Site {} is {} and its previous neighbor {} along dimension {} is {}",
                     i,
                     current_chessboard,
                     previous_position(i, d),
                     d,
-                    lattice
-                        .get(previous_position(i, d))
-                        .read()
-                        .unwrap()
-                        .chessboard
+                    lattice.get(previous_position(i, d)).read().unwrap().colour
                 );
             }
         }
@@ -376,9 +712,212 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Periodic,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let mut lattice = Lattice::new(settings);
+        let mut lattice = Lattice::new(settings).unwrap();
+        lattice.montecarlo_sweep();
+    }
+
+    #[test]
+    fn test_neighbour_shells() {
+        let settings = SettingsBuilder::new()
+            .add_interactions(Interactions {
+                diagonal: -0.5,
+                axial: 0.25,
+                ..Interactions::nearest_neighbour()
+            })
+            .build();
+        let lattice = Lattice::new(settings).unwrap();
+
+        for i in 0..lattice.sites.len() {
+            let site = lattice.get(i);
+            let site = site.read().unwrap();
+            assert_eq!(site.shells.len(), 2);
+            assert_eq!(
+                site.shells[0].sites.len(),
+                2 * DIMENSIONS * (DIMENSIONS - 1)
+            );
+            assert_eq!(site.shells[1].sites.len(), 2 * DIMENSIONS);
+
+            // No site shares its colour with any site it interacts with
+            let neighbours = site
+                .next
+                .iter()
+                .chain(site.previous.iter())
+                .flatten()
+                .chain(site.shells.iter().flat_map(|shell| shell.sites.iter()));
+            for neighbour in neighbours {
+                assert_ne!(neighbour.read().unwrap().colour, site.colour);
+            }
+        }
+
+        // Uniform configuration: each bond contributes minus its coupling once
+        let sites = lattice.sites.len() as f64;
+        let dimensions = DIMENSIONS as f64;
+        let expected =
+            -sites * (dimensions - 0.5 * dimensions * (dimensions - 1.0) + 0.25 * dimensions);
+        assert_eq!(lattice.get_energy(), expected);
+    }
+
+    #[test]
+    fn test_annni_open_boundary_conditions() {
+        let settings = SettingsBuilder::new()
+            .add_boundary_conditions(BoundaryConditions::Open)
+            .add_interactions(Interactions::annni(0.5, 0))
+            .build();
+        let lattice = Lattice::new(settings).unwrap();
+
+        // Axial neighbours only along dimension 0 and only inside the lattice
+        assert_eq!(lattice.get(0).read().unwrap().shells.len(), 1);
+        assert_eq!(lattice.get(0).read().unwrap().shells[0].sites.len(), 1);
+        assert_eq!(lattice.get(1).read().unwrap().shells[0].sites.len(), 1);
+        assert_eq!(
+            lattice.get(0).read().unwrap().shells[0].sites[0]
+                .read()
+                .unwrap()
+                .position,
+            2
+        );
+    }
+
+    #[test]
+    fn test_lattice_montecarlo_sweep_further_neighbours() {
+        let settings = SettingsBuilder::new()
+            .add_beta(0.5)
+            .add_interactions(Interactions::annni(0.6, 0))
+            .build();
+        let mut lattice = Lattice::new(settings).unwrap();
         lattice.montecarlo_sweep();
+        assert!(lattice.get_energy().is_finite());
+    }
+
+    #[test]
+    fn test_lattice_magnetization() {
+        let mut lattice = Lattice::new(SettingsBuilder::new().build()).unwrap();
+        assert_eq!(lattice.get_magnetization(), lattice.len() as f64);
+        lattice.flip(0);
+        assert_eq!(lattice.get_magnetization(), lattice.len() as f64 - 2.0);
+    }
+
+    #[test]
+    fn test_lattice_flip_energy_change() {
+        let settings = SettingsBuilder::new()
+            .add_interactions(Interactions::j1_j2(-0.5))
+            .build();
+        let mut lattice = Lattice::new(settings).unwrap();
+        let energy = lattice.get_energy();
+        let change = lattice.flip_energy_change(5);
+        lattice.flip(5);
+        assert!((lattice.get_energy() - energy - change).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lattice_boltzmann_table() {
+        // The tabulated ratios match the energy changes of flipping and unflipping
+        let settings = SettingsBuilder::new()
+            .add_beta(0.3)
+            .add_site_initialisation(Initialisation::Random)
+            .add_boundary_conditions(BoundaryConditions::Open)
+            .add_interactions(Interactions::j1_j2(-0.5))
+            .build();
+        let mut lattice = Lattice::new(settings).unwrap();
+        lattice.set_magnetic_field(0.2);
+        for position in 0..lattice.len() {
+            let site = lattice.get_site_clone(position);
+            let ratio = lattice
+                .boltzmann
+                .ratio(site.field.value(), site.local_field());
+            let expected = (-0.3 * lattice.flip_energy_change(position)).exp();
+            assert!((ratio - expected).abs() < 1e-12 * expected);
+        }
+    }
+
+    #[test]
+    fn test_lattice_running_totals() {
+        let settings = SettingsBuilder::new()
+            .add_beta(0.3)
+            .add_site_initialisation(Initialisation::Random)
+            .add_interactions(Interactions::j1_j2(-0.5))
+            .build();
+        let mut lattice = Lattice::new(settings).unwrap();
+        let (energy, magnetization) = (lattice.get_energy(), lattice.get_magnetization());
+
+        // The sweeps return the changes they accumulate
+        let (mut energy_change, mut magnetization_change) = (0.0, 0.0);
+        for _ in 0..5 {
+            let (de, dm) = lattice.montecarlo_sweep();
+            energy_change += de;
+            magnetization_change += dm;
+        }
+        assert!((lattice.get_energy() - energy - energy_change).abs() < 1e-9);
+        assert_eq!(
+            lattice.get_magnetization(),
+            magnetization + magnetization_change
+        );
+
+        // Single flips and field changes keep the totals up to date
+        lattice.flip(7);
+        lattice.set_magnetic_field(0.4);
+        assert!((lattice.get_energy() - lattice.compute_energy()).abs() < 1e-9);
+        assert_eq!(lattice.get_magnetization(), lattice.compute_magnetization());
+    }
+
+    #[test]
+    fn test_lattice_magnetic_field() {
+        let settings = SettingsBuilder::new().add_magnetic_field(0.5).build();
+        let mut lattice = Lattice::new(settings).unwrap();
+        let sites = lattice.len() as f64;
+        assert_eq!(
+            lattice.get_energy(),
+            -(DIMENSIONS as f64) * sites - 0.5 * sites
+        );
+
+        let energy = lattice.get_energy();
+        let change = lattice.flip_energy_change(3);
+        lattice.flip(3);
+        assert!((lattice.get_energy() - energy - change).abs() < 1e-9);
+
+        lattice.set_magnetic_field(-0.5);
+        assert_eq!(lattice.settings.magnetic_field, -0.5);
+    }
+
+    #[test]
+    fn test_lattice_set_beta() {
+        let mut lattice = Lattice::new(SettingsBuilder::new().add_beta(1.0).build()).unwrap();
+        lattice.set_beta(0.25);
+        assert_eq!(lattice.settings.beta, 0.25);
+    }
+
+    #[test]
+    fn test_lattice_long_range() {
+        let settings = SettingsBuilder::new()
+            .add_beta(0.1)
+            .add_interactions(Interactions::long_range(0.5))
+            .build();
+        let mut lattice = Lattice::new(settings).unwrap();
+        let couplings = lattice.long_range.as_ref().unwrap().couplings.clone();
+        let expected = -couplings.iter().sum::<f64>() * lattice.sites.len() as f64 / 2.0;
+        assert!((lattice.get_energy() - expected).abs() < 1e-9);
+
+        lattice.montecarlo_sweep();
+        assert!(lattice.get_energy() >= expected - 1e-9);
+    }
+
+    #[test]
+    fn test_lattice_long_range_field() {
+        // Ordered phase along the field: every cluster flip is rejected, and the sweep
+        // still ends
+        let settings = SettingsBuilder::new()
+            .add_beta(2.0)
+            .add_magnetic_field(1.0)
+            .add_site_initialisation(Initialisation::Uniform)
+            .add_interactions(Interactions::long_range(0.5))
+            .build();
+        let mut lattice = Lattice::with_seed(settings, 1).unwrap();
+        for _ in 0..2 {
+            lattice.montecarlo_sweep();
+        }
+        assert_eq!(lattice.get_magnetization(), lattice.len() as f64);
     }
 }
@@ -0,0 +1,254 @@
+use crate::field::ising::IsingField;
+use crate::field::schema::Field;
+use crate::geometry::utils::{position_to_lattice, shift_position};
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+use rand::Rng;
+use rayon::prelude::*;
+
+// Number of periodic images summed along each axis by LongRangeSum::Images
+const IMAGE_SHELLS: isize = 4;
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum LongRangeSum {
+    // Only the closest periodic image of every pair interacts
+    MinimumImage,
+    // Explicit sum over the periodic images around the closest one
+    Images,
+}
+
+// Power-law coupling J(r) = coupling / r^(d + sigma) between every pair of sites
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LongRange {
+    pub sigma: f64,
+    pub coupling: f64,
+    pub sum: LongRangeSum,
+}
+
+impl LongRange {
+    pub fn new(sigma: f64) -> Self {
+        Self {
+            sigma,
+            coupling: 1.0,
+            sum: LongRangeSum::MinimumImage,
+        }
+    }
+
+    pub fn coupling_at(&self, offset: [isize; DIMENSIONS]) -> f64 {
+        let exponent = (DIMENSIONS as f64 + self.sigma) / 2.0;
+        let distance_squared = |offset: [isize; DIMENSIONS]| -> f64 {
+            offset.iter().map(|x| (x * x) as f64).sum::<f64>()
+        };
+
+        match self.sum {
+            LongRangeSum::MinimumImage => {
+                let closest = offset.map(|x| {
+                    let x = x.rem_euclid(LATTICE_SIZE as isize);
+                    x.min(LATTICE_SIZE as isize - x)
+                });
+                self.coupling / distance_squared(closest).powf(exponent)
+            }
+            LongRangeSum::Images => {
+                let mut coupling = 0.0;
+                let images = usize::pow(2 * IMAGE_SHELLS as usize + 1, DIMENSIONS as u32);
+                for image in 0..images {
+                    // Decode the image index into one shift per axis
+                    let mut index = image;
+                    let mut shifted = offset;
+                    for x in shifted.iter_mut() {
+                        let shift = (index % (2 * IMAGE_SHELLS as usize + 1)) as isize;
+                        *x += (shift - IMAGE_SHELLS) * LATTICE_SIZE as isize;
+                        index /= 2 * IMAGE_SHELLS as usize + 1;
+                    }
+                    let distance_squared = distance_squared(shifted);
+                    if distance_squared > 0.0 {
+                        coupling += self.coupling / distance_squared.powf(exponent);
+                    }
+                }
+                coupling
+            }
+        }
+    }
+}
+
+// Couplings to every displacement on the periodic lattice, with the cumulative bond
+// weights used by the Luijten-Blöte cluster algorithm
+#[derive(Debug, Clone)]
+pub struct LongRangeCouplings {
+    pub offsets: Vec<[isize; DIMENSIONS]>,
+    pub couplings: Vec<f64>,
+    // cumulative[k] = sum over j < k of 2 J_j, scaled by beta when growing clusters
+    cumulative: Vec<f64>,
+}
+
+impl LongRangeCouplings {
+    pub fn new(long_range: &LongRange) -> Self {
+        // Every nonzero displacement on the lattice
+        let offsets: Vec<[isize; DIMENSIONS]> = (1..usize::pow(LATTICE_SIZE, DIMENSIONS as u32))
+            .map(|displacement| position_to_lattice(displacement).map(|x| x as isize))
+            .collect();
+        let couplings: Vec<f64> = offsets
+            .iter()
+            .map(|offset| long_range.coupling_at(*offset))
+            .collect();
+
+        let mut cumulative = Vec::with_capacity(couplings.len() + 1);
+        cumulative.push(0.0);
+        for coupling in couplings.iter() {
+            cumulative.push(cumulative.last().unwrap() + 2.0 * coupling);
+        }
+
+        Self {
+            offsets,
+            couplings,
+            cumulative,
+        }
+    }
+
+    pub fn local_energy(&self, fields: &[IsingField], position: usize) -> f64 {
+        self.offsets
+            .iter()
+            .zip(self.couplings.iter())
+            .map(|(offset, coupling)| {
+                let neighbour = shift_position(position, *offset, true).unwrap();
+                coupling * fields[position].interaction(&fields[neighbour])
+            })
+            .sum()
+    }
+
+    pub fn energy(&self, fields: &[IsingField]) -> f64 {
+        // Summed in order, so that the rounding does not depend on the threads, and
+        // divided by 2 because each interaction is counted twice
+        let local_energies: Vec<f64> = (0..fields.len())
+            .into_par_iter()
+            .map(|position| self.local_energy(fields, position))
+            .collect();
+        local_energies.iter().sum::<f64>() / 2.0
+    }
+
+    // Energy change of flipping a cluster: only its bonds to the sites outside it change
+    // sign, at a cost of O(N) per site of the cluster
+    pub fn cluster_energy_change(&self, fields: &[IsingField], cluster: &[usize]) -> f64 {
+        let mut in_cluster = vec![false; fields.len()];
+        for position in cluster.iter() {
+            in_cluster[*position] = true;
+        }
+        let mut energy_change = 0.0;
+        for position in cluster.iter() {
+            for (offset, coupling) in self.offsets.iter().zip(self.couplings.iter()) {
+                let neighbour = shift_position(*position, *offset, true).unwrap();
+                if !in_cluster[neighbour] {
+                    energy_change -=
+                        2.0 * coupling * fields[*position].interaction(&fields[neighbour]);
+                }
+            }
+        }
+        energy_change
+    }
+
+    // Grow a Wolff cluster from the seed without visiting all N - 1 partners of each
+    // site: the next activated bond is drawn directly from the cumulative weights
+    pub fn wolff_cluster<R: Rng>(
+        &self,
+        fields: &[IsingField],
+        seed: usize,
+        beta: f64,
+        rng: &mut R,
+    ) -> Vec<usize> {
+        // No bond is ever activated at infinite temperature
+        if beta <= 0.0 {
+            return vec![seed];
+        }
+
+        let total = *self.cumulative.last().unwrap();
+        let mut in_cluster = vec![false; fields.len()];
+        let mut cluster = vec![seed];
+        let mut stack = vec![seed];
+        in_cluster[seed] = true;
+
+        while let Some(position) = stack.pop() {
+            let mut k = 0;
+            loop {
+                // Skip over the displacements whose bond is not activated
+                let random_number: f64 = rng.random_range(f64::EPSILON..=1.0);
+                let target = self.cumulative[k] - random_number.ln() / beta;
+                if target >= total {
+                    break;
+                }
+                k = self.cumulative.partition_point(|weight| *weight <= target) - 1;
+
+                // Activated bond: add the partner if it is aligned
+                let neighbour = shift_position(position, self.offsets[k], true).unwrap();
+                if !in_cluster[neighbour] && fields[neighbour] == fields[seed] {
+                    in_cluster[neighbour] = true;
+                    cluster.push(neighbour);
+                    stack.push(neighbour);
+                }
+                k += 1;
+            }
+        }
+        cluster
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_range_coupling() {
+        let long_range = LongRange::new(1.0);
+        assert_eq!(long_range.coupling_at([1, 0, 0]), 1.0);
+        assert_eq!(long_range.coupling_at([3, 0, 0]), 1.0);
+        assert_eq!(long_range.coupling_at([1, 1, 0]), 1.0 / 4.0);
+
+        let long_range = LongRange {
+            sum: LongRangeSum::Images,
+            ..LongRange::new(1.0)
+        };
+        assert!(long_range.coupling_at([1, 0, 0]) > 1.0);
+        assert!(
+            (long_range.coupling_at([1, 0, 0]) - long_range.coupling_at([-1, 0, 0])).abs() < 1e-12
+        );
+    }
+
+    #[test]
+    fn test_long_range_couplings_energy() {
+        let couplings = LongRangeCouplings::new(&LongRange::new(0.5));
+        let fields = vec![IsingField::Up; usize::pow(LATTICE_SIZE, DIMENSIONS as u32)];
+        let total: f64 = couplings.couplings.iter().sum();
+        let expected = -total * fields.len() as f64 / 2.0;
+        assert!((couplings.energy(&fields) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_wolff_cluster() {
+        let fields = vec![IsingField::Up; usize::pow(LATTICE_SIZE, DIMENSIONS as u32)];
+        let mut rng = rand::rng();
+
+        let couplings = LongRangeCouplings::new(&LongRange::new(0.5));
+
+        // Infinite temperature: no bond is ever activated
+        assert_eq!(couplings.wolff_cluster(&fields, 3, 0.0, &mut rng), vec![3]);
+
+        // Zero temperature: every aligned site joins the cluster
+        let cluster = couplings.wolff_cluster(&fields, 3, 1e6, &mut rng);
+        assert_eq!(cluster.len(), fields.len());
+    }
+
+    #[test]
+    fn test_cluster_energy_change() {
+        let couplings = LongRangeCouplings::new(&LongRange::new(0.5));
+        let mut fields = vec![IsingField::Up; usize::pow(LATTICE_SIZE, DIMENSIONS as u32)];
+        fields[9] = IsingField::Down;
+        let cluster = [1, 2, 9, 30];
+        let energy = couplings.energy(&fields);
+        let change = couplings.cluster_energy_change(&fields, &cluster);
+        for position in cluster {
+            fields[position] = match fields[position] {
+                IsingField::Up => IsingField::Down,
+                IsingField::Down => IsingField::Up,
+            };
+        }
+        assert!((couplings.energy(&fields) - energy - change).abs() < 1e-9);
+    }
+}
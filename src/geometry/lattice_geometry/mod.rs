@@ -1,2 +1,5 @@
 pub mod boundary_conditions;
+pub mod interactions;
 pub mod lattice;
+pub mod long_range;
+pub mod multispin;
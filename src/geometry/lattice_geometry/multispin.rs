@@ -0,0 +1,443 @@
+use crate::field::initialisation::Initialisation;
+use crate::field::ising::IsingField;
+use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
+use crate::geometry::utils::{chessboard, next_position, position_to_lattice, previous_position};
+use crate::settings::{DIMENSIONS, LATTICE_SIZE, Settings};
+use rand::Rng;
+use rayon::prelude::*;
+use std::io;
+use std::sync::Arc;
+
+// Number of replicas packed into one word
+pub const REPLICAS: usize = u64::BITS as usize;
+
+// Bits needed to count the anti-aligned neighbours, up to 2 D
+const COUNTER_BITS: usize = (usize::BITS - (2 * DIMENSIONS).leading_zeros()) as usize;
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum UpdateRule {
+    Metropolis,
+    HeatBath,
+}
+
+// Multispin-coded nearest-neighbour Ising model on a hypercubic lattice. Bit r of the
+// word of a site is the spin of replica r (1 for up), so one word holds the site in 64
+// independent replicas, updated together with bitwise logic. Every site update draws a
+// single random number shared by all the replicas.
+pub struct MultispinLattice {
+    spins: Vec<u64>,
+    neighbours: Vec<Vec<usize>>,
+    // Sites of the two chessboard colours
+    colours: [Vec<usize>; 2],
+    pub settings: Arc<Settings>,
+    update_rule: UpdateRule,
+    // Acceptance probability by number of neighbours, spin and anti-aligned neighbours
+    acceptance: Vec<[Vec<f64>; 2]>,
+}
+
+impl MultispinLattice {
+    pub fn new(settings: Settings, update_rule: UpdateRule) -> io::Result<Self> {
+        let interactions = &settings.interactions;
+        assert!(
+            !interactions.has_diagonal()
+                && !interactions.has_axial()
+                && interactions.long_range.is_none(),
+            "Multispin coding needs nearest-neighbour interactions"
+        );
+        assert!(
+            matches!(
+                settings.boundary_conditions,
+                BoundaryConditions::Periodic | BoundaryConditions::Open
+            ),
+            "Multispin coding needs periodic or open boundary conditions"
+        );
+
+        let sites = usize::pow(LATTICE_SIZE, DIMENSIONS as u32);
+        let spins = match &settings.site_initialisation {
+            Initialisation::Random => (0..sites).map(|_| rand::random::<u64>()).collect(),
+            initialisation => {
+                // Every replica gets its own draw of a random state
+                let draws = if initialisation.is_random() {
+                    REPLICAS
+                } else {
+                    1
+                };
+                let replicas: Vec<Vec<IsingField>> = (0..draws)
+                    .map(|_| initialisation.fields(rand::random()))
+                    .collect::<io::Result<_>>()?;
+                (0..sites)
+                    .map(|position| {
+                        (0..REPLICAS)
+                            .filter(|r| replicas[r % draws][position] == IsingField::Up)
+                            .fold(0u64, |word, r| word | 1 << r)
+                    })
+                    .collect()
+            }
+        };
+
+        let periodic = settings.boundary_conditions == BoundaryConditions::Periodic;
+        let neighbours = (0..sites)
+            .map(|position| {
+                let lattice_position = position_to_lattice(position);
+                let mut neighbours = Vec::with_capacity(2 * DIMENSIONS);
+                for (dimension, coordinate) in lattice_position.iter().enumerate() {
+                    // Open boundaries drop the bonds wrapping around the lattice
+                    if periodic || coordinate + 1 < LATTICE_SIZE {
+                        neighbours.push(next_position(position, dimension));
+                    }
+                    if periodic || *coordinate > 0 {
+                        neighbours.push(previous_position(position, dimension));
+                    }
+                }
+                neighbours
+            })
+            .collect();
+
+        let mut colours = [Vec::new(), Vec::new()];
+        for position in 0..sites {
+            colours[chessboard(position_to_lattice(position)) as usize].push(position);
+        }
+
+        let mut lattice = Self {
+            spins,
+            neighbours,
+            colours,
+            settings: Arc::new(settings),
+            update_rule,
+            acceptance: Vec::new(),
+        };
+        lattice.update_acceptance();
+        Ok(lattice)
+    }
+
+    fn update_acceptance(&mut self) {
+        let (beta, magnetic_field) = (self.settings.beta, self.settings.magnetic_field);
+        let probability = |energy_change: f64| match self.update_rule {
+            UpdateRule::Metropolis => (-beta * energy_change).exp().min(1.0),
+            UpdateRule::HeatBath => 1.0 / (1.0 + (beta * energy_change).exp()),
+        };
+
+        // dE = 2 (z - 2 k) + 2 h s for a spin s with k of its z neighbours anti-aligned
+        self.acceptance = (0..=2 * DIMENSIONS)
+            .map(|z| {
+                [-1.0, 1.0].map(|spin: f64| {
+                    (0..=z)
+                        .map(|k| {
+                            let energy_change =
+                                2.0 * (z as f64 - 2.0 * k as f64) + 2.0 * magnetic_field * spin;
+                            probability(energy_change)
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+    }
+
+    pub fn set_beta(&mut self, beta: f64) {
+        let mut settings = (*self.settings).clone();
+        settings.beta = beta;
+        self.settings = Arc::new(settings);
+        self.update_acceptance();
+    }
+
+    pub fn set_magnetic_field(&mut self, magnetic_field: f64) {
+        let mut settings = (*self.settings).clone();
+        settings.magnetic_field = magnetic_field;
+        self.settings = Arc::new(settings);
+        self.update_acceptance();
+    }
+
+    pub fn set_update_rule(&mut self, update_rule: UpdateRule) {
+        self.update_rule = update_rule;
+        self.update_acceptance();
+    }
+
+    pub fn len(&self) -> usize {
+        self.spins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spins.is_empty()
+    }
+
+    // Configuration of a single replica
+    pub fn get_fields(&self, replica: usize) -> Vec<IsingField> {
+        self.spins
+            .iter()
+            .map(|word| match word >> replica & 1 {
+                1 => IsingField::Up,
+                _ => IsingField::Down,
+            })
+            .collect()
+    }
+
+    pub fn set_fields(&mut self, replica: usize, fields: &[IsingField]) {
+        for (word, field) in self.spins.iter_mut().zip(fields) {
+            match field {
+                IsingField::Up => *word |= 1 << replica,
+                IsingField::Down => *word &= !(1 << replica),
+            }
+        }
+    }
+
+    pub fn get_energies(&self) -> [f64; REPLICAS] {
+        // Count the anti-aligned bonds and the up spins of every replica
+        let (anti_aligned, up) = self
+            .spins
+            .par_iter()
+            .enumerate()
+            .map(|(position, word)| {
+                let mut anti_aligned = [0u32; REPLICAS];
+                let mut up = [0u32; REPLICAS];
+                for neighbour in self.neighbours[position].iter() {
+                    // Every bond is seen from both of its sites
+                    if *neighbour > position {
+                        count_bits(word ^ self.spins[*neighbour], &mut anti_aligned);
+                    }
+                }
+                count_bits(*word, &mut up);
+                (anti_aligned, up)
+            })
+            .reduce(
+                || ([0u32; REPLICAS], [0u32; REPLICAS]),
+                |(mut a, mut b), (c, d)| {
+                    (0..REPLICAS).for_each(|r| {
+                        a[r] += c[r];
+                        b[r] += d[r];
+                    });
+                    (a, b)
+                },
+            );
+
+        let bonds = self.bonds() as f64;
+        let sites = self.len() as f64;
+        let magnetic_field = self.settings.magnetic_field;
+        std::array::from_fn(|r| {
+            // Aligned bonds contribute -1 and anti-aligned ones +1
+            let magnetization = 2.0 * up[r] as f64 - sites;
+            2.0 * anti_aligned[r] as f64 - bonds - magnetic_field * magnetization
+        })
+    }
+
+    pub fn get_magnetizations(&self) -> [f64; REPLICAS] {
+        let mut up = [0u32; REPLICAS];
+        for word in self.spins.iter() {
+            count_bits(*word, &mut up);
+        }
+        up.map(|up| 2.0 * up as f64 - self.len() as f64)
+    }
+
+    // Energy averaged over the replicas
+    pub fn get_energy(&self) -> f64 {
+        self.get_energies().iter().sum::<f64>() / REPLICAS as f64
+    }
+
+    // Magnetization averaged over the replicas
+    pub fn get_magnetization(&self) -> f64 {
+        self.get_magnetizations().iter().sum::<f64>() / REPLICAS as f64
+    }
+
+    fn bonds(&self) -> usize {
+        self.neighbours
+            .iter()
+            .enumerate()
+            .map(|(position, neighbours)| neighbours.iter().filter(|n| **n > position).count())
+            .sum()
+    }
+
+    // Sweep over the lattice, returns the changes of the energy and magnetization
+    // averaged over the replicas, like get_energy and get_magnetization
+    pub fn montecarlo_sweep(&mut self) -> (f64, f64) {
+        let mut changes = (0.0, 0.0);
+        for colour in 0..2 {
+            // Sites of one colour only see the other colour, so they update in parallel
+            let updated: Vec<u64> = self.colours[colour]
+                .par_iter()
+                .map_init(rand::rng, |rng, position| self.update_site(*position, rng))
+                .collect();
+            for (position, word) in self.colours[colour].iter().zip(updated) {
+                let (energy_change, magnetization_change) = self.changes(*position, word);
+                changes.0 += energy_change;
+                changes.1 += magnetization_change;
+                self.spins[*position] = word;
+            }
+        }
+        (changes.0 / REPLICAS as f64, changes.1 / REPLICAS as f64)
+    }
+
+    // Changes of the energy and magnetization summed over the replicas when a site
+    // takes the updated word
+    fn changes(&self, position: usize, updated: u64) -> (f64, f64) {
+        let word = self.spins[position];
+        let flips = word ^ updated;
+        let magnetization_change =
+            2.0 * ((updated & flips).count_ones() as f64 - (word & flips).count_ones() as f64);
+        // A flip turns an aligned bond (-1) into an anti-aligned one (+1) and back
+        let bond_change: f64 = self.neighbours[position]
+            .iter()
+            .map(|neighbour| {
+                let anti_aligned = word ^ self.spins[*neighbour];
+                2.0 * ((flips & !anti_aligned).count_ones() as f64
+                    - (flips & anti_aligned).count_ones() as f64)
+            })
+            .sum();
+        (
+            bond_change - self.settings.magnetic_field * magnetization_change,
+            magnetization_change,
+        )
+    }
+
+    fn update_site<R: Rng>(&self, position: usize, rng: &mut R) -> u64 {
+        let word = self.spins[position];
+        let neighbours = &self.neighbours[position];
+
+        // Bit-sliced count of the anti-aligned neighbours in every replica
+        let mut counter = [0u64; COUNTER_BITS];
+        for neighbour in neighbours.iter() {
+            add_bits(&mut counter, word ^ self.spins[*neighbour]);
+        }
+
+        // The acceptance grows with the number of anti-aligned neighbours, so the shared
+        // random number translates into a minimum count for each spin orientation
+        let random = rng.random::<f64>();
+        let [down, up] = &self.acceptance[neighbours.len()];
+        let threshold = |acceptance: &Vec<f64>| {
+            acceptance
+                .iter()
+                .filter(|probability| **probability <= random)
+                .count()
+        };
+        let flips = (at_least(&counter, threshold(up)) & word)
+            | (at_least(&counter, threshold(down)) & !word);
+        word ^ flips
+    }
+}
+
+// Add one bit per replica to a bit-sliced counter
+fn add_bits(counter: &mut [u64], bits: u64) {
+    let mut carry = bits;
+    for slice in counter.iter_mut() {
+        let sum = *slice ^ carry;
+        carry &= *slice;
+        *slice = sum;
+    }
+}
+
+// Replicas whose bit-sliced counter is at least the threshold
+fn at_least(counter: &[u64], threshold: usize) -> u64 {
+    if threshold >= 1 << counter.len() {
+        return 0;
+    }
+    let (mut greater, mut equal) = (0, u64::MAX);
+    for (bit, slice) in counter.iter().enumerate().rev() {
+        if threshold >> bit & 1 == 1 {
+            equal &= slice;
+        } else {
+            greater |= equal & slice;
+            equal &= !slice;
+        }
+    }
+    greater | equal
+}
+
+fn count_bits(word: u64, counts: &mut [u32; REPLICAS]) {
+    for (r, count) in counts.iter_mut().enumerate() {
+        *count += (word >> r & 1) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::lattice_geometry::lattice::Lattice;
+    use crate::settings::SettingsBuilder;
+
+    #[test]
+    fn test_bit_sliced_counter() {
+        let mut counter = [0u64; COUNTER_BITS];
+        // Replica r receives r % 7 bits
+        for i in 0..6 {
+            let bits = (0..REPLICAS as u64)
+                .filter(|r| i < r % 7)
+                .fold(0, |bits, r| bits | 1 << r);
+            add_bits(&mut counter, bits);
+        }
+        for threshold in 0..=8 {
+            let expected = (0..REPLICAS as u64)
+                .filter(|r| (r % 7) as usize >= threshold)
+                .fold(0, |bits, r| bits | 1 << r);
+            assert_eq!(at_least(&counter, threshold), expected);
+        }
+    }
+
+    #[test]
+    fn test_multispin_energy() {
+        let settings = SettingsBuilder::new()
+            .add_site_initialisation(Initialisation::Random)
+            .add_magnetic_field(0.3)
+            .build();
+        let multispin = MultispinLattice::new(settings.clone(), UpdateRule::Metropolis).unwrap();
+
+        // Every replica agrees with the same configuration on a lattice
+        let energies = multispin.get_energies();
+        let magnetizations = multispin.get_magnetizations();
+        for replica in [0, 17, 63] {
+            let mut lattice = Lattice::new(settings.clone()).unwrap();
+            for (position, field) in multispin.get_fields(replica).into_iter().enumerate() {
+                lattice.get(position).write().unwrap().field = field;
+            }
+            lattice.refresh_totals();
+            assert!((energies[replica] - lattice.get_energy()).abs() < 1e-9);
+            assert_eq!(magnetizations[replica], lattice.get_magnetization());
+        }
+    }
+
+    #[test]
+    fn test_multispin_open_boundary_conditions() {
+        let settings = SettingsBuilder::new()
+            .add_boundary_conditions(BoundaryConditions::Open)
+            .build();
+        let multispin = MultispinLattice::new(settings.clone(), UpdateRule::HeatBath).unwrap();
+        let lattice = Lattice::new(settings).unwrap();
+        assert_eq!(multispin.get_energy(), lattice.get_energy());
+    }
+
+    #[test]
+    fn test_multispin_montecarlo_sweep() {
+        // The ground state is frozen at zero temperature
+        let settings = SettingsBuilder::new().add_beta(f64::INFINITY).build();
+        let mut multispin = MultispinLattice::new(settings, UpdateRule::Metropolis).unwrap();
+        let ground_state = multispin.get_energy();
+        multispin.montecarlo_sweep();
+        assert_eq!(multispin.get_energy(), ground_state);
+
+        // At infinite temperature heat bath randomises every spin at every sweep. The
+        // replicas share their random numbers, so only sweeps average out.
+        multispin.set_beta(0.0);
+        multispin.set_update_rule(UpdateRule::HeatBath);
+        let sweeps = 200;
+        let mut energy = 0.0;
+        for _ in 0..sweeps {
+            multispin.montecarlo_sweep();
+            energy += multispin.get_energy() / multispin.len() as f64;
+        }
+        assert!((energy / sweeps as f64).abs() < 0.1);
+
+        // The returned changes follow the averaged energy and magnetization
+        let settings = SettingsBuilder::new()
+            .add_beta(0.4)
+            .add_magnetic_field(0.3)
+            .add_boundary_conditions(BoundaryConditions::Open)
+            .build();
+        let mut lattice = MultispinLattice::new(settings, UpdateRule::Metropolis).unwrap();
+        let (energy, magnetization) = (lattice.get_energy(), lattice.get_magnetization());
+        let (energy_change, magnetization_change) = lattice.montecarlo_sweep();
+        assert!((lattice.get_energy() - energy - energy_change).abs() < 1e-9);
+        assert!((lattice.get_magnetization() - magnetization - magnetization_change).abs() < 1e-9);
+
+        // Replicas are set one at a time
+        let fields = multispin.get_fields(0);
+        multispin.set_fields(1, &fields);
+        assert_eq!(multispin.get_fields(1), fields);
+    }
+}
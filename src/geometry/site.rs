@@ -1,12 +1,19 @@
-use crate::field::initialisation::Initialisation;
 use crate::field::ising::IsingField;
-use crate::field::schema::Field;
-use crate::geometry::utils::{chessboard, position_to_lattice};
-use crate::settings::{DIMENSIONS, Settings};
+use crate::geometry::lattice_geometry::interactions::Interactions;
+use crate::geometry::utils::{colour, position_to_lattice};
+use crate::montecarlo::boltzmann::BoltzmannTable;
+use crate::settings::DIMENSIONS;
 use rand::Rng;
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
+// Further-neighbour shell sharing a single coupling
+#[derive(Debug, Clone)]
+pub struct NeighbourShell {
+    pub coupling: f64,
+    pub sites: Vec<Arc<RwLock<Site>>>,
+}
+
 #[derive(Debug)]
 pub struct Site {
     pub id: Uuid,
@@ -14,8 +21,14 @@ pub struct Site {
     pub field: IsingField,
     pub next: [Option<Arc<RwLock<Site>>>; DIMENSIONS],
     pub previous: [Option<Arc<RwLock<Site>>>; DIMENSIONS],
+    // Signs of the nearest-neighbour couplings, reversed across antiperiodic boundaries
+    pub next_coupling: [f64; DIMENSIONS],
+    pub previous_coupling: [f64; DIMENSIONS],
+    // Sum of the couplings times the spins of the fixed boundaries next to the site
+    pub boundary_field: f64,
+    pub shells: Vec<NeighbourShell>,
     pub lattice_position: [usize; DIMENSIONS],
-    pub chessboard: bool,
+    pub colour: usize,
 }
 
 impl Clone for Site {
@@ -23,25 +36,33 @@ impl Clone for Site {
         Self {
             id: Uuid::new_v4(),
             position: self.position,
-            field: self.field.clone(),
+            field: self.field,
             next: self.next.clone(),
             previous: self.previous.clone(),
+            next_coupling: self.next_coupling,
+            previous_coupling: self.previous_coupling,
+            boundary_field: self.boundary_field,
+            shells: self.shells.clone(),
             lattice_position: self.lattice_position,
-            chessboard: self.chessboard,
+            colour: self.colour,
         }
     }
 }
 
 impl Site {
-    pub fn new(position: usize, initialisation: Initialisation) -> Self {
+    pub fn new(position: usize, field: IsingField) -> Self {
         Self {
             id: Uuid::new_v4(),
             position,
-            field: IsingField::new(initialisation),
+            field,
             next: [const { None }; DIMENSIONS],
             previous: [const { None }; DIMENSIONS],
+            next_coupling: [1.0; DIMENSIONS],
+            previous_coupling: [1.0; DIMENSIONS],
+            boundary_field: 0.0,
+            shells: Vec::new(),
             lattice_position: position_to_lattice(position),
-            chessboard: chessboard(position_to_lattice(position)),
+            colour: colour(position_to_lattice(position), &Interactions::default()),
         }
     }
 
@@ -53,6 +74,14 @@ impl Site {
         self.previous[dimension] = site;
     }
 
+    pub fn add_shell(&mut self, shell: NeighbourShell) {
+        self.shells.push(shell);
+    }
+
+    pub fn update_colour(&mut self, colour: usize) {
+        self.colour = colour;
+    }
+
     pub fn flip(&mut self) {
         self.field = match self.field {
             IsingField::Up => IsingField::Down,
@@ -61,63 +90,65 @@ impl Site {
     }
 
     pub fn local_energy(&self) -> f64 {
-        // Initialise the energy to zero
-        let mut energy = 0.0;
-
-        // Add the energy of the next site
-        for next in self.next.iter().flatten() {
-            let current_field = self.field;
-            let next_field = next.read().unwrap().field;
-            energy += current_field.interaction(&next_field);
-        }
-
-        // Add the energy of the previous site
-        for previous in self.previous.iter().flatten() {
-            let current_field = self.field;
-            let previous_field = previous.read().unwrap().field;
-            energy += current_field.interaction(&previous_field);
-        }
-
-        // Return the energy
-        energy
+        // The energy of every bond is -J s s', so the local energy is -s f
+        -self.field.value() * self.local_field()
     }
 
-    pub fn montecarlo_single_site<R: Rng>(&mut self, settings: &Settings, rng: &mut R) -> bool {
-        // Compute the local energy BEFORE flipping (only read locks)
-        let local_energy = self.local_energy();
+    // Energy of the bonds to fixed boundaries, which only this site counts
+    pub fn boundary_energy(&self) -> f64 {
+        -self.field.value() * self.boundary_field
+    }
 
-        // Flip the site
-        self.flip();
+    // Sum of the neighbouring spins weighted by their couplings, so that the local
+    // energy is -s f
+    pub fn local_field(&self) -> f64 {
+        let mut field = self.boundary_field;
+
+        // Add the nearest neighbours
+        let neighbours = self.next.iter().zip(self.next_coupling.iter());
+        let neighbours = neighbours.chain(self.previous.iter().zip(self.previous_coupling.iter()));
+        for (neighbour, coupling) in neighbours {
+            if let Some(neighbour) = neighbour {
+                field += coupling * neighbour.read().unwrap().field.value();
+            }
+        }
 
-        // Compute the local energy after flipping
-        let new_local_energy = self.local_energy();
+        // Add the further-neighbour shells
+        for shell in self.shells.iter() {
+            for neighbour in shell.sites.iter() {
+                field += shell.coupling * neighbour.read().unwrap().field.value();
+            }
+        }
 
-        // Compute the energy ratio
-        let energy_ratio = (-settings.beta * (new_local_energy - local_energy)).exp();
+        field
+    }
 
-        // Compute the acceptance probability
-        if energy_ratio > 1.0 {
-            //println!("Accepting flip!");
-            // Accept the flip
-            true
+    // Metropolis update, returns the energy change of an accepted flip
+    pub fn montecarlo_single_site<R: Rng>(
+        &mut self,
+        boltzmann: &BoltzmannTable,
+        rng: &mut R,
+    ) -> Option<f64> {
+        // Look up exp(-beta dE) of the flip, dE = 2 s (f + h), without flipping the site
+        let spin = self.field.value();
+        let local_field = self.local_field();
+        let energy_ratio = boltzmann.ratio(spin, local_field);
+
+        // Compute the acceptance probability, drawing a random number only if needed
+        if energy_ratio > 1.0 || rng.random_range(0.0..=1.0) < energy_ratio {
+            self.flip();
+            Some(2.0 * spin * (local_field + boltzmann.magnetic_field()))
         } else {
-            // Sampling step
-            let random_number = rng.random_range(0.0..=1.0);
-            if random_number < energy_ratio {
-                //println!("Accepting flip!");
-                // Accept the flip
-                true
-            } else {
-                // Flip the site back
-                self.flip();
-
-                // Reject the flip
-                false
-            }
+            None
         }
     }
 }
 
+// Metropolis acceptance of a move with the given log weight ratio
+pub fn metropolis<R: Rng>(ln_ratio: f64, rng: &mut R) -> bool {
+    ln_ratio >= 0.0 || rng.random_range(0.0..=1.0) < ln_ratio.exp()
+}
+
 impl PartialEq for Site {
     fn eq(&self, other: &Self) -> bool {
         self.position == other.position
@@ -158,23 +189,22 @@ impl PartialEq<Site> for Arc<Site> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::field::initialisation::Initialisation;
     use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
     use crate::settings::SettingsBuilder;
 
     #[test]
     fn test_site_new() {
         let position = 0;
-        let initialisation = Initialisation::Uniform;
-        let site = Site::new(position, initialisation.clone());
+        let site = Site::new(position, IsingField::Up);
         assert_eq!(site.position, position);
-        assert_eq!(site.field, IsingField::new(initialisation));
+        assert_eq!(site.field, IsingField::Up);
     }
 
     #[test]
     fn test_site_flip() {
         let position = 0;
-        let initialisation = Initialisation::Uniform;
-        let mut site = Site::new(position, initialisation.clone());
+        let mut site = Site::new(position, IsingField::Up);
         site.flip();
         assert_eq!(site.field, IsingField::Down);
     }
@@ -182,8 +212,7 @@ mod tests {
     #[test]
     fn test_site_local_energy() {
         let position = 0;
-        let initialisation = Initialisation::Uniform;
-        let site = Site::new(position, initialisation.clone());
+        let site = Site::new(position, IsingField::Up);
         assert_eq!(site.local_energy(), 0.0);
     }
 
@@ -193,10 +222,30 @@ mod tests {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Periodic,
             site_initialisation: Initialisation::Uniform,
+            ..SettingsBuilder::new()
         }
         .build();
-        let mut site = Site::new(0, Initialisation::Uniform);
+        let mut site = Site::new(0, IsingField::Up);
         let mut rng = rand::rng();
-        site.montecarlo_single_site(&settings, &mut rng);
+        site.montecarlo_single_site(&BoltzmannTable::new(&settings), &mut rng);
+    }
+
+    #[test]
+    fn test_site_montecarlo_single_site_magnetic_field() {
+        // An isolated site in a strong field always ends up aligned with it
+        let settings = SettingsBuilder::new()
+            .add_beta(1.0)
+            .add_magnetic_field(-100.0)
+            .build();
+        let boltzmann = BoltzmannTable::new(&settings);
+        let mut site = Site::new(0, IsingField::Up);
+        let mut rng = rand::rng();
+        assert_eq!(
+            site.montecarlo_single_site(&boltzmann, &mut rng),
+            Some(-200.0)
+        );
+        assert_eq!(site.field, IsingField::Down);
+        assert_eq!(site.montecarlo_single_site(&boltzmann, &mut rng), None);
+        assert_eq!(site.field, IsingField::Down);
     }
 }
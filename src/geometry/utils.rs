@@ -1,3 +1,4 @@
+use crate::geometry::lattice_geometry::interactions::Interactions;
 use crate::settings::{DIMENSIONS, LATTICE_SIZE};
 
 pub fn next_position(position: usize, dimension: usize) -> usize {
@@ -26,12 +27,12 @@ pub fn position_to_lattice(position: usize) -> [usize; DIMENSIONS] {
     let mut lattice_position = [0; DIMENSIONS];
     let mut position = position;
 
-    for i in 0..DIMENSIONS {
+    for coordinate in lattice_position.iter_mut() {
         // Compute the lattice position in the dimension passed
-        lattice_position[i] = position % LATTICE_SIZE;
+        *coordinate = position % LATTICE_SIZE;
 
         // Update the position
-        position = position / LATTICE_SIZE;
+        position /= LATTICE_SIZE;
     }
     lattice_position
 }
@@ -41,17 +42,56 @@ pub fn lattice_to_position(lattice_position: [usize; DIMENSIONS]) -> usize {
     let mut position = 0;
 
     // Iterate over the dimensions
-    for i in 0..DIMENSIONS {
+    for (i, coordinate) in lattice_position.iter().enumerate() {
         // Compute the lattice position in the dimension passed
-        position += lattice_position[i] * usize::pow(LATTICE_SIZE, i as u32);
+        position += coordinate * usize::pow(LATTICE_SIZE, i as u32);
     }
     position
 }
 
+pub fn shift_position(
+    position: usize,
+    offset: [isize; DIMENSIONS],
+    periodic: bool,
+) -> Option<usize> {
+    // Convert to lattice position
+    let mut lattice_position = position_to_lattice(position);
+
+    // Shift the lattice position in every dimension
+    for (coordinate, shift) in lattice_position.iter_mut().zip(offset) {
+        let shifted = *coordinate as isize + shift;
+
+        // Outside of the lattice with open boundaries there is no site
+        if !periodic && !(0..LATTICE_SIZE as isize).contains(&shifted) {
+            return None;
+        }
+        *coordinate = shifted.rem_euclid(LATTICE_SIZE as isize) as usize;
+    }
+
+    // Convert back to position
+    Some(lattice_to_position(lattice_position))
+}
+
 pub fn chessboard(lattice_position: [usize; DIMENSIONS]) -> bool {
     lattice_position.iter().sum::<usize>() % 2 == 0
 }
 
+pub fn colour(lattice_position: [usize; DIMENSIONS], interactions: &Interactions) -> usize {
+    // Nearest neighbours only need the two chessboard colours
+    if interactions.colours() == 2 {
+        return if chessboard(lattice_position) { 0 } else { 1 };
+    }
+
+    // Otherwise every coordinate modulo the colour period of its axis labels the colour
+    let mut colour = 0;
+    let mut stride = 1;
+    for (coordinate, period) in lattice_position.iter().zip(interactions.colour_periods()) {
+        colour += (coordinate % period) * stride;
+        stride *= period;
+    }
+    colour
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,9 +119,33 @@ mod tests {
 
     #[test]
     fn test_chessboard() {
-        assert_eq!(chessboard([0, 0, 0]), true);
-        assert_eq!(chessboard([0, 0, 1]), false);
-        assert_eq!(chessboard([0, 1, 0]), false);
-        assert_eq!(chessboard([0, 1, 1]), true);
+        assert!(chessboard([0, 0, 0]));
+        assert!(!chessboard([0, 0, 1]));
+        assert!(!chessboard([0, 1, 0]));
+        assert!(chessboard([0, 1, 1]));
+    }
+
+    #[test]
+    fn test_shift_position() {
+        assert_eq!(shift_position(0, [1, 0, 0], true), Some(1));
+        assert_eq!(shift_position(0, [-1, 1, 0], true), Some(7));
+        assert_eq!(shift_position(0, [-1, 1, 0], false), None);
+        assert_eq!(shift_position(0, [0, 0, -2], true), Some(32));
+        assert_eq!(shift_position(5, [2, 2, 0], false), Some(15));
+    }
+
+    #[test]
+    fn test_colour() {
+        let interactions = Interactions::nearest_neighbour();
+        assert_eq!(colour([0, 0, 0], &interactions), 0);
+        assert_eq!(colour([0, 1, 0], &interactions), 1);
+
+        let interactions = Interactions::j1_j2(1.0);
+        assert_eq!(colour([1, 1, 0], &interactions), 3);
+        assert_eq!(colour([3, 2, 1], &interactions), 5);
+
+        let interactions = Interactions::annni(0.5, 2);
+        assert_eq!(colour([2, 0, 0], &interactions), 0);
+        assert_eq!(colour([0, 1, 3], &interactions), 14);
     }
 }
@@ -1,3 +1,6 @@
 pub mod field;
 pub mod geometry;
+pub mod montecarlo;
+pub mod output;
 pub mod settings;
+pub mod statistics;
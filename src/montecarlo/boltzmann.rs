@@ -0,0 +1,130 @@
+use crate::settings::{DIMENSIONS, Settings};
+
+// Local fields closer than this share an entry
+const FIELD_RESOLUTION: f64 = 1e-9;
+
+// Metropolis ratios exp(-beta dE) of single spin flips, tabulated by the spin and the
+// local field f = sum_j J_j s_j of its neighbours, since dE = 2 s (f + h) only takes a
+// few values for discrete couplings. Rebuild whenever beta, J or h change.
+#[derive(Debug, Clone)]
+pub struct BoltzmannTable {
+    beta: f64,
+    magnetic_field: f64,
+    keys: Vec<i64>,
+    // Ratios of a down and an up spin for every local field
+    ratios: Vec<[f64; 2]>,
+}
+
+impl BoltzmannTable {
+    pub fn new(settings: &Settings) -> Self {
+        let interactions = &settings.interactions;
+        let shells = [
+            (1.0, 2 * DIMENSIONS),
+            (interactions.diagonal, interactions.diagonal_offsets().len()),
+            (interactions.axial, interactions.axial_offsets().len()),
+        ];
+
+        // Every local field reachable with up to the full shells of neighbours, which
+        // also covers the sites missing neighbours at open boundaries
+        let mut fields = vec![0.0];
+        for (coupling, size) in shells {
+            if size == 0 {
+                continue;
+            }
+            let size = size as i64;
+            fields = fields
+                .iter()
+                .flat_map(|field| (-size..=size).map(move |sum| field + coupling * sum as f64))
+                .collect();
+        }
+
+        let mut keys: Vec<i64> = fields.iter().map(|field| Self::key(*field)).collect();
+        keys.sort();
+        keys.dedup();
+
+        let mut table = Self {
+            beta: settings.beta,
+            magnetic_field: settings.magnetic_field,
+            ratios: Vec::with_capacity(keys.len()),
+            keys,
+        };
+        table.ratios = table
+            .keys
+            .iter()
+            .map(|key| {
+                let field = *key as f64 * FIELD_RESOLUTION;
+                [-1.0, 1.0].map(|spin| table.compute(spin, field))
+            })
+            .collect();
+        table
+    }
+
+    fn key(field: f64) -> i64 {
+        (field / FIELD_RESOLUTION).round() as i64
+    }
+
+    fn compute(&self, spin: f64, field: f64) -> f64 {
+        (-self.beta * 2.0 * spin * (field + self.magnetic_field)).exp()
+    }
+
+    // exp(-beta dE) of flipping a spin in the given local field
+    pub fn ratio(&self, spin: f64, field: f64) -> f64 {
+        match self.keys.binary_search(&Self::key(field)) {
+            Ok(index) => self.ratios[index][(spin > 0.0) as usize],
+            // Fields outside of the table, e.g. from couplings changed by hand
+            Err(_) => self.compute(spin, field),
+        }
+    }
+
+    pub fn magnetic_field(&self) -> f64 {
+        self.magnetic_field
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::lattice_geometry::interactions::Interactions;
+    use crate::settings::SettingsBuilder;
+
+    #[test]
+    fn test_boltzmann_table() {
+        let settings = SettingsBuilder::new()
+            .add_beta(0.4)
+            .add_magnetic_field(0.1)
+            .build();
+        let table = BoltzmannTable::new(&settings);
+        assert_eq!(table.len(), 4 * DIMENSIONS + 1);
+        for field in -(2 * DIMENSIONS as i64)..=2 * DIMENSIONS as i64 {
+            for spin in [-1.0, 1.0] {
+                let energy_change = 2.0 * spin * (field as f64 + 0.1);
+                let expected = (-0.4 * energy_change).exp();
+                assert_eq!(table.ratio(spin, field as f64), expected);
+            }
+        }
+        // Missing entries are computed directly
+        assert_eq!(table.ratio(1.0, 0.25), (-0.4 * 2.0 * 0.35_f64).exp());
+    }
+
+    #[test]
+    fn test_boltzmann_table_further_neighbours() {
+        let settings = SettingsBuilder::new()
+            .add_beta(0.4)
+            .add_interactions(Interactions::j1_j2(-0.5))
+            .build();
+        let table = BoltzmannTable::new(&settings);
+        // Nearest-neighbour sums plus half-integer diagonal sums
+        let diagonal = Interactions::j1_j2(-0.5).diagonal_offsets().len() as f64;
+        let expected = 2.0 * (2.0 * DIMENSIONS as f64 + 0.5 * diagonal) / 0.5 + 1.0;
+        assert_eq!(table.len(), expected as usize);
+        assert_eq!(table.ratio(-1.0, 1.5), (0.4 * 2.0 * 1.5_f64).exp());
+    }
+}
@@ -0,0 +1,7 @@
+pub mod boltzmann;
+pub mod multicanonical;
+pub mod nfold_way;
+pub mod parallel_tempering;
+pub mod random;
+pub mod schedule;
+pub mod wang_landau;
@@ -0,0 +1,343 @@
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::geometry::site::metropolis;
+use crate::montecarlo::wang_landau::{DensityOfStates, log_sum_exp};
+use crate::settings::Settings;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::io;
+
+// Tabulated ln W(E) on energy bins. Outside of the tabulated bins the weight is
+// extrapolated canonically at beta from the closest tabulated bin.
+#[derive(Debug, Clone)]
+pub struct MulticanonicalWeights {
+    pub resolution: f64,
+    pub beta: f64,
+    ln_w: BTreeMap<i64, f64>,
+}
+
+impl MulticanonicalWeights {
+    // Canonical weights W(E) = exp(-beta E) as a starting point of the iteration
+    pub fn canonical(beta: f64, resolution: f64) -> Self {
+        Self {
+            resolution,
+            beta,
+            ln_w: BTreeMap::new(),
+        }
+    }
+
+    // Weights W(E) = 1 / g(E), for instance from a Wang-Landau run
+    pub fn from_density_of_states(
+        density_of_states: &DensityOfStates,
+        beta: f64,
+        resolution: f64,
+    ) -> Self {
+        let mut weights = Self::canonical(beta, resolution);
+        for (energy, ln_g) in density_of_states.levels.iter() {
+            weights.ln_w.insert(weights.bin(*energy), -ln_g);
+        }
+        weights
+    }
+
+    pub fn bin(&self, energy: f64) -> i64 {
+        (energy / self.resolution).round() as i64
+    }
+
+    pub fn ln_weight(&self, energy: f64) -> f64 {
+        let bin = self.bin(energy);
+        if let Some(ln_w) = self.ln_w.get(&bin) {
+            return *ln_w;
+        }
+
+        // Closest tabulated bin on either side
+        let below = self.ln_w.range(..bin).next_back();
+        let above = self.ln_w.range(bin..).next();
+        let closest = match (below, above) {
+            (Some(below), Some(above)) => match bin - below.0 <= above.0 - bin {
+                true => below,
+                false => above,
+            },
+            (Some(closest), None) | (None, Some(closest)) => closest,
+            (None, None) => return -self.beta * energy,
+        };
+        closest.1 - self.beta * (bin - closest.0) as f64 * self.resolution
+    }
+
+    // One step of the weight recursion: ln W(E) <- ln W(E) - ln H(E) on visited bins
+    pub fn update(&mut self, histogram: &BTreeMap<i64, u64>) {
+        for (bin, count) in histogram.iter().filter(|(_, count)| **count > 0) {
+            let ln_w = self.ln_weight(*bin as f64 * self.resolution);
+            self.ln_w.insert(*bin, ln_w - (*count as f64).ln());
+        }
+    }
+}
+
+pub struct Multicanonical {
+    pub lattice: Lattice,
+    pub weights: MulticanonicalWeights,
+    // Minimum ratio between the smallest and the mean histogram entry
+    pub flatness: f64,
+    // Energy range the flat histogram has to cover
+    pub range: Option<(f64, f64)>,
+    histogram: BTreeMap<i64, u64>,
+    energy: f64,
+    magnetization: f64,
+    // Production time series, reweighted to canonical ensembles
+    pub energies: Vec<f64>,
+    pub magnetizations: Vec<f64>,
+}
+
+impl Multicanonical {
+    pub fn new(settings: Settings, weights: MulticanonicalWeights) -> io::Result<Self> {
+        assert!(
+            settings.interactions.long_range.is_none(),
+            "Multicanonical sampling needs short-range interactions"
+        );
+        let lattice = Lattice::new(settings)?;
+        let energy = lattice.get_energy();
+        let magnetization = lattice.get_magnetization();
+        Ok(Self {
+            lattice,
+            weights,
+            flatness: 0.5,
+            range: None,
+            histogram: BTreeMap::new(),
+            energy,
+            magnetization,
+            energies: Vec::new(),
+            magnetizations: Vec::new(),
+        })
+    }
+
+    // Sequential sweep: the weights depend on the total energy, so sites cannot be
+    // updated in parallel
+    pub fn montecarlo_sweep<R: Rng>(&mut self, rng: &mut R) {
+        for _ in 0..self.lattice.len() {
+            let position = rng.random_range(0..self.lattice.len());
+            let energy_change = self.lattice.flip_energy_change(position);
+
+            // Accept with min(1, W(E') / W(E)) from the tabulated weights
+            let ln_ratio = self.weights.ln_weight(self.energy + energy_change)
+                - self.weights.ln_weight(self.energy);
+            if metropolis(ln_ratio, rng) {
+                self.lattice.flip(position);
+                self.energy += energy_change;
+                self.magnetization +=
+                    2.0 * self.lattice.get(position).read().unwrap().field.value();
+            }
+            *self
+                .histogram
+                .entry(self.weights.bin(self.energy))
+                .or_insert(0) += 1;
+        }
+
+        // The sites were updated directly: refresh the lattice totals, which also
+        // avoids the accumulation of rounding errors
+        self.lattice.refresh_totals();
+        self.energy = self.lattice.get_energy();
+    }
+
+    pub fn is_flat(&self) -> bool {
+        // Only the bins inside the range count, and both ends have to be reached
+        let (from, to) = match self.range {
+            Some((min, max)) => (self.weights.bin(min), self.weights.bin(max)),
+            None => (i64::MIN, i64::MAX),
+        };
+        let counts: Vec<u64> = self
+            .histogram
+            .range(from..=to)
+            .map(|(_, count)| *count)
+            .collect();
+        if counts.is_empty() {
+            return false;
+        }
+        if self.range.is_some() {
+            let visited_from = *self.histogram.keys().next().unwrap();
+            let visited_to = *self.histogram.keys().next_back().unwrap();
+            if visited_from > from || visited_to < to {
+                return false;
+            }
+        }
+
+        let min = *counts.iter().min().unwrap() as f64;
+        let mean = counts.iter().sum::<u64>() as f64 / counts.len() as f64;
+        min >= self.flatness * mean
+    }
+
+    // Iterate the weights until the energy histogram is flat, returns the number of
+    // iterations or None if the histogram never became flat
+    pub fn iterate_weights<R: Rng>(
+        &mut self,
+        sweeps: u32,
+        max_iterations: u32,
+        rng: &mut R,
+    ) -> Option<u32> {
+        for iteration in 1..=max_iterations {
+            self.histogram.clear();
+            for _ in 0..sweeps {
+                self.montecarlo_sweep(rng);
+            }
+            if self.is_flat() {
+                return Some(iteration);
+            }
+            self.weights.update(&self.histogram);
+        }
+        None
+    }
+
+    // Production sweep with fixed weights, recording the time series
+    pub fn measure<R: Rng>(&mut self, rng: &mut R) {
+        self.montecarlo_sweep(rng);
+        self.energies.push(self.energy);
+        self.magnetizations.push(self.magnetization);
+    }
+
+    // Log reweighting factors exp(-beta E - ln W(E)) of the recorded measurements
+    fn ln_reweighting(&self, beta: f64) -> Vec<f64> {
+        let ln_factors: Vec<f64> = self
+            .energies
+            .iter()
+            .map(|energy| -beta * energy - self.weights.ln_weight(*energy))
+            .collect();
+        let ln_norm = log_sum_exp(ln_factors.iter().copied());
+        ln_factors.iter().map(|ln| ln - ln_norm).collect()
+    }
+
+    // Canonical average at beta of an observable of energy and magnetization
+    pub fn reweight<F: Fn(f64, f64) -> f64>(&self, beta: f64, observable: F) -> f64 {
+        self.ln_reweighting(beta)
+            .iter()
+            .zip(self.energies.iter().zip(self.magnetizations.iter()))
+            .map(|(ln, (energy, magnetization))| ln.exp() * observable(*energy, *magnetization))
+            .sum()
+    }
+
+    // Canonical probability distribution at beta of the recorded values
+    pub fn reweighted_histogram(&self, beta: f64, values: &[f64], width: f64) -> Vec<(f64, f64)> {
+        let mut histogram: BTreeMap<i64, f64> = BTreeMap::new();
+        for (ln, value) in self.ln_reweighting(beta).iter().zip(values.iter()) {
+            *histogram
+                .entry((value / width).round() as i64)
+                .or_insert(0.0) += ln.exp();
+        }
+        histogram
+            .iter()
+            .map(|(bin, probability)| (*bin as f64 * width, *probability))
+            .collect()
+    }
+
+    pub fn energy_histogram(&self, beta: f64) -> Vec<(f64, f64)> {
+        self.reweighted_histogram(beta, &self.energies, self.weights.resolution)
+    }
+
+    // Magnetization changes in steps of 2
+    pub fn magnetization_histogram(&self, beta: f64) -> Vec<(f64, f64)> {
+        self.reweighted_histogram(beta, &self.magnetizations, 2.0)
+    }
+}
+
+// Interface tension beta sigma = ln(P_max / P_min) / (2 A) from a double-peak
+// histogram, with A the cross-section of the interfaces. P_max is the geometric mean
+// of the two peaks and P_min the minimum between them.
+pub fn interface_tension(histogram: &[(f64, f64)], area: f64) -> Option<f64> {
+    let probabilities: Vec<f64> = histogram.iter().map(|(_, p)| *p).collect();
+    let highest =
+        (0..probabilities.len()).max_by(|a, b| probabilities[*a].total_cmp(&probabilities[*b]))?;
+
+    // Second peak: the one with the deepest valley between itself and the highest
+    let valley = |i: usize| -> f64 {
+        let (from, to) = (i.min(highest), i.max(highest));
+        probabilities[from..=to]
+            .iter()
+            .copied()
+            .fold(f64::INFINITY, f64::min)
+    };
+    let second = (0..probabilities.len()).max_by(|a, b| {
+        (probabilities[*a] - valley(*a)).total_cmp(&(probabilities[*b] - valley(*b)))
+    })?;
+    let minimum = valley(second);
+    if probabilities[second] <= minimum || minimum <= 0.0 {
+        return None;
+    }
+
+    let ln_max = 0.5 * (probabilities[highest].ln() + probabilities[second].ln());
+    Some((ln_max - minimum.ln()) / (2.0 * area))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingsBuilder;
+
+    #[test]
+    fn test_multicanonical_weights() {
+        let mut weights = MulticanonicalWeights::canonical(0.5, 4.0);
+        assert_eq!(weights.ln_weight(-8.0), 4.0);
+
+        let mut histogram = BTreeMap::new();
+        histogram.insert(-2, 10);
+        weights.update(&histogram);
+        assert!((weights.ln_weight(-8.0) - (4.0 - 10.0_f64.ln())).abs() < 1e-12);
+
+        // Canonical extrapolation from the closest tabulated bin
+        assert!((weights.ln_weight(-16.0) - (8.0 - 10.0_f64.ln())).abs() < 1e-12);
+        assert!((weights.ln_weight(0.0) - (0.0 - 10.0_f64.ln())).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_interface_tension() {
+        let histogram = vec![
+            (-3.0, 0.1),
+            (-2.0, 0.4),
+            (-1.0, 0.3),
+            (0.0, 0.01),
+            (1.0, 0.35),
+            (2.0, 0.4),
+            (3.0, 0.1),
+        ];
+        let tension = interface_tension(&histogram, 2.0).unwrap();
+        assert!((tension - (0.4_f64 / 0.01).ln() / 4.0).abs() < 1e-12);
+
+        // Single peak: no interface
+        let histogram = vec![(-1.0, 0.2), (0.0, 0.6), (1.0, 0.2)];
+        assert_eq!(interface_tension(&histogram, 2.0), None);
+    }
+
+    #[test]
+    fn test_multicanonical_canonical_reweighting() {
+        let settings = SettingsBuilder::new().add_beta(0.2).build();
+        let weights = MulticanonicalWeights::canonical(0.2, 4.0);
+        let mut multicanonical = Multicanonical::new(settings, weights).unwrap();
+        let mut rng = rand::rng();
+        for _ in 0..10 {
+            multicanonical.measure(&mut rng);
+        }
+        assert_eq!(multicanonical.energies.len(), 10);
+        assert!(
+            (multicanonical.magnetization - multicanonical.lattice.get_magnetization()).abs()
+                < 1e-9
+        );
+
+        // Canonical weights at the same beta: reweighting is a plain average
+        let mean = multicanonical.energies.iter().sum::<f64>() / 10.0;
+        assert!((multicanonical.reweight(0.2, |energy, _| energy) - mean).abs() < 1e-9);
+
+        let histogram = multicanonical.energy_histogram(0.2);
+        assert!((histogram.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_multicanonical_is_flat() {
+        let settings = SettingsBuilder::new().build();
+        let weights = MulticanonicalWeights::canonical(0.0, 4.0);
+        let mut multicanonical = Multicanonical::new(settings, weights).unwrap();
+        multicanonical.histogram.insert(-2, 10);
+        multicanonical.histogram.insert(-1, 8);
+        assert!(multicanonical.is_flat());
+
+        // The histogram has to reach both ends of the range
+        multicanonical.range = Some((-8.0, 0.0));
+        assert!(!multicanonical.is_flat());
+        multicanonical.histogram.insert(0, 6);
+        assert!(multicanonical.is_flat());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use rand::Rng;
+use std::collections::BTreeMap;
+
+// Energy changes closer than this fall into the same class
+const CLASS_RESOLUTION: f64 = 1e-9;
+
+// Sites sharing the same flip energy change, hence the same flip rate
+#[derive(Debug, Clone)]
+struct FlipClass {
+    rate: f64,
+    sites: Vec<usize>,
+}
+
+// Saved state of the n-fold way: the time and the order of the sites in every class,
+// which the choice of the flipped site depends on
+#[derive(Debug, PartialEq, Clone)]
+pub struct NFoldWayState {
+    pub time: f64,
+    // Key, rate and sites of every class
+    pub classes: Vec<(i64, f64, Vec<usize>)>,
+}
+
+// Rejection-free continuous-time Monte Carlo (Bortz-Kalos-Lebowitz n-fold way).
+// Every site flips at the Metropolis rate min(1, exp(-beta dE)) per unit of time, so
+// one unit of time corresponds to one sweep of random sequential single-site updates.
+pub struct NFoldWay {
+    pub time: f64,
+    neighbours: Vec<Vec<usize>>,
+    classes: BTreeMap<i64, FlipClass>,
+    // Class of every site and its index inside the class
+    class_of: Vec<i64>,
+    index_in_class: Vec<usize>,
+}
+
+impl NFoldWay {
+    pub fn new(lattice: &Lattice) -> Self {
+        assert!(
+            lattice.settings.interactions.long_range.is_none(),
+            "The n-fold way needs short-range interactions"
+        );
+
+        // Every site whose flip energy change depends on the site
+        let neighbours = (0..lattice.len())
+            .map(|position| {
+                let site = lattice.get(position);
+                let site = site.read().unwrap();
+                let mut neighbours: Vec<usize> = site
+                    .next
+                    .iter()
+                    .chain(site.previous.iter())
+                    .flatten()
+                    .chain(site.shells.iter().flat_map(|shell| shell.sites.iter()))
+                    .map(|neighbour| neighbour.read().unwrap().position)
+                    .collect();
+                neighbours.sort();
+                neighbours.dedup();
+                neighbours
+            })
+            .collect();
+
+        let mut nfold_way = Self {
+            time: 0.0,
+            neighbours,
+            classes: BTreeMap::new(),
+            class_of: vec![0; lattice.len()],
+            index_in_class: vec![0; lattice.len()],
+        };
+        nfold_way.refresh(lattice);
+        nfold_way
+    }
+
+    pub fn state(&self) -> NFoldWayState {
+        NFoldWayState {
+            time: self.time,
+            classes: self
+                .classes
+                .iter()
+                .map(|(key, class)| (*key, class.rate, class.sites.clone()))
+                .collect(),
+        }
+    }
+
+    // N-fold way on a lattice in the state it was saved with
+    pub fn restore(lattice: &Lattice, state: &NFoldWayState) -> Self {
+        let mut nfold_way = Self::new(lattice);
+        nfold_way.time = state.time;
+        nfold_way.classes.clear();
+        for (key, rate, sites) in state.classes.iter() {
+            for (index, position) in sites.iter().enumerate() {
+                nfold_way.class_of[*position] = *key;
+                nfold_way.index_in_class[*position] = index;
+            }
+            nfold_way.classes.insert(
+                *key,
+                FlipClass {
+                    rate: *rate,
+                    sites: sites.clone(),
+                },
+            );
+        }
+        nfold_way
+    }
+
+    fn class_key(energy_change: f64) -> i64 {
+        (energy_change / CLASS_RESOLUTION).round() as i64
+    }
+
+    // Reclassify every site, needed whenever beta or the magnetic field change
+    pub fn refresh(&mut self, lattice: &Lattice) {
+        self.classes.clear();
+        for position in 0..lattice.len() {
+            self.insert(lattice, position);
+        }
+    }
+
+    fn insert(&mut self, lattice: &Lattice, position: usize) {
+        let energy_change = lattice.flip_energy_change(position);
+        let key = Self::class_key(energy_change);
+        let class = self.classes.entry(key).or_insert_with(|| FlipClass {
+            rate: (-lattice.settings.beta * energy_change).exp().min(1.0),
+            sites: Vec::new(),
+        });
+        self.class_of[position] = key;
+        self.index_in_class[position] = class.sites.len();
+        class.sites.push(position);
+    }
+
+    fn remove(&mut self, position: usize) {
+        let key = self.class_of[position];
+        let class = self.classes.get_mut(&key).unwrap();
+        let index = self.index_in_class[position];
+        class.sites.swap_remove(index);
+        if let Some(moved) = class.sites.get(index) {
+            self.index_in_class[*moved] = index;
+        }
+        if class.sites.is_empty() {
+            self.classes.remove(&key);
+        }
+    }
+
+    pub fn total_rate(&self) -> f64 {
+        self.classes
+            .values()
+            .map(|class| class.rate * class.sites.len() as f64)
+            .sum()
+    }
+
+    // Flip one site chosen with probability proportional to its rate and advance the
+    // time by an exponentially distributed waiting time. Returns false if no site can
+    // flip.
+    pub fn step<R: Rng>(&mut self, lattice: &mut Lattice, rng: &mut R) -> bool {
+        let total_rate = self.total_rate();
+        if total_rate <= 0.0 {
+            return false;
+        }
+        self.time += -rng.random_range(f64::EPSILON..=1.0).ln() / total_rate;
+        self.flip_event(lattice, total_rate, rng);
+        true
+    }
+
+    fn flip_event<R: Rng>(&mut self, lattice: &mut Lattice, total_rate: f64, rng: &mut R) {
+        // Pick the class with probability n_c w_c / R, then a site uniformly inside it
+        let mut target = rng.random_range(0.0..total_rate);
+        let mut chosen = None;
+        for class in self.classes.values() {
+            let weight = class.rate * class.sites.len() as f64;
+            if target < weight {
+                chosen = Some(class.sites[rng.random_range(0..class.sites.len())]);
+                break;
+            }
+            target -= weight;
+        }
+        // Rounding can leave the target just beyond the last class
+        let position = chosen.unwrap_or_else(|| {
+            let class = self.classes.values().next_back().unwrap();
+            class.sites[rng.random_range(0..class.sites.len())]
+        });
+
+        lattice.flip(position);
+
+        // Only the flipped site and its neighbours change class
+        let neighbours = self.neighbours[position].clone();
+        for site in std::iter::once(position).chain(neighbours) {
+            self.remove(site);
+            self.insert(lattice, site);
+        }
+    }
+
+    // Run until the time has advanced by the given duration, returns the changes of the
+    // energy and magnetization
+    pub fn advance<R: Rng>(
+        &mut self,
+        lattice: &mut Lattice,
+        duration: f64,
+        rng: &mut R,
+    ) -> (f64, f64) {
+        let (energy, magnetization) = (lattice.get_energy(), lattice.get_magnetization());
+        let end = self.time + duration;
+        loop {
+            let total_rate = self.total_rate();
+            if total_rate <= 0.0 {
+                break;
+            }
+
+            // The waiting time is memoryless, so an event beyond the end is discarded
+            let waiting = -rng.random_range(f64::EPSILON..=1.0).ln() / total_rate;
+            if self.time + waiting > end {
+                break;
+            }
+            self.time += waiting;
+            self.flip_event(lattice, total_rate, rng);
+        }
+        self.time = end;
+        (
+            lattice.get_energy() - energy,
+            lattice.get_magnetization() - magnetization,
+        )
+    }
+
+    // One unit of time, the continuous-time equivalent of a sweep
+    pub fn montecarlo_sweep<R: Rng>(&mut self, lattice: &mut Lattice, rng: &mut R) -> (f64, f64) {
+        let changes = self.advance(lattice, 1.0, rng);
+        // One unit of time counts as a sweep of the lattice
+        lattice.set_sweeps(lattice.sweeps() + 1);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::lattice_geometry::interactions::Interactions;
+    use crate::settings::{DIMENSIONS, SettingsBuilder};
+
+    fn assert_classes(nfold_way: &NFoldWay, lattice: &Lattice) {
+        for position in 0..lattice.len() {
+            let key = NFoldWay::class_key(lattice.flip_energy_change(position));
+            assert_eq!(nfold_way.class_of[position], key);
+            let class = &nfold_way.classes[&key];
+            assert_eq!(class.sites[nfold_way.index_in_class[position]], position);
+        }
+    }
+
+    #[test]
+    fn test_nfold_way_classes() {
+        let settings = SettingsBuilder::new().add_beta(0.3).build();
+        let lattice = Lattice::new(settings).unwrap();
+        let nfold_way = NFoldWay::new(&lattice);
+
+        // Uniform configuration: a single class of sites with dE = 4 D
+        assert_eq!(nfold_way.classes.len(), 1);
+        let rate = (-0.3 * 4.0 * DIMENSIONS as f64).exp();
+        assert!((nfold_way.total_rate() - rate * lattice.len() as f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_nfold_way_step() {
+        let settings = SettingsBuilder::new()
+            .add_beta(0.3)
+            .add_magnetic_field(0.2)
+            .add_interactions(Interactions::j1_j2(-0.5))
+            .build();
+        let mut lattice = Lattice::new(settings).unwrap();
+        let mut nfold_way = NFoldWay::new(&lattice);
+        let mut rng = rand::rng();
+
+        for _ in 0..50 {
+            assert!(nfold_way.step(&mut lattice, &mut rng));
+        }
+        assert!(nfold_way.time > 0.0);
+        assert_classes(&nfold_way, &lattice);
+
+        nfold_way.montecarlo_sweep(&mut lattice, &mut rng);
+        assert_classes(&nfold_way, &lattice);
+    }
+
+    #[test]
+    fn test_nfold_way_frozen() {
+        // Zero temperature ground state: no site can flip, time still advances
+        let settings = SettingsBuilder::new().add_beta(f64::INFINITY).build();
+        let mut lattice = Lattice::new(settings).unwrap();
+        let mut nfold_way = NFoldWay::new(&lattice);
+        let mut rng = rand::rng();
+        assert!(!nfold_way.step(&mut lattice, &mut rng));
+        nfold_way.advance(&mut lattice, 2.5, &mut rng);
+        assert_eq!(nfold_way.time, 2.5);
+    }
+}
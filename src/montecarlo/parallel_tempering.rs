@@ -0,0 +1,328 @@
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::montecarlo::random::random_stream;
+use crate::settings::Settings;
+use rand::Rng;
+use rayon::prelude::*;
+use std::io;
+
+// Label of a replica for round-trip bookkeeping: the extreme of the ladder it
+// visited most recently
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Direction {
+    None,
+    // Last visited the smallest beta (hottest temperature)
+    Up,
+    // Last visited the largest beta (coldest temperature)
+    Down,
+}
+
+pub struct ParallelTempering {
+    pub replicas: Vec<Lattice>,
+    // Ladder of inverse temperatures, in ascending order
+    pub betas: Vec<f64>,
+    // Index of the replica currently simulated at each beta of the ladder
+    replica_at: Vec<usize>,
+    directions: Vec<Direction>,
+    // Swap attempts and acceptances between beta i and beta i + 1
+    attempts: Vec<u64>,
+    accepted: Vec<u64>,
+    // Round-trip bookkeeping per replica
+    trip_start: Vec<Option<u64>>,
+    round_trips: Vec<u64>,
+    // Visits at each beta by replicas labelled Up or Down, for feedback optimisation
+    visits_up: Vec<u64>,
+    visits_down: Vec<u64>,
+    seed: u64,
+    sweeps: u64,
+}
+
+impl ParallelTempering {
+    pub fn new(settings: Settings, betas: &[f64]) -> io::Result<Self> {
+        Self::with_seed(settings, betas, rand::random())
+    }
+
+    // Replicas and swaps all determined by the seed: every replica gets its own seed
+    // drawn from it, and the swaps of a sweep draw from their own stream
+    pub fn with_seed(settings: Settings, betas: &[f64], seed: u64) -> io::Result<Self> {
+        assert!(
+            betas.len() >= 2,
+            "Parallel tempering needs at least two betas"
+        );
+        let mut betas = betas.to_vec();
+        betas.sort_by(|a, b| a.total_cmp(b));
+
+        let replicas = betas
+            .iter()
+            .enumerate()
+            .map(|(i, beta)| {
+                let mut settings = settings.clone();
+                settings.beta = *beta;
+                Lattice::with_seed(settings, random_stream(seed, u64::MAX, i as u64).random())
+            })
+            .collect::<io::Result<_>>()?;
+
+        let n = betas.len();
+        Ok(Self {
+            replicas,
+            betas,
+            replica_at: (0..n).collect(),
+            directions: vec![Direction::None; n],
+            attempts: vec![0; n - 1],
+            accepted: vec![0; n - 1],
+            trip_start: vec![None; n],
+            round_trips: Vec::new(),
+            visits_up: vec![0; n],
+            visits_down: vec![0; n],
+            seed,
+            sweeps: 0,
+        })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Lattice currently simulated at the i-th beta of the ladder
+    pub fn replica_at(&self, i: usize) -> &Lattice {
+        &self.replicas[self.replica_at[i]]
+    }
+
+    pub fn get_energies(&self) -> Vec<f64> {
+        (0..self.betas.len())
+            .map(|i| self.replica_at(i).get_energy())
+            .collect()
+    }
+
+    pub fn montecarlo_sweep(&mut self) {
+        // Sweep all replicas concurrently
+        self.replicas.par_iter_mut().for_each(|replica| {
+            replica.montecarlo_sweep();
+        });
+        self.sweeps += 1;
+
+        // Exchange replicas between neighbouring betas, alternating even and odd pairs
+        let mut rng = random_stream(self.seed, self.sweeps, u64::MAX);
+        self.attempt_swaps((self.sweeps % 2) as usize, &mut rng);
+        self.update_round_trips();
+    }
+
+    pub fn attempt_swaps<R: Rng>(&mut self, first: usize, rng: &mut R) {
+        let energies: Vec<f64> = self
+            .replicas
+            .par_iter()
+            .map(|replica| replica.get_energy())
+            .collect();
+
+        for i in (first..self.betas.len() - 1).step_by(2) {
+            let (a, b) = (self.replica_at[i], self.replica_at[i + 1]);
+            self.attempts[i] += 1;
+
+            // Accept with min(1, exp[(beta_i - beta_j) (E_i - E_j)])
+            let delta = (self.betas[i] - self.betas[i + 1]) * (energies[a] - energies[b]);
+            if delta >= 0.0 || rng.random_range(0.0..1.0) < delta.exp() {
+                self.accepted[i] += 1;
+                self.replica_at.swap(i, i + 1);
+                self.replicas[a].set_beta(self.betas[i + 1]);
+                self.replicas[b].set_beta(self.betas[i]);
+            }
+        }
+    }
+
+    fn update_round_trips(&mut self) {
+        let last = self.betas.len() - 1;
+
+        // Hottest end: a replica coming back from the coldest end completes a trip
+        let hottest = self.replica_at[0];
+        if self.directions[hottest] == Direction::Down
+            && let Some(start) = self.trip_start[hottest]
+        {
+            self.round_trips.push(self.sweeps - start);
+        }
+        if self.directions[hottest] != Direction::Up {
+            self.trip_start[hottest] = Some(self.sweeps);
+        }
+        self.directions[hottest] = Direction::Up;
+
+        // Coldest end
+        let coldest = self.replica_at[last];
+        self.directions[coldest] = Direction::Down;
+
+        // Histograms of the labels at every beta
+        for i in 0..=last {
+            match self.directions[self.replica_at[i]] {
+                Direction::Up => self.visits_up[i] += 1,
+                Direction::Down => self.visits_down[i] += 1,
+                Direction::None => {}
+            }
+        }
+    }
+
+    pub fn swap_acceptance_rates(&self) -> Vec<f64> {
+        self.attempts
+            .iter()
+            .zip(self.accepted.iter())
+            .map(|(attempts, accepted)| match attempts {
+                0 => 0.0,
+                _ => *accepted as f64 / *attempts as f64,
+            })
+            .collect()
+    }
+
+    pub fn round_trips(&self) -> &[u64] {
+        &self.round_trips
+    }
+
+    pub fn mean_round_trip_time(&self) -> Option<f64> {
+        if self.round_trips.is_empty() {
+            return None;
+        }
+        Some(self.round_trips.iter().sum::<u64>() as f64 / self.round_trips.len() as f64)
+    }
+
+    // Fraction of the visits at each beta made by replicas last seen at the hottest end
+    pub fn up_fractions(&self) -> Vec<f64> {
+        self.visits_up
+            .iter()
+            .zip(self.visits_down.iter())
+            .map(|(up, down)| match up + down {
+                0 => 0.0,
+                total => *up as f64 / total as f64,
+            })
+            .collect()
+    }
+
+    // Feedback optimisation (Katzgraber, Trebst, Huse and Troyer): redistribute the
+    // temperatures so that the up fraction decreases linearly along the ladder.
+    // The end points are kept fixed and the statistics are reset.
+    pub fn optimise_betas(&mut self) {
+        assert!(
+            self.betas[0] > 0.0,
+            "Feedback optimisation needs finite temperatures"
+        );
+        let n = self.betas.len();
+        let fractions = self.up_fractions();
+
+        // Work in temperatures, hottest first
+        let temperatures: Vec<f64> = self.betas.iter().map(|beta| 1.0 / beta).collect();
+
+        // Density of temperatures on each interval: sqrt(df/dT / dT)
+        let densities: Vec<f64> = (0..n - 1)
+            .map(|i| {
+                let width = (temperatures[i] - temperatures[i + 1]).abs();
+                let slope = (fractions[i] - fractions[i + 1]).max(1e-6) / width;
+                (slope / width).sqrt()
+            })
+            .collect();
+        let weights: Vec<f64> = (0..n - 1)
+            .map(|i| densities[i] * (temperatures[i] - temperatures[i + 1]).abs())
+            .collect();
+        let total: f64 = weights.iter().sum();
+
+        // Place the new temperatures at equal fractions of the integrated density
+        let mut new_temperatures = vec![temperatures[0]];
+        let mut interval = 0;
+        let mut integrated = 0.0;
+        for k in 1..n - 1 {
+            let target = total * k as f64 / (n - 1) as f64;
+            while integrated + weights[interval] < target {
+                integrated += weights[interval];
+                interval += 1;
+            }
+            let fraction = (target - integrated) / weights[interval];
+            new_temperatures.push(
+                temperatures[interval]
+                    + fraction * (temperatures[interval + 1] - temperatures[interval]),
+            );
+        }
+        new_temperatures.push(temperatures[n - 1]);
+
+        self.betas = new_temperatures.iter().map(|t| 1.0 / t).collect();
+        for (i, replica) in self.replica_at.iter().enumerate() {
+            self.replicas[*replica].set_beta(self.betas[i]);
+        }
+
+        self.attempts.iter_mut().for_each(|x| *x = 0);
+        self.accepted.iter_mut().for_each(|x| *x = 0);
+        self.visits_up.iter_mut().for_each(|x| *x = 0);
+        self.visits_down.iter_mut().for_each(|x| *x = 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingsBuilder;
+
+    #[test]
+    fn test_parallel_tempering_new() {
+        let settings = SettingsBuilder::new().build();
+        let parallel_tempering = ParallelTempering::new(settings, &[0.5, 0.1, 0.3]).unwrap();
+        assert_eq!(parallel_tempering.betas, vec![0.1, 0.3, 0.5]);
+        for i in 0..3 {
+            assert_eq!(
+                parallel_tempering.replica_at(i).settings.beta,
+                parallel_tempering.betas[i]
+            );
+        }
+    }
+
+    #[test]
+    fn test_parallel_tempering_swaps() {
+        // Equal betas: every swap is accepted
+        let settings = SettingsBuilder::new().build();
+        let mut parallel_tempering = ParallelTempering::new(settings, &[0.2, 0.2, 0.2]).unwrap();
+        let mut rng = rand::rng();
+        parallel_tempering.attempt_swaps(0, &mut rng);
+        parallel_tempering.attempt_swaps(1, &mut rng);
+        assert_eq!(parallel_tempering.swap_acceptance_rates(), vec![1.0, 1.0]);
+        assert_eq!(parallel_tempering.replica_at, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn test_parallel_tempering_with_seed() {
+        let run = || {
+            let settings = SettingsBuilder::new().build();
+            let mut parallel_tempering =
+                ParallelTempering::with_seed(settings, &[0.2, 0.3, 0.4], 7).unwrap();
+            for _ in 0..10 {
+                parallel_tempering.montecarlo_sweep();
+            }
+            (
+                parallel_tempering.get_energies(),
+                parallel_tempering.replica_at,
+            )
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_parallel_tempering_round_trips() {
+        let settings = SettingsBuilder::new().build();
+        let mut parallel_tempering = ParallelTempering::new(settings, &[0.2, 0.2]).unwrap();
+        for _ in 0..4 {
+            parallel_tempering.montecarlo_sweep();
+        }
+
+        // Replica 0 is labelled at the hottest end after sweep 1, reaches the coldest
+        // end at sweep 2 and is back at the hottest end at sweep 4
+        assert_eq!(parallel_tempering.round_trips(), &[3]);
+        assert_eq!(parallel_tempering.mean_round_trip_time(), Some(3.0));
+    }
+
+    #[test]
+    fn test_parallel_tempering_optimise_betas() {
+        let settings = SettingsBuilder::new().build();
+        let mut parallel_tempering =
+            ParallelTempering::new(settings, &[0.1, 0.2, 0.3, 0.4]).unwrap();
+        for _ in 0..10 {
+            parallel_tempering.montecarlo_sweep();
+        }
+        parallel_tempering.optimise_betas();
+
+        let betas = &parallel_tempering.betas;
+        assert!((betas[0] - 0.1).abs() < 1e-12);
+        assert!((betas[3] - 0.4).abs() < 1e-12);
+        assert!(betas.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!((0..4).all(|i| parallel_tempering.replica_at(i).settings.beta == betas[i]));
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum RampKind {
+    Constant,
+    Linear,
+    Geometric,
+}
+
+// Value of a parameter over the sweeps of a run
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Ramp {
+    pub kind: RampKind,
+    pub from: f64,
+    pub to: f64,
+}
+
+impl Ramp {
+    pub fn new(kind: RampKind, from: f64, to: f64) -> Self {
+        if kind == RampKind::Geometric {
+            assert!(
+                from * to > 0.0,
+                "Geometric ramps need nonzero end points of the same sign"
+            );
+        }
+        Self { kind, from, to }
+    }
+
+    pub fn constant(value: f64) -> Self {
+        Self::new(RampKind::Constant, value, value)
+    }
+
+    // Value at a fraction of the run between 0 and 1
+    pub fn at(&self, fraction: f64) -> f64 {
+        match self.kind {
+            RampKind::Constant => self.from,
+            RampKind::Linear => self.from + fraction * (self.to - self.from),
+            RampKind::Geometric => self.from * (self.to / self.from).powf(fraction),
+        }
+    }
+}
+
+// Row of a schedule table: beta and magnetic field from the given sweep on
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ScheduleEntry {
+    pub sweep: u32,
+    pub beta: f64,
+    pub magnetic_field: f64,
+}
+
+// Beta and magnetic field applied sweep by sweep
+#[derive(Debug, PartialEq, Clone)]
+pub enum Schedule {
+    Ramps { beta: Ramp, magnetic_field: Ramp },
+    // Linearly interpolated between the entries, constant outside of them
+    Table(Vec<ScheduleEntry>),
+}
+
+impl Schedule {
+    pub fn constant(beta: f64, magnetic_field: f64) -> Self {
+        Schedule::Ramps {
+            beta: Ramp::constant(beta),
+            magnetic_field: Ramp::constant(magnetic_field),
+        }
+    }
+
+    // Parse a table with one "sweep beta h" row per line, separated by whitespace or
+    // commas. Empty lines and lines starting with '#' are skipped.
+    pub fn from_table(table: &str) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid schedule entry: {}", line),
+            )
+        };
+
+        let mut entries = Vec::new();
+        for line in table.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .collect();
+            let [sweep, beta, magnetic_field] = values[..] else {
+                return Err(invalid(line));
+            };
+            entries.push(ScheduleEntry {
+                sweep: sweep.parse().map_err(|_| invalid(line))?,
+                beta: beta.parse().map_err(|_| invalid(line))?,
+                magnetic_field: magnetic_field.parse().map_err(|_| invalid(line))?,
+            });
+        }
+
+        if entries.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Empty schedule table",
+            ));
+        }
+        entries.sort_by_key(|entry| entry.sweep);
+        Ok(Schedule::Table(entries))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_table(&fs::read_to_string(path)?)
+    }
+
+    // Beta and magnetic field at a sweep out of the total number of sweeps
+    pub fn at(&self, sweep: u32, sweeps: u32) -> (f64, f64) {
+        match self {
+            Schedule::Ramps {
+                beta,
+                magnetic_field,
+            } => {
+                let fraction = match sweeps {
+                    0 | 1 => 0.0,
+                    _ => sweep as f64 / (sweeps - 1) as f64,
+                };
+                (beta.at(fraction), magnetic_field.at(fraction))
+            }
+            Schedule::Table(entries) => {
+                let next = entries.partition_point(|entry| entry.sweep <= sweep);
+                if next == 0 {
+                    return (entries[0].beta, entries[0].magnetic_field);
+                }
+                if next == entries.len() {
+                    let last = entries[next - 1];
+                    return (last.beta, last.magnetic_field);
+                }
+
+                let (a, b) = (entries[next - 1], entries[next]);
+                let fraction = (sweep - a.sweep) as f64 / (b.sweep - a.sweep) as f64;
+                (
+                    a.beta + fraction * (b.beta - a.beta),
+                    a.magnetic_field + fraction * (b.magnetic_field - a.magnetic_field),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramp() {
+        let ramp = Ramp::new(RampKind::Linear, 0.1, 0.5);
+        assert_eq!(ramp.at(0.0), 0.1);
+        assert!((ramp.at(0.5) - 0.3).abs() < 1e-12);
+        assert_eq!(ramp.at(1.0), 0.5);
+
+        let ramp = Ramp::new(RampKind::Geometric, 0.1, 10.0);
+        assert!((ramp.at(0.5) - 1.0).abs() < 1e-12);
+        assert!((ramp.at(1.0) - 10.0).abs() < 1e-12);
+
+        assert_eq!(Ramp::constant(2.0).at(0.7), 2.0);
+    }
+
+    #[test]
+    fn test_schedule_ramps() {
+        let schedule = Schedule::Ramps {
+            beta: Ramp::new(RampKind::Linear, 0.0, 1.0),
+            magnetic_field: Ramp::new(RampKind::Linear, 1.0, -1.0),
+        };
+        assert_eq!(schedule.at(0, 11), (0.0, 1.0));
+        assert_eq!(schedule.at(5, 11), (0.5, 0.0));
+        assert_eq!(schedule.at(10, 11), (1.0, -1.0));
+        assert_eq!(Schedule::constant(0.4, 0.1).at(7, 11), (0.4, 0.1));
+    }
+
+    #[test]
+    fn test_schedule_table() {
+        let table = "# sweep beta h\n10 0.2 0.0\n0, 0.1, 1.0\n\n20 0.2 -1.0\n";
+        let schedule = Schedule::from_table(table).unwrap();
+        assert_eq!(schedule.at(0, 30), (0.1, 1.0));
+        assert_eq!(schedule.at(5, 30), (0.15000000000000002, 0.5));
+        assert_eq!(schedule.at(15, 30), (0.2, -0.5));
+        assert_eq!(schedule.at(25, 30), (0.2, -1.0));
+
+        assert!(Schedule::from_table("0 0.1").is_err());
+        assert!(Schedule::from_table("0 beta 0.1").is_err());
+        assert!(Schedule::from_table("# empty").is_err());
+    }
+}
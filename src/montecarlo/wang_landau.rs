@@ -0,0 +1,262 @@
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::settings::Settings;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::io;
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum ModificationSchedule {
+    // Halve ln f every time the histogram is flat
+    Halving,
+    // Halving until ln f reaches 1/t, then ln f = 1/t (Belardinelli-Pereyra)
+    InverseTime,
+}
+
+// Canonical averages at a single beta, derived from the density of states
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CanonicalAverages {
+    pub beta: f64,
+    pub energy: f64,
+    pub specific_heat: f64,
+    pub free_energy: f64,
+    pub entropy: f64,
+}
+
+// ln g(E) on the visited energy levels, in ascending order of energy
+#[derive(Debug, Clone)]
+pub struct DensityOfStates {
+    pub levels: Vec<(f64, f64)>,
+    pub sites: usize,
+}
+
+impl DensityOfStates {
+    // Normalise so that the total number of states is 2^N
+    pub fn normalise(&mut self) {
+        let ln_total = log_sum_exp(self.levels.iter().map(|(_, ln_g)| *ln_g));
+        let shift = self.sites as f64 * 2.0_f64.ln() - ln_total;
+        for (_, ln_g) in self.levels.iter_mut() {
+            *ln_g += shift;
+        }
+    }
+
+    pub fn canonical(&self, beta: f64) -> CanonicalAverages {
+        // Boltzmann weights in log space: ln g(E) - beta E
+        let ln_weights: Vec<f64> = self
+            .levels
+            .iter()
+            .map(|(energy, ln_g)| ln_g - beta * energy)
+            .collect();
+        let ln_z = log_sum_exp(ln_weights.iter().copied());
+
+        let mut energy = 0.0;
+        let mut energy_squared = 0.0;
+        for ((level, _), ln_weight) in self.levels.iter().zip(ln_weights.iter()) {
+            let probability = (ln_weight - ln_z).exp();
+            energy += probability * level;
+            energy_squared += probability * level * level;
+        }
+
+        let free_energy = -ln_z / beta;
+        CanonicalAverages {
+            beta,
+            energy,
+            specific_heat: beta * beta * (energy_squared - energy * energy) / self.sites as f64,
+            free_energy,
+            entropy: beta * (energy - free_energy),
+        }
+    }
+}
+
+pub fn log_sum_exp(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+    max + values.map(|value| (value - max).exp()).sum::<f64>().ln()
+}
+
+pub struct WangLandau {
+    pub lattice: Lattice,
+    // Width of the energy bins
+    pub resolution: f64,
+    pub schedule: ModificationSchedule,
+    // Minimum ratio between the smallest and the mean histogram entry
+    pub flatness: f64,
+    pub ln_f: f64,
+    pub ln_f_final: f64,
+    ln_g: BTreeMap<i64, f64>,
+    histogram: BTreeMap<i64, u64>,
+    energy: f64,
+    attempts: u64,
+    inverse_time: bool,
+}
+
+impl WangLandau {
+    pub fn new(
+        settings: Settings,
+        resolution: f64,
+        schedule: ModificationSchedule,
+    ) -> io::Result<Self> {
+        let lattice = Lattice::new(settings)?;
+        let energy = lattice.get_energy();
+        Ok(Self {
+            lattice,
+            resolution,
+            schedule,
+            flatness: 0.8,
+            ln_f: 1.0,
+            ln_f_final: 1e-8,
+            ln_g: BTreeMap::new(),
+            histogram: BTreeMap::new(),
+            energy,
+            attempts: 0,
+            inverse_time: false,
+        })
+    }
+
+    fn bin(&self, energy: f64) -> i64 {
+        (energy / self.resolution).round() as i64
+    }
+
+    // ln g of a bin, levels never visited start from the smallest known value
+    fn ln_g(&self, bin: i64) -> f64 {
+        match self.ln_g.get(&bin) {
+            Some(ln_g) => *ln_g,
+            None => self
+                .ln_g
+                .values()
+                .copied()
+                .fold(f64::INFINITY, f64::min)
+                .min(0.0),
+        }
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.ln_f < self.ln_f_final
+    }
+
+    pub fn is_flat(&self) -> bool {
+        if self.histogram.is_empty() {
+            return false;
+        }
+        let min = *self.histogram.values().min().unwrap() as f64;
+        let mean = self.histogram.values().sum::<u64>() as f64 / self.histogram.len() as f64;
+        min >= self.flatness * mean
+    }
+
+    // Monte Carlo time of the 1/t schedule: trial moves per energy level
+    fn time(&self) -> f64 {
+        self.attempts as f64 / self.ln_g.len().max(1) as f64
+    }
+
+    // One attempted flip per site, accepted with min(1, g(E) / g(E'))
+    pub fn montecarlo_sweep<R: Rng>(&mut self, rng: &mut R) {
+        for _ in 0..self.lattice.len() {
+            let position = rng.random_range(0..self.lattice.len());
+            let new_energy = self.energy + self.lattice.flip_energy_change(position);
+
+            let ln_ratio = self.ln_g(self.bin(self.energy)) - self.ln_g(self.bin(new_energy));
+            if ln_ratio >= 0.0 || rng.random_range(0.0..1.0) < ln_ratio.exp() {
+                self.lattice.flip(position);
+                self.energy = new_energy;
+            }
+
+            // Update the density of states and the histogram at the current level
+            let bin = self.bin(self.energy);
+            let ln_g = self.ln_g(bin);
+            self.ln_g.insert(bin, ln_g + self.ln_f);
+            *self.histogram.entry(bin).or_insert(0) += 1;
+            self.attempts += 1;
+        }
+
+        // Avoid the accumulation of rounding errors
+        self.lattice.refresh_totals();
+        self.energy = self.lattice.get_energy();
+
+        if self.inverse_time {
+            self.ln_f = 1.0 / self.time();
+        }
+    }
+
+    // Reduce the modification factor once the histogram is flat
+    pub fn update_modification_factor(&mut self) {
+        if self.inverse_time || !self.is_flat() {
+            return;
+        }
+
+        self.ln_f /= 2.0;
+        self.histogram.clear();
+
+        if self.schedule == ModificationSchedule::InverseTime && self.ln_f <= 1.0 / self.time() {
+            self.inverse_time = true;
+            self.ln_f = 1.0 / self.time();
+        }
+    }
+
+    // Sweep until ln f drops below ln_f_final, checking flatness every `interval` sweeps
+    pub fn run<R: Rng>(&mut self, interval: u32, rng: &mut R) {
+        while !self.is_converged() {
+            for _ in 0..interval {
+                self.montecarlo_sweep(rng);
+            }
+            self.update_modification_factor();
+        }
+    }
+
+    pub fn density_of_states(&self) -> DensityOfStates {
+        let mut density_of_states = DensityOfStates {
+            levels: self
+                .ln_g
+                .iter()
+                .map(|(bin, ln_g)| (*bin as f64 * self.resolution, *ln_g))
+                .collect(),
+            sites: self.lattice.len(),
+        };
+        density_of_states.normalise();
+        density_of_states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingsBuilder;
+
+    #[test]
+    fn test_log_sum_exp() {
+        let values = [1000.0, 1000.0];
+        assert!((log_sum_exp(values.iter().copied()) - (1000.0 + 2.0_f64.ln())).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_density_of_states_canonical() {
+        // Two-level system with g(-1) = g(1) = 1
+        let mut density_of_states = DensityOfStates {
+            levels: vec![(-1.0, 5.0), (1.0, 5.0)],
+            sites: 1,
+        };
+        density_of_states.normalise();
+        assert!((density_of_states.levels[0].1).abs() < 1e-12);
+
+        let canonical = density_of_states.canonical(0.5);
+        assert!((canonical.energy + 0.5_f64.tanh()).abs() < 1e-12);
+        assert!((canonical.specific_heat - 0.25 / 0.5_f64.cosh().powi(2)).abs() < 1e-12);
+        assert!((canonical.free_energy + 2.0 * (2.0 * 0.5_f64.cosh()).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_wang_landau_sweep() {
+        let settings = SettingsBuilder::new().build();
+        let mut wang_landau =
+            WangLandau::new(settings, 1.0, ModificationSchedule::InverseTime).unwrap();
+        let mut rng = rand::rng();
+        for _ in 0..20 {
+            wang_landau.montecarlo_sweep(&mut rng);
+        }
+        assert!(wang_landau.ln_g.len() > 1);
+        assert!((wang_landau.energy - wang_landau.lattice.get_energy()).abs() < 1e-9);
+
+        // Flat histogram: ln f is halved and the histogram reset
+        wang_landau.flatness = 0.0;
+        wang_landau.update_modification_factor();
+        assert_eq!(wang_landau.ln_f, 0.5);
+        assert!(wang_landau.histogram.is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::output::gif::GifWriter;
+use crate::output::image::{Colour, ColourMap, Image, ImageFormat, Slice};
+use crate::settings::LATTICE_SIZE;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum AnimationFormat {
+    // Single animated GIF file
+    Gif,
+    // Directory of numbered PPM frames
+    Ppm,
+}
+
+// Field drawn in every frame
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum FrameField {
+    // Spin configuration at the frame, in the up and down colours
+    Spins,
+    // Mean spin of every site over the sweeps since the last frame, on the colour map
+    Average,
+}
+
+// Appearance of the frames
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FrameStyle {
+    pub slice: Slice,
+    pub scale: usize,
+    pub colours: [Colour; 2],
+    pub colour_map: ColourMap,
+}
+
+enum Frames {
+    Gif(GifWriter<BufWriter<File>>),
+    Ppm(PathBuf),
+}
+
+// Records a frame every `stride` sweeps of the evolution of a lattice
+pub struct Animation {
+    frames: Frames,
+    field: FrameField,
+    stride: u32,
+    style: FrameStyle,
+    // Spin sums of the sweeps since the last frame
+    sums: Vec<f64>,
+    sweeps: u32,
+    count: usize,
+}
+
+impl Animation {
+    // A GIF at the path with the given frame delay in hundredths of a second, or a
+    // directory of frames
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: AnimationFormat,
+        field: FrameField,
+        stride: u32,
+        delay: u16,
+        style: FrameStyle,
+    ) -> io::Result<Self> {
+        assert!(stride > 0, "The frame stride must be positive");
+        let size = LATTICE_SIZE * style.scale;
+        let frames = match format {
+            AnimationFormat::Gif => Frames::Gif(GifWriter::create(path, size, size, delay)?),
+            AnimationFormat::Ppm => {
+                fs::create_dir_all(&path)?;
+                Frames::Ppm(path.as_ref().to_path_buf())
+            }
+        };
+        Ok(Self {
+            frames,
+            field,
+            stride,
+            style,
+            sums: Vec::new(),
+            sweeps: 0,
+            count: 0,
+        })
+    }
+
+    // Called after every sweep, writes a frame at the end of each stride
+    pub fn record(&mut self, lattice: &Lattice) -> io::Result<()> {
+        if self.field == FrameField::Average {
+            self.sums.resize(lattice.len(), 0.0);
+            for (sum, field) in self.sums.iter_mut().zip(lattice.get_fields()) {
+                *sum += field.value();
+            }
+        }
+        self.sweeps += 1;
+        if self.sweeps < self.stride {
+            return Ok(());
+        }
+
+        let style = &self.style;
+        let image = match self.field {
+            FrameField::Spins => {
+                Image::from_lattice(lattice, &style.slice, style.scale, style.colours)
+            }
+            FrameField::Average => {
+                let means: Vec<f64> = self
+                    .sums
+                    .iter()
+                    .map(|sum| sum / self.sweeps as f64)
+                    .collect();
+                Image::from_values(
+                    &means,
+                    &style.slice,
+                    style.scale,
+                    (-1.0, 1.0),
+                    style.colour_map,
+                )
+            }
+        };
+        match &mut self.frames {
+            Frames::Gif(gif) => gif.add_frame(&image)?,
+            Frames::Ppm(directory) => {
+                let path = directory.join(format!("frame_{:06}.ppm", self.count));
+                image.save(path, ImageFormat::Ppm)?;
+            }
+        }
+        self.sums.fill(0.0);
+        self.sweeps = 0;
+        self.count += 1;
+        Ok(())
+    }
+
+    // Number of frames written
+    pub fn frames(&self) -> usize {
+        self.count
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self.frames {
+            Frames::Gif(gif) => gif.finish().map(|_| ()),
+            Frames::Ppm(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingsBuilder;
+
+    #[test]
+    fn test_animation() {
+        let directory = std::env::temp_dir().join(format!("animation-{}", std::process::id()));
+        let style = FrameStyle {
+            slice: Slice::new(Some(2), 0),
+            scale: 1,
+            colours: [Colour([255; 3]), Colour([0; 3])],
+            colour_map: ColourMap::Grey,
+        };
+        let mut lattice = Lattice::new(SettingsBuilder::new().add_beta(0.0).build()).unwrap();
+        let mut animation = Animation::create(
+            &directory,
+            AnimationFormat::Ppm,
+            FrameField::Average,
+            2,
+            0,
+            style,
+        )
+        .unwrap();
+        for _ in 0..5 {
+            lattice.montecarlo_sweep();
+            animation.record(&lattice).unwrap();
+        }
+        assert_eq!(animation.frames(), 2);
+        animation.finish().unwrap();
+        let frame = fs::read(directory.join("frame_000001.ppm")).unwrap();
+        assert!(frame.starts_with(b"P6\n4 4\n255\n"));
+        // Means over two sweeps are -1, 0 or 1
+        assert!(frame[11..].iter().all(|c| [0, 128, 255].contains(c)));
+        assert!(!directory.join("frame_000002.ppm").exists());
+        fs::remove_dir_all(&directory).unwrap();
+
+        let path = std::env::temp_dir().join(format!("animation-{}.gif", std::process::id()));
+        let mut animation =
+            Animation::create(&path, AnimationFormat::Gif, FrameField::Spins, 1, 5, style).unwrap();
+        animation.record(&lattice).unwrap();
+        animation.finish().unwrap();
+        let gif = fs::read(&path).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+        assert_eq!(gif.last(), Some(&0x3b));
+        fs::remove_file(&path).unwrap();
+    }
+}
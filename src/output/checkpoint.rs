@@ -0,0 +1,541 @@
+use crate::field::initialisation::Initialisation;
+use crate::field::ising::IsingField;
+use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
+use crate::geometry::lattice_geometry::interactions::Interactions;
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::geometry::lattice_geometry::long_range::{LongRange, LongRangeSum};
+use crate::montecarlo::nfold_way::NFoldWayState;
+use crate::settings::{DIMENSIONS, LATTICE_SIZE, Settings};
+use crate::statistics::correlations::Correlations;
+use crate::statistics::percolation::Percolation;
+use crate::statistics::structure_factor::StructureFactor;
+use crate::statistics::time_series::TimeSeries;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"ISINGCKP";
+// Bumped whenever the layout changes, older files are refused
+pub const CHECKPOINT_VERSION: u32 = 2;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Little-endian encoder of the checkpoint contents
+#[derive(Debug, Default)]
+pub struct CheckpointWriter {
+    bytes: Vec<u8>,
+}
+
+impl CheckpointWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f64s(&mut self, values: &[f64]) {
+        self.u64(values.len() as u64);
+        values.iter().for_each(|value| self.f64(*value));
+    }
+
+    pub fn usizes(&mut self, values: &[usize]) {
+        self.u64(values.len() as u64);
+        values.iter().for_each(|value| self.u64(*value as u64));
+    }
+
+    pub fn text(&mut self, value: &str) {
+        self.u64(value.len() as u64);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn option<T: Checkpoint>(&mut self, value: Option<&T>) {
+        self.u8(value.is_some() as u8);
+        if let Some(value) = value {
+            value.save(self);
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+// Decoder matching CheckpointWriter, failing on truncated or malformed data
+pub struct CheckpointReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> CheckpointReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Truncated checkpoint",
+            ));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // Length prefix of a sequence, checked against the bytes left
+    fn length(&mut self, element_size: usize) -> io::Result<usize> {
+        let length = self.u64()? as usize;
+        if length.saturating_mul(element_size) > self.bytes.len() {
+            return Err(invalid("Invalid sequence length in checkpoint"));
+        }
+        Ok(length)
+    }
+
+    pub fn f64s(&mut self) -> io::Result<Vec<f64>> {
+        let length = self.length(8)?;
+        (0..length).map(|_| self.f64()).collect()
+    }
+
+    pub fn usizes(&mut self) -> io::Result<Vec<usize>> {
+        let length = self.length(8)?;
+        (0..length).map(|_| Ok(self.u64()? as usize)).collect()
+    }
+
+    pub fn text(&mut self) -> io::Result<String> {
+        let length = self.length(1)?;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| invalid("Invalid text in checkpoint"))
+    }
+
+    pub fn option<T: Checkpoint>(&mut self) -> io::Result<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::load(self)?)),
+            _ => Err(invalid("Invalid optional entry in checkpoint")),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+// State that can be written to a checkpoint and read back identically
+pub trait Checkpoint: Sized {
+    fn save(&self, writer: &mut CheckpointWriter);
+    fn load(reader: &mut CheckpointReader) -> io::Result<Self>;
+}
+
+impl Checkpoint for Settings {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.u64(self.dimensions as u64);
+        writer.u64(self.lattice_size as u64);
+        writer.f64(self.beta);
+        writer.u8(match self.boundary_conditions {
+            BoundaryConditions::Periodic => 0,
+            BoundaryConditions::Open => 1,
+            BoundaryConditions::Antiperiodic => 2,
+            BoundaryConditions::Fixed => 3,
+        });
+        writer.u64(self.boundary_axis as u64);
+        match &self.site_initialisation {
+            Initialisation::Random => writer.u8(0),
+            Initialisation::Uniform => writer.u8(1),
+            Initialisation::Checkerboard => writer.u8(2),
+            Initialisation::Stripes(width) => {
+                writer.u8(3);
+                writer.u64(*width as u64);
+            }
+            Initialisation::Droplet(radius) => {
+                writer.u8(4);
+                writer.f64(*radius);
+            }
+            Initialisation::FixedMagnetization(magnetization) => {
+                writer.u8(5);
+                writer.f64(*magnetization);
+            }
+            Initialisation::FromFile(path) => {
+                writer.u8(6);
+                writer.text(&path.to_string_lossy());
+            }
+        }
+        let interactions = &self.interactions;
+        writer.f64(interactions.diagonal);
+        writer.f64(interactions.axial);
+        writer.u8(interactions.axial_dimension.is_some() as u8);
+        writer.u64(interactions.axial_dimension.unwrap_or(0) as u64);
+        writer.u8(interactions.long_range.is_some() as u8);
+        if let Some(long_range) = &interactions.long_range {
+            writer.f64(long_range.sigma);
+            writer.f64(long_range.coupling);
+            writer.u8(match long_range.sum {
+                LongRangeSum::MinimumImage => 0,
+                LongRangeSum::Images => 1,
+            });
+        }
+        writer.f64(self.magnetic_field);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> io::Result<Self> {
+        let (dimensions, lattice_size) = (reader.u64()? as usize, reader.u64()? as usize);
+        // The geometry is fixed at compile time
+        if (dimensions, lattice_size) != (DIMENSIONS, LATTICE_SIZE) {
+            return Err(invalid(&format!(
+                "Checkpoint of a {}-dimensional lattice of size {}, expected {} and {}",
+                dimensions, lattice_size, DIMENSIONS, LATTICE_SIZE
+            )));
+        }
+        let beta = reader.f64()?;
+        let boundary_conditions = match reader.u8()? {
+            0 => BoundaryConditions::Periodic,
+            1 => BoundaryConditions::Open,
+            2 => BoundaryConditions::Antiperiodic,
+            3 => BoundaryConditions::Fixed,
+            _ => return Err(invalid("Invalid boundary conditions in checkpoint")),
+        };
+        let boundary_axis = reader.u64()? as usize;
+        if boundary_axis >= DIMENSIONS {
+            return Err(invalid("Invalid boundary axis in checkpoint"));
+        }
+        let site_initialisation = match reader.u8()? {
+            0 => Initialisation::Random,
+            1 => Initialisation::Uniform,
+            2 => Initialisation::Checkerboard,
+            3 => Initialisation::Stripes(reader.u64()? as usize),
+            4 => Initialisation::Droplet(reader.f64()?),
+            5 => Initialisation::FixedMagnetization(reader.f64()?),
+            6 => Initialisation::FromFile(reader.text()?.into()),
+            _ => return Err(invalid("Invalid initialisation in checkpoint")),
+        };
+        let diagonal = reader.f64()?;
+        let axial = reader.f64()?;
+        let axial_dimension = match (reader.u8()?, reader.u64()? as usize) {
+            (0, _) => None,
+            (_, dimension) => Some(dimension),
+        };
+        let long_range = match reader.u8()? {
+            0 => None,
+            _ => Some(LongRange {
+                sigma: reader.f64()?,
+                coupling: reader.f64()?,
+                sum: match reader.u8()? {
+                    0 => LongRangeSum::MinimumImage,
+                    1 => LongRangeSum::Images,
+                    _ => return Err(invalid("Invalid long-range summation in checkpoint")),
+                },
+            }),
+        };
+        Ok(Settings {
+            dimensions,
+            lattice_size,
+            beta,
+            boundary_conditions,
+            boundary_axis,
+            site_initialisation,
+            interactions: Interactions {
+                diagonal,
+                axial,
+                axial_dimension,
+                long_range,
+            },
+            magnetic_field: reader.f64()?,
+        })
+    }
+}
+
+impl Checkpoint for TimeSeries {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.f64(self.beta);
+        writer.u64(self.sites as u64);
+        writer.f64s(&self.energies);
+        writer.f64s(&self.magnetizations);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> io::Result<Self> {
+        Ok(TimeSeries {
+            beta: reader.f64()?,
+            sites: reader.u64()? as usize,
+            energies: reader.f64s()?,
+            magnetizations: reader.f64s()?,
+        })
+    }
+}
+
+impl Checkpoint for NFoldWayState {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.f64(self.time);
+        writer.u64(self.classes.len() as u64);
+        for (key, rate, sites) in self.classes.iter() {
+            writer.u64(*key as u64);
+            writer.f64(*rate);
+            writer.usizes(sites);
+        }
+    }
+
+    fn load(reader: &mut CheckpointReader) -> io::Result<Self> {
+        let time = reader.f64()?;
+        let classes = (0..reader.length(24)?)
+            .map(|_| Ok((reader.u64()? as i64, reader.f64()?, reader.usizes()?)))
+            .collect::<io::Result<_>>()?;
+        Ok(NFoldWayState { time, classes })
+    }
+}
+
+// Full state of a single-lattice run, from which it continues bit-identically: the
+// random numbers of every sweep only depend on the seed and the sweep counter
+pub struct SimulationCheckpoint {
+    pub settings: Settings,
+    pub fields: Vec<IsingField>,
+    pub energy: f64,
+    pub magnetization: f64,
+    pub seed: u64,
+    // Sweeps done by the lattice, including the thermalization
+    pub lattice_sweeps: u64,
+    // Next sweep of the measurement loop
+    pub sweep: u64,
+    pub nfold_way: Option<NFoldWayState>,
+    pub time_series: TimeSeries,
+    pub correlations: Option<Correlations>,
+    pub structure_factor: Option<StructureFactor>,
+    pub percolation: Option<Percolation>,
+    // Length of the measurement output file when the checkpoint was written
+    pub output_length: Option<u64>,
+}
+
+impl SimulationCheckpoint {
+    // Lattice in the saved state
+    pub fn lattice(&self) -> Lattice {
+        // The saved fields replace the initial state, which is not drawn or read again
+        let mut lattice = Lattice::with_fields(self.settings.clone(), self.seed, &self.fields);
+        lattice.restore_totals(self.energy, self.magnetization);
+        lattice.set_sweeps(self.lattice_sweeps);
+        lattice
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = CheckpointWriter::new();
+        MAGIC.iter().for_each(|byte| writer.u8(*byte));
+        writer.u64(CHECKPOINT_VERSION as u64);
+        self.settings.save(&mut writer);
+        // One byte per spin
+        writer.u64(self.fields.len() as u64);
+        for field in self.fields.iter() {
+            writer.u8((*field == IsingField::Up) as u8);
+        }
+        writer.f64(self.energy);
+        writer.f64(self.magnetization);
+        writer.u64(self.seed);
+        writer.u64(self.lattice_sweeps);
+        writer.u64(self.sweep);
+        writer.option(self.nfold_way.as_ref());
+        self.time_series.save(&mut writer);
+        writer.option(self.correlations.as_ref());
+        writer.option(self.structure_factor.as_ref());
+        writer.option(self.percolation.as_ref());
+        writer.u8(self.output_length.is_some() as u8);
+        writer.u64(self.output_length.unwrap_or(0));
+        writer.into_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = CheckpointReader::new(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("Not a checkpoint file"));
+        }
+        let version = reader.u64()?;
+        if version != CHECKPOINT_VERSION as u64 {
+            return Err(invalid(&format!(
+                "Checkpoint version {} is not supported, expected {}",
+                version, CHECKPOINT_VERSION
+            )));
+        }
+        let settings = Settings::load(&mut reader)?;
+        let sites = reader.length(1)?;
+        if sites != usize::pow(LATTICE_SIZE, DIMENSIONS as u32) {
+            return Err(invalid("Wrong number of sites in checkpoint"));
+        }
+        let fields = (0..sites)
+            .map(|_| match reader.u8()? {
+                0 => Ok(IsingField::Down),
+                1 => Ok(IsingField::Up),
+                _ => Err(invalid("Invalid spin in checkpoint")),
+            })
+            .collect::<io::Result<Vec<IsingField>>>()?;
+
+        let checkpoint = Self {
+            settings,
+            fields,
+            energy: reader.f64()?,
+            magnetization: reader.f64()?,
+            seed: reader.u64()?,
+            lattice_sweeps: reader.u64()?,
+            sweep: reader.u64()?,
+            nfold_way: reader.option()?,
+            time_series: TimeSeries::load(&mut reader)?,
+            correlations: reader.option()?,
+            structure_factor: reader.option()?,
+            percolation: reader.option()?,
+            output_length: match (reader.u8()?, reader.u64()?) {
+                (0, _) => None,
+                (_, length) => Some(length),
+            },
+        };
+        if !reader.is_empty() {
+            return Err(invalid("Trailing data in checkpoint"));
+        }
+        Ok(checkpoint)
+    }
+
+    // Written to a temporary file first, so that a kill while writing leaves the
+    // previous checkpoint intact
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_bytes())?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::montecarlo::nfold_way::NFoldWay;
+    use crate::settings::SettingsBuilder;
+    use crate::statistics::percolation::ClusterKind;
+
+    // Checkpoint after 3 sweeps, with the lattice that took them
+    fn checkpoint() -> (SimulationCheckpoint, Lattice) {
+        let settings = SettingsBuilder::new()
+            .add_beta(0.3)
+            .add_magnetic_field(0.1)
+            .add_site_initialisation(Initialisation::Random)
+            .add_interactions(Interactions::annni(0.5, 2))
+            .build();
+        let mut lattice = Lattice::with_seed(settings, 11).unwrap();
+        let mut time_series = TimeSeries::new(0.3, lattice.len());
+        let mut correlations = Correlations::new();
+        let mut structure_factor = StructureFactor::new();
+        let mut percolation = Percolation::new(ClusterKind::Geometric);
+        for _ in 0..3 {
+            lattice.montecarlo_sweep();
+            time_series.push(lattice.get_energy(), lattice.get_magnetization());
+            correlations.measure(&lattice);
+            structure_factor.measure(&lattice);
+            percolation.measure(&lattice, &mut rand::rng());
+        }
+        let checkpoint = SimulationCheckpoint {
+            settings: (*lattice.settings).clone(),
+            fields: lattice.get_fields(),
+            energy: lattice.get_energy(),
+            magnetization: lattice.get_magnetization(),
+            seed: lattice.seed(),
+            lattice_sweeps: lattice.sweeps(),
+            sweep: 3,
+            nfold_way: Some(NFoldWay::new(&lattice).state()),
+            time_series,
+            correlations: Some(correlations),
+            structure_factor: Some(structure_factor),
+            percolation: Some(percolation),
+            output_length: Some(1234),
+        };
+        (checkpoint, lattice)
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let (checkpoint, _) = checkpoint();
+        let bytes = checkpoint.to_bytes();
+        let loaded = SimulationCheckpoint::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+        assert_eq!(loaded.fields, checkpoint.fields);
+        assert_eq!(loaded.time_series, checkpoint.time_series);
+        assert_eq!(loaded.output_length, Some(1234));
+
+        // Truncated, corrupted and future files are refused
+        assert!(SimulationCheckpoint::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(SimulationCheckpoint::from_bytes(&bytes[1..]).is_err());
+        let mut future = bytes.clone();
+        future[MAGIC.len()] += 1;
+        assert!(SimulationCheckpoint::from_bytes(&future).is_err());
+    }
+
+    #[test]
+    fn test_initialisation_round_trip() {
+        for initialisation in [
+            Initialisation::Stripes(3),
+            Initialisation::Droplet(2.5),
+            Initialisation::FixedMagnetization(-0.25),
+            Initialisation::FromFile("configurations/start.pgm".into()),
+        ] {
+            let settings = SettingsBuilder::new()
+                .add_site_initialisation(initialisation.clone())
+                .build();
+            let mut writer = CheckpointWriter::new();
+            settings.save(&mut writer);
+            let bytes = writer.into_bytes();
+            let loaded = Settings::load(&mut CheckpointReader::new(&bytes)).unwrap();
+            assert_eq!(loaded.site_initialisation, initialisation);
+        }
+    }
+
+    #[test]
+    fn test_boundary_round_trip() {
+        let settings = SettingsBuilder::new()
+            .add_boundary_conditions(BoundaryConditions::Fixed)
+            .add_boundary_axis(2)
+            .build();
+        let mut writer = CheckpointWriter::new();
+        settings.save(&mut writer);
+        let bytes = writer.into_bytes();
+        let loaded = Settings::load(&mut CheckpointReader::new(&bytes)).unwrap();
+        assert_eq!(loaded.boundary_conditions, BoundaryConditions::Fixed);
+        assert_eq!(loaded.boundary_axis, 2);
+    }
+
+    #[test]
+    fn test_resume_is_bit_identical() {
+        let (checkpoint, mut original) = checkpoint();
+        let mut resumed = SimulationCheckpoint::from_bytes(&checkpoint.to_bytes())
+            .unwrap()
+            .lattice();
+        assert_eq!(resumed.sweeps(), 3);
+        for _ in 0..5 {
+            original.montecarlo_sweep();
+            resumed.montecarlo_sweep();
+            assert_eq!(original.get_fields(), resumed.get_fields());
+            assert_eq!(
+                original.get_energy().to_bits(),
+                resumed.get_energy().to_bits()
+            );
+        }
+    }
+}
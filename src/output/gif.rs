@@ -0,0 +1,285 @@
+use crate::output::image::{Colour, Image};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Largest LZW code size of GIF
+const MAX_CODE_SIZE: u32 = 12;
+
+// Animated GIF89a written frame by frame, every frame with its own colour table so that
+// frames with different colour maps need no common palette
+pub struct GifWriter<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+    // Frame delay in hundredths of a second
+    delay: u16,
+}
+
+impl GifWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        delay: u16,
+    ) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), width, height, delay)
+    }
+}
+
+impl<W: Write> GifWriter<W> {
+    pub fn new(mut writer: W, width: usize, height: usize, delay: u16) -> io::Result<Self> {
+        writer.write_all(b"GIF89a")?;
+        // Logical screen without a global colour table
+        writer.write_all(&(width as u16).to_le_bytes())?;
+        writer.write_all(&(height as u16).to_le_bytes())?;
+        writer.write_all(&[0, 0, 0])?;
+        // Netscape application extension, looping forever
+        writer.write_all(&[0x21, 0xff, 11])?;
+        writer.write_all(b"NETSCAPE2.0")?;
+        writer.write_all(&[3, 1, 0, 0, 0])?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+            delay,
+        })
+    }
+
+    pub fn add_frame(&mut self, image: &Image) -> io::Result<()> {
+        assert_eq!(
+            (image.width, image.height),
+            (self.width, self.height),
+            "Every frame needs the size of the animation"
+        );
+        let (palette, indices) = palette(&image.pixels);
+        // Colour tables hold 2^bits entries, at least 2 for LZW
+        let bits = (usize::BITS - (palette.len() - 1).leading_zeros()).max(1);
+
+        // Graphic control extension with the delay
+        self.writer.write_all(&[0x21, 0xf9, 4, 0])?;
+        self.writer.write_all(&self.delay.to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+
+        // Image descriptor covering the screen, with a local colour table
+        self.writer.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.writer.write_all(&(self.width as u16).to_le_bytes())?;
+        self.writer.write_all(&(self.height as u16).to_le_bytes())?;
+        self.writer.write_all(&[0x80 | (bits - 1) as u8])?;
+        for i in 0..1 << bits {
+            let colour = palette.get(i).copied().unwrap_or(Colour([0; 3]));
+            self.writer.write_all(&colour.0)?;
+        }
+
+        let min_code_size = bits.max(2);
+        self.writer.write_all(&[min_code_size as u8])?;
+        for block in lzw(&indices, min_code_size).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])
+    }
+
+    // Writes the trailer and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0x3b])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Distinct colours in order of appearance and the index of every pixel
+fn palette(pixels: &[Colour]) -> (Vec<Colour>, Vec<u8>) {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let indices = pixels
+        .iter()
+        .map(|colour| {
+            *lookup.entry(colour.0).or_insert_with(|| {
+                palette.push(*colour);
+                palette.len() - 1
+            }) as u8
+        })
+        .collect();
+    assert!(palette.len() <= 256, "GIF frames hold at most 256 colours");
+    (palette, indices)
+}
+
+// Codes packed least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u32, size: u32) {
+        self.buffer |= code << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// Variable-length LZW compression of GIF. The code size grows as soon as the decoder,
+// which adds every entry one code later, needs the next bit, and the table is cleared
+// when it is full.
+fn lzw(indices: &[u8], min_code_size: u32) -> Vec<u8> {
+    let clear = 1 << min_code_size;
+    let end = clear + 1;
+    let mut output = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+    let mut table: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut size = min_code_size + 1;
+    let mut next = end + 1;
+
+    output.write(clear, size);
+    let Some((first, rest)) = indices.split_first() else {
+        output.write(end, size);
+        return output.finish();
+    };
+    let mut prefix = *first as u32;
+    for index in rest {
+        if let Some(code) = table.get(&(prefix, *index)) {
+            prefix = *code;
+            continue;
+        }
+        output.write(prefix, size);
+        if next >= 1 << size && size < MAX_CODE_SIZE {
+            size += 1;
+        }
+        if next == 1 << MAX_CODE_SIZE {
+            output.write(clear, size);
+            table.clear();
+            size = min_code_size + 1;
+            next = end + 1;
+        } else {
+            table.insert((prefix, *index), next);
+            next += 1;
+        }
+        prefix = *index as u32;
+    }
+    output.write(prefix, size);
+    if next >= 1 << size && size < MAX_CODE_SIZE {
+        size += 1;
+    }
+    output.write(end, size);
+    output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference decoder following the GIF specification
+    fn decode(bytes: &[u8], min_code_size: u32) -> Vec<u8> {
+        let clear = 1 << min_code_size;
+        let end = clear + 1;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut size = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut output = Vec::new();
+        let (mut buffer, mut bits, mut position) = (0u32, 0, 0);
+        loop {
+            while bits < size {
+                buffer |= (bytes[position] as u32) << bits;
+                position += 1;
+                bits += 8;
+            }
+            let code = buffer & ((1 << size) - 1);
+            buffer >>= size;
+            bits -= size;
+
+            if code == clear {
+                table = (0..clear).map(|i| vec![i as u8]).collect();
+                table.extend([Vec::new(), Vec::new()]);
+                size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return output;
+            }
+            let entry = match (table.get(code as usize), previous.as_ref()) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.clone(), vec![previous[0]]].concat(),
+                (None, None) => panic!("Invalid code"),
+            };
+            output.extend(&entry);
+            if let Some(previous) = previous
+                && table.len() < 1 << MAX_CODE_SIZE
+            {
+                table.push([previous, vec![entry[0]]].concat());
+                if table.len() == 1 << size && size < MAX_CODE_SIZE {
+                    size += 1;
+                }
+            }
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn test_lzw() {
+        // Example of the GIF specification: a 10 x 10 image of 4 colours
+        let indices: Vec<u8> = (0..100).map(|i| [1, 1, 2, 2, 0][i % 5]).collect();
+        assert_eq!(decode(&lzw(&indices, 2), 2), indices);
+        assert_eq!(decode(&lzw(&[], 2), 2), Vec::<u8>::new());
+
+        // Long and noisy streams fill the table and clear it
+        let mut state = 12345u64;
+        let noisy: Vec<u8> = (0..200000)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (state >> 60) as u8
+            })
+            .collect();
+        assert_eq!(decode(&lzw(&noisy, 4), 4), noisy);
+        let uniform = vec![3; 100000];
+        assert_eq!(decode(&lzw(&uniform, 2), 2), uniform);
+    }
+
+    #[test]
+    fn test_gif() {
+        let mut image = Image::new(4, 2, Colour([0, 0, 0]));
+        image.pixels[5] = Colour([255, 0, 0]);
+        image.pixels[6] = Colour([0, 255, 0]);
+        let mut gif = GifWriter::new(Vec::new(), 4, 2, 10).unwrap();
+        gif.add_frame(&image).unwrap();
+        gif.add_frame(&Image::new(4, 2, Colour([9, 9, 9]))).unwrap();
+        let bytes = gif.finish().unwrap();
+
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(bytes[6..10], [4, 0, 2, 0]);
+        assert_eq!(bytes.last(), Some(&0x3b));
+        // Graphic control extension and descriptor of the first frame, with a table of
+        // 4 colours after it
+        let frame = 13 + 19;
+        assert_eq!(bytes[frame..frame + 8], [0x21, 0xf9, 4, 0, 10, 0, 0, 0]);
+        assert_eq!(
+            bytes[frame + 8..frame + 18],
+            [0x2c, 0, 0, 0, 0, 4, 0, 2, 0, 0x81]
+        );
+        let table = frame + 18;
+        assert_eq!(bytes[table..table + 9], [0, 0, 0, 255, 0, 0, 0, 255, 0]);
+
+        let data = table + 12;
+        assert_eq!(bytes[data], 2);
+        let length = bytes[data + 1] as usize;
+        let pixels = decode(&bytes[data + 2..data + 2 + length], 2);
+        assert_eq!(pixels, [0, 0, 0, 0, 0, 1, 2, 0]);
+    }
+}
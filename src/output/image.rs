@@ -0,0 +1,411 @@
+use crate::field::ising::IsingField;
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::geometry::utils::position_to_lattice;
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+// Largest payload of a stored deflate block
+const STORED_BLOCK: usize = 65535;
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum ImageFormat {
+    // Binary greyscale portable graymap
+    Pgm,
+    // Binary colour portable pixmap
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Pgm => "pgm",
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Colour(pub [u8; 3]);
+
+impl Colour {
+    // Rec. 601 luma, for greyscale output
+    pub fn grey(&self) -> u8 {
+        let [r, g, b] = self.0.map(|c| c as f64);
+        (0.299 * r + 0.587 * g + 0.114 * b).round() as u8
+    }
+}
+
+// Parsed from hexadecimal "#rrggbb" or "rrggbb"
+impl FromStr for Colour {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        let channel = |i: usize| {
+            hex.get(2 * i..2 * i + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+        };
+        match (hex.len(), channel(0), channel(1), channel(2)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Colour([r, g, b])),
+            _ => Err(format!("Invalid colour {}, expected #rrggbb", text)),
+        }
+    }
+}
+
+// Colour maps of scalar fields, sampled at 256 levels so that every image fits a GIF
+// palette
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum ColourMap {
+    Grey,
+    // Diverging blue-white-red map, for fields symmetric around zero
+    Coolwarm,
+    Viridis,
+}
+
+impl ColourMap {
+    fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            ColourMap::Grey => &[[0, 0, 0], [255, 255, 255]],
+            ColourMap::Coolwarm => &[[59, 76, 192], [221, 221, 221], [180, 4, 38]],
+            ColourMap::Viridis => &[
+                [68, 1, 84],
+                [59, 82, 139],
+                [33, 145, 140],
+                [94, 201, 98],
+                [253, 231, 37],
+            ],
+        }
+    }
+
+    // Colour of t in [0, 1], clamped, interpolated linearly between the stops
+    pub fn colour(&self, t: f64) -> Colour {
+        let stops = self.stops();
+        let level = (t.clamp(0.0, 1.0) * 255.0).round() / 255.0;
+        let x = level * (stops.len() - 1) as f64;
+        let i = (x.floor() as usize).min(stops.len() - 2);
+        let fraction = x - i as f64;
+        let mut colour = [0; 3];
+        for (c, channel) in colour.iter_mut().enumerate() {
+            let (a, b) = (stops[i][c] as f64, stops[i + 1][c] as f64);
+            *channel = (a + fraction * (b - a)).round() as u8;
+        }
+        Colour(colour)
+    }
+}
+
+// Plane of the lattice shown in an image: the first two axes other than the normal one,
+// with every other coordinate fixed at the given position
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Slice {
+    pub axes: [usize; 2],
+    pub position: usize,
+}
+
+impl Slice {
+    pub fn new(normal: Option<usize>, position: usize) -> Self {
+        assert!(
+            position < LATTICE_SIZE,
+            "The slice lies outside of the lattice"
+        );
+        let mut axes = (0..DIMENSIONS).filter(|axis| Some(*axis) != normal);
+        Self {
+            axes: [
+                axes.next().unwrap(),
+                axes.next().expect("Images need at least two dimensions"),
+            ],
+            position,
+        }
+    }
+
+    // Pixel of a site in the plane, with the first axis horizontal
+    pub fn pixel(&self, lattice_position: [usize; DIMENSIONS]) -> Option<(usize, usize)> {
+        let in_plane = (0..DIMENSIONS)
+            .filter(|axis| !self.axes.contains(axis))
+            .all(|axis| lattice_position[axis] == self.position);
+        in_plane.then(|| {
+            (
+                lattice_position[self.axes[0]],
+                lattice_position[self.axes[1]],
+            )
+        })
+    }
+}
+
+// RGB raster image
+#[derive(Debug, PartialEq, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Colour>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, background: Colour) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width * height],
+        }
+    }
+
+    // One square of scale x scale pixels per site of the slice
+    pub fn from_lattice(
+        lattice: &Lattice,
+        slice: &Slice,
+        scale: usize,
+        colours: [Colour; 2],
+    ) -> Self {
+        let size = LATTICE_SIZE * scale;
+        let mut image = Self::new(size, size, colours[1]);
+        for position in 0..lattice.len() {
+            let site = lattice.get(position);
+            let site = site.read().unwrap();
+            if let Some((x, y)) = slice.pixel(site.lattice_position) {
+                let colour = match site.field {
+                    IsingField::Up => colours[0],
+                    IsingField::Down => colours[1],
+                };
+                image.fill(x * scale, y * scale, scale, colour);
+            }
+        }
+        image
+    }
+
+    // Scalar field with one value per site, mapped from [min, max] onto the colour map
+    pub fn from_values(
+        values: &[f64],
+        slice: &Slice,
+        scale: usize,
+        (min, max): (f64, f64),
+        colour_map: ColourMap,
+    ) -> Self {
+        let size = LATTICE_SIZE * scale;
+        let mut image = Self::new(size, size, colour_map.colour(0.0));
+        for (position, value) in values.iter().enumerate() {
+            if let Some((x, y)) = slice.pixel(position_to_lattice(position)) {
+                let colour = colour_map.colour((value - min) / (max - min));
+                image.fill(x * scale, y * scale, scale, colour);
+            }
+        }
+        image
+    }
+
+    fn fill(&mut self, x: usize, y: usize, size: usize, colour: Colour) {
+        for row in y..y + size {
+            let start = row * self.width + x;
+            self.pixels[start..start + size].fill(colour);
+        }
+    }
+
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut bytes = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend(self.pixels.iter().map(Colour::grey));
+        bytes
+    }
+
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend(self.pixels.iter().flat_map(|colour| colour.0));
+        bytes
+    }
+
+    // 8-bit RGB PNG, with the image data in stored (uncompressed) deflate blocks
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut bytes = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+        let mut header = Vec::new();
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // Bit depth, colour type RGB, compression, filter and interlace methods
+        header.extend([8, 2, 0, 0, 0]);
+        png_chunk(&mut bytes, b"IHDR", &header);
+
+        // Every row starts with its filter type, 0 for none
+        let mut rows = Vec::with_capacity(self.height * (3 * self.width + 1));
+        for row in self.pixels.chunks(self.width) {
+            rows.push(0);
+            rows.extend(row.iter().flat_map(|colour| colour.0));
+        }
+        png_chunk(&mut bytes, b"IDAT", &zlib_stored(&rows));
+        png_chunk(&mut bytes, b"IEND", &[]);
+        bytes
+    }
+
+    pub fn encode(&self, format: ImageFormat) -> Vec<u8> {
+        match format {
+            ImageFormat::Pgm => self.to_pgm(),
+            ImageFormat::Ppm => self.to_ppm(),
+            ImageFormat::Png => self.to_png(),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> io::Result<()> {
+        fs::write(path, self.encode(format))
+    }
+}
+
+// CRC-32 of PNG chunks and gzip (reflected polynomial 0xedb88320)
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// Adler-32 checksum closing a zlib stream
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// Zlib stream of stored deflate blocks, valid without any compression
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window and no preset dictionary, with the check bits
+    let mut bytes = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = match data.is_empty() {
+        true => vec![&[]],
+        false => data.chunks(STORED_BLOCK).collect(),
+    };
+    for (i, block) in blocks.iter().enumerate() {
+        // BFINAL on the last block, BTYPE 00
+        bytes.push((i + 1 == blocks.len()) as u8);
+        let length = block.len() as u16;
+        bytes.extend(length.to_le_bytes());
+        bytes.extend((!length).to_le_bytes());
+        bytes.extend_from_slice(block);
+    }
+    bytes.extend(adler32(data).to_be_bytes());
+    bytes
+}
+
+fn png_chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    bytes.extend((data.len() as u32).to_be_bytes());
+    let start = bytes.len();
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(data);
+    let crc = crc32(&bytes[start..]);
+    bytes.extend(crc.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingsBuilder;
+
+    const WHITE: Colour = Colour([255, 255, 255]);
+    const BLACK: Colour = Colour([0, 0, 0]);
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn test_colour() {
+        assert_eq!("#ff8000".parse(), Ok(Colour([255, 128, 0])));
+        assert_eq!("0000ff".parse(), Ok(Colour([0, 0, 255])));
+        assert!("#ff80".parse::<Colour>().is_err());
+        assert!("#gg0000".parse::<Colour>().is_err());
+        assert_eq!(WHITE.grey(), 255);
+    }
+
+    #[test]
+    fn test_colour_map() {
+        assert_eq!(ColourMap::Grey.colour(-1.0), BLACK);
+        assert_eq!(ColourMap::Grey.colour(0.5), Colour([128, 128, 128]));
+        assert_eq!(ColourMap::Coolwarm.colour(0.0), Colour([59, 76, 192]));
+        assert_eq!(ColourMap::Viridis.colour(1.0), Colour([253, 231, 37]));
+        // No more than 256 distinct colours
+        let mut colours: Vec<[u8; 3]> = (0..10000)
+            .map(|i| ColourMap::Viridis.colour(i as f64 / 9999.0).0)
+            .collect();
+        colours.sort();
+        colours.dedup();
+        assert!(colours.len() <= 256);
+    }
+
+    #[test]
+    fn test_slice() {
+        // Plane z = 2 of the 3D test lattice
+        let slice = Slice::new(Some(2), 2);
+        assert_eq!(slice.axes, [0, 1]);
+        assert_eq!(slice.pixel([1, 3, 2]), Some((1, 3)));
+        assert_eq!(slice.pixel([1, 3, 0]), None);
+        assert_eq!(Slice::new(Some(0), 0).axes, [1, 2]);
+    }
+
+    #[test]
+    fn test_image() {
+        let lattice = Lattice::new(SettingsBuilder::new().build()).unwrap();
+        // Flip the sites with x = 1 in the plane z = 0
+        for position in 0..lattice.len() {
+            let x = position_to_lattice(position);
+            if x[0] == 1 && x[2] == 0 {
+                lattice.get(position).write().unwrap().flip();
+            }
+        }
+        let image = Image::from_lattice(&lattice, &Slice::new(Some(2), 0), 2, [WHITE, BLACK]);
+        assert_eq!((image.width, image.height), (8, 8));
+        // Columns 2 and 3 hold the flipped sites
+        let row: Vec<Colour> = image.pixels[..8].to_vec();
+        assert_eq!(
+            row,
+            [WHITE, WHITE, BLACK, BLACK, WHITE, WHITE, WHITE, WHITE]
+        );
+
+        let ppm = image.to_ppm();
+        assert!(ppm.starts_with(b"P6\n8 8\n255\n"));
+        assert_eq!(ppm.len(), 11 + 3 * 64);
+        let pgm = image.to_pgm();
+        assert_eq!(pgm[pgm.len() - 64..][..4], [255, 255, 0, 0]);
+
+        let values: Vec<f64> = lattice.get_fields().iter().map(|f| f.value()).collect();
+        let map = ColourMap::Grey;
+        let grey = Image::from_values(&values, &Slice::new(Some(2), 0), 2, (-1.0, 1.0), map);
+        assert_eq!(grey.pixels[..8], row);
+    }
+
+    #[test]
+    fn test_png() {
+        let image = Image::new(3, 2, Colour([1, 2, 3]));
+        let png = image.to_png();
+        assert_eq!(
+            png[..8],
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']
+        );
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 3, 0, 0, 0, 2]);
+        assert_eq!(
+            png[png.len() - 12..],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+
+        // The stored block holds the filtered rows verbatim
+        let rows = [0, 1, 2, 3, 1, 2, 3, 1, 2, 3];
+        let stream = zlib_stored(&[rows, rows].concat());
+        assert_eq!(stream[..7], [0x78, 0x01, 1, 20, 0, !20, 0xff]);
+        assert_eq!(
+            zlib_stored(&vec![0; STORED_BLOCK + 1]).len(),
+            2 + 2 * 5 + STORED_BLOCK + 1 + 4
+        );
+    }
+}
@@ -0,0 +1,7 @@
+pub mod animation;
+pub mod checkpoint;
+pub mod gif;
+pub mod image;
+pub mod terminal;
+pub mod vtk;
+pub mod writer;
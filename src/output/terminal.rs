@@ -0,0 +1,160 @@
+use crate::output::image::{Colour, Image};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Read};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Upper half block, drawn with the upper pixel as foreground and the lower as background
+const HALF_BLOCK: char = '▀';
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WatchCommand {
+    BetaUp,
+    BetaDown,
+    FieldUp,
+    FieldDown,
+    Pause,
+    Quit,
+}
+
+// Commands of the keys pressed: arrows up and down or + and - change beta, arrows right
+// and left or ] and [ change h, space pauses and q quits. Other keys are ignored.
+pub fn parse_keys(bytes: &[u8]) -> Vec<WatchCommand> {
+    let mut commands = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let command = match &bytes[i..] {
+            [0x1b, b'[', arrow, ..] => {
+                i += 2;
+                match arrow {
+                    b'A' => Some(WatchCommand::BetaUp),
+                    b'B' => Some(WatchCommand::BetaDown),
+                    b'C' => Some(WatchCommand::FieldUp),
+                    b'D' => Some(WatchCommand::FieldDown),
+                    _ => None,
+                }
+            }
+            [b'+' | b'=', ..] => Some(WatchCommand::BetaUp),
+            [b'-' | b'_', ..] => Some(WatchCommand::BetaDown),
+            [b']', ..] => Some(WatchCommand::FieldUp),
+            [b'[', ..] => Some(WatchCommand::FieldDown),
+            [b' ', ..] => Some(WatchCommand::Pause),
+            [b'q' | b'Q', ..] => Some(WatchCommand::Quit),
+            _ => None,
+        };
+        commands.extend(command);
+        i += 1;
+    }
+    commands
+}
+
+fn foreground(text: &mut String, Colour([r, g, b]): Colour) {
+    write!(text, "\x1b[38;2;{};{};{}m", r, g, b).unwrap();
+}
+
+fn background(text: &mut String, Colour([r, g, b]): Colour) {
+    write!(text, "\x1b[48;2;{};{};{}m", r, g, b).unwrap();
+}
+
+// Two pixel rows per line of text in 24-bit ANSI colours, with the colours only sent
+// when they change
+pub fn half_blocks(image: &Image) -> String {
+    let mut text = String::new();
+    for y in (0..image.height).step_by(2) {
+        let mut colours = None;
+        for x in 0..image.width {
+            let upper = image.pixels[y * image.width + x];
+            let lower = (y + 1 < image.height).then(|| image.pixels[(y + 1) * image.width + x]);
+            if colours != Some((upper, lower)) {
+                foreground(&mut text, upper);
+                match lower {
+                    Some(lower) => background(&mut text, lower),
+                    None => text.push_str("\x1b[49m"),
+                }
+                colours = Some((upper, lower));
+            }
+            text.push(HALF_BLOCK);
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    text
+}
+
+// Unbuffered input without echo on the controlling terminal, restored when dropped
+pub struct RawMode {
+    saved: String,
+}
+
+fn stty(arguments: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(arguments)
+        .stdin(File::open("/dev/tty")?)
+        .output()?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => Err(io::Error::other("stty failed")),
+    }
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo"])?;
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+// Keys read from the standard input on a background thread, to poll without blocking
+pub fn key_reader() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buffer = [0; 16];
+        while let Ok(length) = stdin.read(&mut buffer) {
+            if length == 0 || sender.send(buffer[..length].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        use WatchCommand::*;
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1b[D+x q"),
+            [BetaUp, FieldDown, BetaUp, Pause, Quit]
+        );
+        assert_eq!(parse_keys(b"[]-"), [FieldDown, FieldUp, BetaDown]);
+        // Unknown escape sequences are skipped whole
+        assert_eq!(parse_keys(b"\x1b[Hq"), [Quit]);
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let white = Colour([255; 3]);
+        let black = Colour([0; 3]);
+        let mut image = Image::new(2, 3, white);
+        image.pixels[3] = black;
+        let text = half_blocks(&image);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let (fg, bg) = ("\x1b[38;2;255;255;255m", "\x1b[48;2;255;255;255m");
+        assert_eq!(lines[0], format!("{fg}{bg}▀{fg}\x1b[48;2;0;0;0m▀\x1b[0m"));
+        // The odd last row has the default background
+        assert_eq!(lines[1], "\x1b[38;2;255;255;255m\x1b[49m▀▀\x1b[0m");
+    }
+}
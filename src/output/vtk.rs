@@ -0,0 +1,315 @@
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::geometry::utils::position_to_lattice;
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum VtkFormat {
+    // Legacy ASCII structured points, .vtk
+    Legacy,
+    // XML image data, .vti
+    Xml,
+}
+
+impl VtkFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VtkFormat::Legacy => "vtk",
+            VtkFormat::Xml => "vti",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum VtkData {
+    Scalars(Vec<f64>),
+    Vectors(Vec<[f64; 3]>),
+}
+
+// Point data with one value per site, indexed by the position of the site
+#[derive(Debug, PartialEq, Clone)]
+pub struct VtkField {
+    pub name: String,
+    pub data: VtkData,
+}
+
+impl VtkField {
+    pub fn scalars(name: &str, values: Vec<f64>) -> Self {
+        Self {
+            name: name.to_string(),
+            data: VtkData::Scalars(values),
+        }
+    }
+
+    pub fn vectors(name: &str, values: Vec<[f64; 3]>) -> Self {
+        Self {
+            name: name.to_string(),
+            data: VtkData::Vectors(values),
+        }
+    }
+
+    // The spins as +1 and -1
+    pub fn spins(lattice: &Lattice) -> Self {
+        let values = lattice.get_fields().iter().map(|f| f.value()).collect();
+        Self::scalars("spin", values)
+    }
+
+    fn len(&self) -> usize {
+        match &self.data {
+            VtkData::Scalars(values) => values.len(),
+            VtkData::Vectors(values) => values.len(),
+        }
+    }
+
+    // Values in VTK point order, x fastest, then y and z
+    fn ordered(&self) -> Vec<String> {
+        let mut values = vec![String::new(); self.len()];
+        for position in 0..self.len() {
+            values[point_index(position)] = match &self.data {
+                VtkData::Scalars(scalars) => scalars[position].to_string(),
+                VtkData::Vectors(vectors) => {
+                    let [x, y, z] = vectors[position];
+                    format!("{} {} {}", x, y, z)
+                }
+            };
+        }
+        values
+    }
+}
+
+// Index of the VTK point of a site, from its lattice coordinates
+fn point_index(position: usize) -> usize {
+    position_to_lattice(position)
+        .iter()
+        .rev()
+        .fold(0, |index, coordinate| index * LATTICE_SIZE + coordinate)
+}
+
+fn check_dimensions() -> io::Result<()> {
+    match DIMENSIONS <= 3 {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "VTK images have at most three dimensions",
+        )),
+    }
+}
+
+// Points along x, y and z; lower-dimensional lattices are flat in the missing axes
+fn dimensions() -> [usize; 3] {
+    check_dimensions().unwrap();
+    let mut dimensions = [1; 3];
+    dimensions
+        .iter_mut()
+        .take(DIMENSIONS)
+        .for_each(|n| *n = LATTICE_SIZE);
+    dimensions
+}
+
+pub fn to_legacy(fields: &[VtkField], title: &str) -> String {
+    let [nx, ny, nz] = dimensions();
+    let mut text = String::new();
+    writeln!(text, "# vtk DataFile Version 3.0").unwrap();
+    // The title is a single line of at most 256 characters
+    let title: String = title.replace('\n', " ").chars().take(256).collect();
+    writeln!(text, "{}", title).unwrap();
+    writeln!(text, "ASCII").unwrap();
+    writeln!(text, "DATASET STRUCTURED_POINTS").unwrap();
+    writeln!(text, "DIMENSIONS {} {} {}", nx, ny, nz).unwrap();
+    writeln!(text, "ORIGIN 0 0 0").unwrap();
+    writeln!(text, "SPACING 1 1 1").unwrap();
+    writeln!(text, "POINT_DATA {}", nx * ny * nz).unwrap();
+    for field in fields {
+        assert_eq!(
+            field.len(),
+            nx * ny * nz,
+            "Every field needs a value per site"
+        );
+        match field.data {
+            VtkData::Scalars(_) => {
+                writeln!(text, "SCALARS {} double 1", field.name).unwrap();
+                writeln!(text, "LOOKUP_TABLE default").unwrap();
+            }
+            VtkData::Vectors(_) => writeln!(text, "VECTORS {} double", field.name).unwrap(),
+        }
+        for value in field.ordered() {
+            writeln!(text, "{}", value).unwrap();
+        }
+    }
+    text
+}
+
+pub fn to_xml(fields: &[VtkField]) -> String {
+    let [nx, ny, nz] = dimensions();
+    let extent = format!("0 {} 0 {} 0 {}", nx - 1, ny - 1, nz - 1);
+    let mut text = String::new();
+    writeln!(text, "<?xml version=\"1.0\"?>").unwrap();
+    writeln!(
+        text,
+        "<VTKFile type=\"ImageData\" version=\"1.0\" byte_order=\"LittleEndian\">"
+    )
+    .unwrap();
+    writeln!(
+        text,
+        "  <ImageData WholeExtent=\"{}\" Origin=\"0 0 0\" Spacing=\"1 1 1\">",
+        extent
+    )
+    .unwrap();
+    writeln!(text, "    <Piece Extent=\"{}\">", extent).unwrap();
+    // The first scalar and vector fields are the active ones
+    let active = |vectors: bool| {
+        fields
+            .iter()
+            .find(|field| matches!(field.data, VtkData::Vectors(_)) == vectors)
+            .map(|field| field.name.as_str())
+    };
+    let mut attributes = String::new();
+    if let Some(name) = active(false) {
+        write!(attributes, " Scalars=\"{}\"", name).unwrap();
+    }
+    if let Some(name) = active(true) {
+        write!(attributes, " Vectors=\"{}\"", name).unwrap();
+    }
+    writeln!(text, "      <PointData{}>", attributes).unwrap();
+    for field in fields {
+        assert_eq!(
+            field.len(),
+            nx * ny * nz,
+            "Every field needs a value per site"
+        );
+        let components = match field.data {
+            VtkData::Scalars(_) => 1,
+            VtkData::Vectors(_) => 3,
+        };
+        writeln!(
+            text,
+            "        <DataArray type=\"Float64\" Name=\"{}\" NumberOfComponents=\"{}\" format=\"ascii\">",
+            field.name, components
+        )
+        .unwrap();
+        writeln!(text, "          {}", field.ordered().join(" ")).unwrap();
+        writeln!(text, "        </DataArray>").unwrap();
+    }
+    writeln!(text, "      </PointData>").unwrap();
+    writeln!(text, "    </Piece>").unwrap();
+    writeln!(text, "  </ImageData>").unwrap();
+    writeln!(text, "</VTKFile>").unwrap();
+    text
+}
+
+// Numbered VTK files in a directory with a ParaView collection (.pvd) indexing them by
+// sweep. The index is rewritten after every file so that it is valid if the run stops.
+pub struct VtkSeries {
+    directory: PathBuf,
+    format: VtkFormat,
+    steps: Vec<(u64, String)>,
+}
+
+impl VtkSeries {
+    pub fn create<P: AsRef<Path>>(directory: P, format: VtkFormat) -> io::Result<Self> {
+        check_dimensions()?;
+        fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory: directory.as_ref().to_path_buf(),
+            format,
+            steps: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, sweep: u64, fields: &[VtkField]) -> io::Result<()> {
+        check_dimensions()?;
+        let name = format!("lattice_{:06}.{}", sweep, self.format.extension());
+        let text = match self.format {
+            VtkFormat::Legacy => to_legacy(fields, &format!("Ising lattice at sweep {}", sweep)),
+            VtkFormat::Xml => to_xml(fields),
+        };
+        fs::write(self.directory.join(&name), text)?;
+        self.steps.push((sweep, name));
+
+        let mut index = String::new();
+        writeln!(index, "<?xml version=\"1.0\"?>").unwrap();
+        writeln!(index, "<VTKFile type=\"Collection\" version=\"1.0\">").unwrap();
+        writeln!(index, "  <Collection>").unwrap();
+        for (sweep, name) in self.steps.iter() {
+            writeln!(
+                index,
+                "    <DataSet timestep=\"{}\" part=\"0\" file=\"{}\"/>",
+                sweep, name
+            )
+            .unwrap();
+        }
+        writeln!(index, "  </Collection>").unwrap();
+        writeln!(index, "</VTKFile>").unwrap();
+        fs::write(self.directory.join("lattice.pvd"), index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingsBuilder;
+
+    fn fields() -> Vec<VtkField> {
+        let lattice = Lattice::new(SettingsBuilder::new().build()).unwrap();
+        lattice.get(5).write().unwrap().flip();
+        let positions = (0..lattice.len())
+            .map(|position| {
+                let [x, y, z] = position_to_lattice(position).map(|x| x as f64);
+                [x, y, z]
+            })
+            .collect();
+        vec![
+            VtkField::spins(&lattice),
+            VtkField::vectors("coordinates", positions),
+        ]
+    }
+
+    #[test]
+    fn test_point_order() {
+        // Site (1, 2, 3) is the point x + 4 y + 16 z
+        let position = crate::geometry::utils::lattice_to_position([1, 2, 3]);
+        assert_eq!(point_index(position), 1 + 4 * 2 + 16 * 3);
+        assert_eq!(dimensions(), [4, 4, 4]);
+    }
+
+    #[test]
+    fn test_legacy() {
+        let text = to_legacy(&fields(), "test");
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[4], "DIMENSIONS 4 4 4");
+        assert_eq!(lines[7], "POINT_DATA 64");
+        assert_eq!(
+            lines[8..10],
+            ["SCALARS spin double 1", "LOOKUP_TABLE default"]
+        );
+        // The flipped site at x = 1, y = 1
+        assert_eq!(lines[10 + 5], "-1");
+        let vectors = 10 + 64;
+        assert_eq!(lines[vectors], "VECTORS coordinates double");
+        assert_eq!(lines[vectors + 1 + 63], "3 3 3");
+        assert_eq!(lines.len(), vectors + 1 + 64);
+    }
+
+    #[test]
+    fn test_xml() {
+        let text = to_xml(&fields());
+        assert!(text.contains("<ImageData WholeExtent=\"0 3 0 3 0 3\""));
+        assert!(text.contains("<PointData Scalars=\"spin\" Vectors=\"coordinates\">"));
+        assert!(text.contains("Name=\"coordinates\" NumberOfComponents=\"3\""));
+        assert_eq!(text.matches("</DataArray>").count(), 2);
+
+        let directory = std::env::temp_dir().join(format!("vtk-{}", std::process::id()));
+        let mut series = VtkSeries::create(&directory, VtkFormat::Xml).unwrap();
+        series.write(0, &fields()).unwrap();
+        series.write(10, &fields()).unwrap();
+        let index = fs::read_to_string(directory.join("lattice.pvd")).unwrap();
+        assert!(
+            index.contains("<DataSet timestep=\"10\" part=\"0\" file=\"lattice_000010.vti\"/>")
+        );
+        assert!(directory.join("lattice_000000.vti").exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+use crate::settings::Settings;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum OutputFormat {
+    // Metadata as '#' comment lines, then a header row and one row per measurement
+    Csv,
+    // Metadata as the first JSON object, then one object per measurement
+    Jsonl,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MetadataValue {
+    Integer(u64),
+    Number(f64),
+    Text(String),
+    Missing,
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        MetadataValue::Number(value)
+    }
+}
+
+impl From<u64> for MetadataValue {
+    fn from(value: u64) -> Self {
+        MetadataValue::Integer(value)
+    }
+}
+
+impl From<usize> for MetadataValue {
+    fn from(value: usize) -> Self {
+        MetadataValue::Integer(value as u64)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::Text(value.to_string())
+    }
+}
+
+impl<T: Into<MetadataValue>> From<Option<T>> for MetadataValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(MetadataValue::Missing, Into::into)
+    }
+}
+
+// Run metadata written once at the top of the output
+#[derive(Debug, PartialEq, Clone)]
+pub struct RunMetadata {
+    pub entries: Vec<(String, MetadataValue)>,
+}
+
+impl RunMetadata {
+    // Every Settings field, the seed, the algorithm and the version of the crate
+    pub fn new(settings: &Settings, seed: Option<u64>, algorithm: &str) -> Self {
+        let interactions = &settings.interactions;
+        let long_range = interactions.long_range.as_ref();
+        let mut metadata = Self {
+            entries: Vec::new(),
+        };
+        metadata
+            .add("version", env!("CARGO_PKG_VERSION"))
+            .add("algorithm", algorithm)
+            .add("seed", seed)
+            .add("dimensions", settings.dimensions)
+            .add("lattice_size", settings.lattice_size)
+            .add("beta", settings.beta)
+            .add(
+                "boundary_conditions",
+                format!("{:?}", settings.boundary_conditions).as_str(),
+            )
+            .add("boundary_axis", settings.boundary_axis)
+            .add(
+                "site_initialisation",
+                format!("{:?}", settings.site_initialisation).as_str(),
+            )
+            .add("magnetic_field", settings.magnetic_field)
+            .add("diagonal_coupling", interactions.diagonal)
+            .add("axial_coupling", interactions.axial)
+            .add("axial_dimension", interactions.axial_dimension)
+            .add("long_range_sigma", long_range.map(|l| l.sigma))
+            .add("long_range_coupling", long_range.map(|l| l.coupling))
+            .add(
+                "long_range_sum",
+                long_range.map(|l| format!("{:?}", l.sum)).as_deref(),
+            );
+        metadata
+    }
+
+    pub fn add<T: Into<MetadataValue>>(&mut self, key: &str, value: T) -> &mut Self {
+        self.entries.push((key.to_string(), value.into()));
+        self
+    }
+}
+
+// JSON string with the characters that need escaping escaped
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// JSON has no infinities or NaN
+fn json_number(value: f64) -> String {
+    match value.is_finite() {
+        true => value.to_string(),
+        false => "null".to_string(),
+    }
+}
+
+// Structured writer of the measurements, one record per measurement with the sweep
+// index, energy, magnetization and the extra columns chosen at creation
+pub struct MeasurementWriter<W: Write> {
+    writer: W,
+    format: OutputFormat,
+    extras: Vec<String>,
+}
+
+impl MeasurementWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: OutputFormat,
+        metadata: &RunMetadata,
+        extras: &[&str],
+    ) -> io::Result<Self> {
+        Self::new(
+            BufWriter::new(File::create(path)?),
+            format,
+            metadata,
+            extras,
+        )
+    }
+
+    // Continue a file written up to `length` bytes, dropping the records after it
+    pub fn resume<P: AsRef<Path>>(
+        path: P,
+        format: OutputFormat,
+        extras: &[&str],
+        length: u64,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(length)?;
+        let mut writer = BufWriter::new(file);
+        writer.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            writer,
+            format,
+            extras: extras.iter().map(|extra| extra.to_string()).collect(),
+        })
+    }
+}
+
+impl<W: Write> MeasurementWriter<W> {
+    pub fn new(
+        mut writer: W,
+        format: OutputFormat,
+        metadata: &RunMetadata,
+        extras: &[&str],
+    ) -> io::Result<Self> {
+        match format {
+            OutputFormat::Csv => {
+                for (key, value) in metadata.entries.iter() {
+                    let value = match value {
+                        MetadataValue::Integer(integer) => integer.to_string(),
+                        MetadataValue::Number(number) => number.to_string(),
+                        MetadataValue::Text(text) => text.clone(),
+                        MetadataValue::Missing => String::new(),
+                    };
+                    writeln!(writer, "# {}: {}", key, value)?;
+                }
+                let columns: Vec<&str> = ["sweep", "energy", "magnetization"]
+                    .into_iter()
+                    .chain(extras.iter().copied())
+                    .collect();
+                writeln!(writer, "{}", columns.join(","))?;
+            }
+            OutputFormat::Jsonl => {
+                let fields: Vec<String> = metadata
+                    .entries
+                    .iter()
+                    .map(|(key, value)| {
+                        let value = match value {
+                            MetadataValue::Integer(integer) => integer.to_string(),
+                            MetadataValue::Number(number) => json_number(*number),
+                            MetadataValue::Text(text) => json_string(text),
+                            MetadataValue::Missing => "null".to_string(),
+                        };
+                        format!("{}:{}", json_string(key), value)
+                    })
+                    .collect();
+                writeln!(writer, "{{\"metadata\":{{{}}}}}", fields.join(","))?;
+            }
+        }
+        Ok(Self {
+            writer,
+            format,
+            extras: extras.iter().map(|extra| extra.to_string()).collect(),
+        })
+    }
+
+    pub fn write(
+        &mut self,
+        sweep: u64,
+        energy: f64,
+        magnetization: f64,
+        extras: &[f64],
+    ) -> io::Result<()> {
+        assert_eq!(
+            extras.len(),
+            self.extras.len(),
+            "Every record needs a value for each extra column"
+        );
+        match self.format {
+            OutputFormat::Csv => {
+                let values: Vec<String> = [energy, magnetization]
+                    .iter()
+                    .chain(extras)
+                    .map(|value| value.to_string())
+                    .collect();
+                writeln!(self.writer, "{},{}", sweep, values.join(","))
+            }
+            OutputFormat::Jsonl => {
+                let fields: Vec<String> = [("energy", energy), ("magnetization", magnetization)]
+                    .into_iter()
+                    .chain(
+                        self.extras
+                            .iter()
+                            .map(String::as_str)
+                            .zip(extras.iter().copied()),
+                    )
+                    .map(|(key, value)| format!("{}:{}", json_string(key), json_number(value)))
+                    .collect();
+                writeln!(self.writer, "{{\"sweep\":{},{}}}", sweep, fields.join(","))
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingsBuilder;
+
+    fn output(format: OutputFormat) -> String {
+        let settings = SettingsBuilder::new().add_beta(0.5).build();
+        let metadata = RunMetadata::new(&settings, Some(7), "metropolis");
+        let mut writer = MeasurementWriter::new(Vec::new(), format, &metadata, &["beta"]).unwrap();
+        writer.write(0, -1.5, 2.0, &[0.5]).unwrap();
+        writer.write(1, f64::NAN, -2.0, &[0.25]).unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let output = output(OutputFormat::Csv);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines.contains(&"# algorithm: metropolis"));
+        assert!(lines.contains(&"# seed: 7"));
+        assert!(lines.contains(&"# beta: 0.5"));
+        assert!(lines.contains(&"# long_range_sigma: "));
+        let header = lines
+            .iter()
+            .position(|line| !line.starts_with('#'))
+            .unwrap();
+        assert_eq!(
+            lines[header..],
+            [
+                "sweep,energy,magnetization,beta",
+                "0,-1.5,2,0.5",
+                "1,NaN,-2,0.25"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jsonl_output() {
+        let output = output(OutputFormat::Jsonl);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("{\"metadata\":{\"version\":"));
+        assert!(lines[0].contains("\"seed\":7,\"dimensions\":3,"));
+        assert!(lines[0].contains("\"boundary_conditions\":\"Periodic\""));
+        assert!(lines[0].contains("\"boundary_axis\":0"));
+        assert!(lines[0].contains("\"axial_dimension\":null"));
+        assert_eq!(
+            lines[1],
+            "{\"sweep\":0,\"energy\":-1.5,\"magnetization\":2,\"beta\":0.5}"
+        );
+        assert_eq!(
+            lines[2],
+            "{\"sweep\":1,\"energy\":null,\"magnetization\":-2,\"beta\":0.25}"
+        );
+        assert_eq!(json_string("a\"b\\\n"), "\"a\\\"b\\\\\\n\"");
+    }
+}
@@ -12,6 +12,7 @@ pub const LATTICE_SIZE: usize = 4; //---TESTS---
 
 use crate::field::initialisation::Initialisation;
 use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
+use crate::geometry::lattice_geometry::interactions::Interactions;
 
 #[derive(Clone)]
 pub struct Settings {
@@ -19,13 +20,21 @@ pub struct Settings {
     pub lattice_size: usize,
     pub beta: f64,
     pub boundary_conditions: BoundaryConditions,
+    // Axis of antiperiodic and fixed boundaries
+    pub boundary_axis: usize,
     pub site_initialisation: Initialisation,
+    pub interactions: Interactions,
+    pub magnetic_field: f64,
 }
 
 pub struct SettingsBuilder {
     pub beta: f64,
     pub boundary_conditions: BoundaryConditions,
+    // Axis of antiperiodic and fixed boundaries
+    pub boundary_axis: usize,
     pub site_initialisation: Initialisation,
+    pub interactions: Interactions,
+    pub magnetic_field: f64,
 }
 
 impl Default for SettingsBuilder {
@@ -39,7 +48,10 @@ impl SettingsBuilder {
         Self {
             beta: 0.0,
             boundary_conditions: BoundaryConditions::Periodic,
+            boundary_axis: 0,
             site_initialisation: Initialisation::Uniform,
+            interactions: Interactions::nearest_neighbour(),
+            magnetic_field: 0.0,
         }
     }
 
@@ -48,7 +60,10 @@ impl SettingsBuilder {
         Self {
             beta,
             boundary_conditions: self.boundary_conditions,
-            site_initialisation: self.site_initialisation,
+            boundary_axis: self.boundary_axis,
+            site_initialisation: self.site_initialisation.clone(),
+            interactions: self.interactions,
+            magnetic_field: self.magnetic_field,
         }
     }
 
@@ -60,7 +75,22 @@ impl SettingsBuilder {
         Self {
             beta: self.beta,
             boundary_conditions,
-            site_initialisation: self.site_initialisation,
+            boundary_axis: self.boundary_axis,
+            site_initialisation: self.site_initialisation.clone(),
+            interactions: self.interactions,
+            magnetic_field: self.magnetic_field,
+        }
+    }
+
+    pub fn add_boundary_axis(&mut self, boundary_axis: usize) -> SettingsBuilder {
+        self.boundary_axis = boundary_axis;
+        Self {
+            beta: self.beta,
+            boundary_conditions: self.boundary_conditions,
+            boundary_axis,
+            site_initialisation: self.site_initialisation.clone(),
+            interactions: self.interactions,
+            magnetic_field: self.magnetic_field,
         }
     }
 
@@ -68,21 +98,56 @@ impl SettingsBuilder {
         &mut self,
         site_initialisation: Initialisation,
     ) -> SettingsBuilder {
-        self.site_initialisation = site_initialisation;
+        self.site_initialisation = site_initialisation.clone();
         Self {
             beta: self.beta,
             boundary_conditions: self.boundary_conditions,
+            boundary_axis: self.boundary_axis,
             site_initialisation,
+            interactions: self.interactions,
+            magnetic_field: self.magnetic_field,
+        }
+    }
+
+    pub fn add_interactions(&mut self, interactions: Interactions) -> SettingsBuilder {
+        self.interactions = interactions;
+        Self {
+            beta: self.beta,
+            boundary_conditions: self.boundary_conditions,
+            boundary_axis: self.boundary_axis,
+            site_initialisation: self.site_initialisation.clone(),
+            interactions,
+            magnetic_field: self.magnetic_field,
+        }
+    }
+
+    pub fn add_magnetic_field(&mut self, magnetic_field: f64) -> SettingsBuilder {
+        self.magnetic_field = magnetic_field;
+        Self {
+            beta: self.beta,
+            boundary_conditions: self.boundary_conditions,
+            boundary_axis: self.boundary_axis,
+            site_initialisation: self.site_initialisation.clone(),
+            interactions: self.interactions,
+            magnetic_field,
         }
     }
 
     pub fn build(self) -> Settings {
+        assert!(
+            self.boundary_axis < DIMENSIONS,
+            "The boundary axis must be below {}",
+            DIMENSIONS
+        );
         Settings {
             dimensions: DIMENSIONS,
             lattice_size: LATTICE_SIZE,
             beta: self.beta,
             boundary_conditions: self.boundary_conditions,
-            site_initialisation: self.site_initialisation,
+            boundary_axis: self.boundary_axis,
+            site_initialisation: self.site_initialisation.clone(),
+            interactions: self.interactions,
+            magnetic_field: self.magnetic_field,
         }
     }
 }
@@ -108,7 +173,10 @@ mod tests {
         let settings = SettingsBuilder {
             beta: 1.0,
             boundary_conditions: BoundaryConditions::Periodic,
+            boundary_axis: 0,
             site_initialisation: Initialisation::Uniform,
+            interactions: Interactions::nearest_neighbour(),
+            magnetic_field: 0.0,
         }
         .build();
         assert_eq!(settings.dimensions, DIMENSIONS);
@@ -116,5 +184,36 @@ mod tests {
         assert_eq!(settings.beta, 1.0);
         assert_eq!(settings.boundary_conditions, BoundaryConditions::Periodic);
         assert_eq!(settings.site_initialisation, Initialisation::Uniform);
+        assert_eq!(settings.interactions, Interactions::nearest_neighbour());
+        assert_eq!(settings.magnetic_field, 0.0);
+    }
+
+    #[test]
+    fn test_settings_builder_add_magnetic_field() {
+        let settings = SettingsBuilder::new()
+            .add_beta(0.5)
+            .add_magnetic_field(0.1)
+            .build();
+        assert_eq!(settings.beta, 0.5);
+        assert_eq!(settings.magnetic_field, 0.1);
+    }
+
+    #[test]
+    fn test_settings_builder_add_interactions() {
+        let settings = SettingsBuilder::new()
+            .add_interactions(Interactions::annni(0.5, 0))
+            .build();
+        assert_eq!(settings.interactions.axial, -0.5);
+        assert_eq!(settings.interactions.axial_dimension, Some(0));
+    }
+
+    #[test]
+    fn test_settings_builder_add_boundary_axis() {
+        let settings = SettingsBuilder::new()
+            .add_boundary_conditions(BoundaryConditions::Fixed)
+            .add_boundary_axis(2)
+            .build();
+        assert_eq!(settings.boundary_conditions, BoundaryConditions::Fixed);
+        assert_eq!(settings.boundary_axis, 2);
     }
 }
@@ -0,0 +1,139 @@
+use crate::statistics::structure_factor::{Complex, fft};
+
+// Window constant c of Sokal's criterion W >= c tau_int(W)
+const SOKAL_CONSTANT: f64 = 6.0;
+// Ratio S = tau / tau_int of Wolff's Gamma-method
+const WOLFF_RATIO: f64 = 1.5;
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum Windowing {
+    Sokal,
+    Wolff,
+}
+
+// tau_int = 1/2 + sum_{t=1}^{W} rho(t) with its statistical error
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AutocorrelationTime {
+    pub tau: f64,
+    pub error: f64,
+    pub window: usize,
+}
+
+// Normalised autocorrelation function rho(t) for t = 0..n, computed by FFT of the
+// series padded with zeros to avoid the periodic wrap-around
+pub fn autocorrelation(series: &[f64]) -> Vec<f64> {
+    let n = series.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let mean = series.iter().sum::<f64>() / n as f64;
+    let size = (2 * n).next_power_of_two();
+    let mut padded: Vec<Complex> = series.iter().map(|x| Complex::new(x - mean, 0.0)).collect();
+    padded.resize(size, Complex::default());
+
+    // Wiener-Khinchin: the autocovariance is the transform of the power spectrum,
+    // the conjugate transform only reverses the order of the momenta
+    let power: Vec<Complex> = fft(&padded)
+        .iter()
+        .map(|value| Complex::new(value.norm_squared(), 0.0))
+        .collect();
+    let transform = fft(&power);
+    let covariance: Vec<f64> = (0..n)
+        .map(|t| transform[(size - t) % size].re / (size * (n - t)) as f64)
+        .collect();
+
+    // A constant series is not correlated
+    if covariance[0] <= 0.0 {
+        let mut rho = vec![0.0; n];
+        rho[0] = 1.0;
+        return rho;
+    }
+    covariance.iter().map(|c| c / covariance[0]).collect()
+}
+
+// Integrated autocorrelation time with an automatically chosen summation window
+pub fn integrated_autocorrelation_time(
+    series: &[f64],
+    windowing: Windowing,
+) -> AutocorrelationTime {
+    let n = series.len();
+    let rho = autocorrelation(series);
+    let mut tau = 0.5;
+    let mut window = 0;
+
+    for (t, rho_t) in rho.iter().enumerate().skip(1) {
+        tau += rho_t;
+        window = t;
+        let stop = match windowing {
+            // Stop as soon as the window exceeds a few autocorrelation times
+            Windowing::Sokal => t as f64 >= SOKAL_CONSTANT * tau,
+            // Minimise the sum of the systematic and statistical errors
+            Windowing::Wolff => {
+                let tau_w = if tau <= 0.5 {
+                    f64::MIN_POSITIVE
+                } else {
+                    WOLFF_RATIO / ((2.0 * tau + 1.0) / (2.0 * tau - 1.0)).ln()
+                };
+                (-(t as f64) / tau_w).exp() - tau_w / (t as f64 * n as f64).sqrt() < 0.0
+            }
+        };
+        if stop {
+            break;
+        }
+    }
+
+    AutocorrelationTime {
+        tau,
+        // Madras-Sokal variance 2 (2 W + 1) tau^2 / n
+        error: (2.0 * (2.0 * window as f64 + 1.0) / n as f64).sqrt() * tau,
+        window,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_autocorrelation() {
+        let series = [1.0, 3.0, 2.0, 5.0, 4.0, 1.0];
+        let mean = series.iter().sum::<f64>() / 6.0;
+        let covariance = |t: usize| {
+            (0..6 - t)
+                .map(|i| (series[i] - mean) * (series[i + t] - mean))
+                .sum::<f64>()
+                / (6 - t) as f64
+        };
+        let rho = autocorrelation(&series);
+        for (t, rho_t) in rho.iter().enumerate() {
+            assert!((rho_t - covariance(t) / covariance(0)).abs() < 1e-9);
+        }
+        assert_eq!(autocorrelation(&[2.0, 2.0]), vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_integrated_autocorrelation_time() {
+        // AR(1) process x_t = a x_{t-1} + noise: tau_int = (1 + a) / (2 (1 - a))
+        let a: f64 = 0.8;
+        let mut rng = rand::rng();
+        let mut x = 0.0;
+        let series: Vec<f64> = (0..20000)
+            .map(|_| {
+                x = a * x + rng.random_range(-1.0..1.0);
+                x
+            })
+            .collect();
+        let expected = (1.0 + a) / (2.0 * (1.0 - a));
+        for windowing in [Windowing::Sokal, Windowing::Wolff] {
+            let tau = integrated_autocorrelation_time(&series, windowing);
+            assert!((tau.tau - expected).abs() < 5.0 * tau.error.max(0.1));
+            assert!(tau.window > 0);
+        }
+
+        // Uncorrelated samples
+        let series: Vec<f64> = (0..10000).map(|_| rng.random_range(0.0..1.0)).collect();
+        let tau = integrated_autocorrelation_time(&series, Windowing::Sokal);
+        assert!((tau.tau - 0.5).abs() < 0.1);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::statistics::jackknife::Estimate;
+
+// Fewest bins an error estimate is based on
+const MIN_BINS: usize = 32;
+
+// Means of consecutive bins of equal size, a leftover partial bin is dropped
+pub fn bin_means(series: &[f64], bin_size: usize) -> Vec<f64> {
+    series
+        .chunks_exact(bin_size)
+        .map(|bin| bin.iter().sum::<f64>() / bin_size as f64)
+        .collect()
+}
+
+fn naive_error(series: &[f64]) -> f64 {
+    let n = series.len() as f64;
+    let mean = series.iter().sum::<f64>() / n;
+    (series.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n * (n - 1.0))).sqrt()
+}
+
+// Error of the mean against the bin size, doubled at every level while enough bins
+// remain. Once the bins are longer than the autocorrelation time the error reaches a
+// plateau, so the largest bin size gives the estimate.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BinningAnalysis {
+    pub levels: Vec<(usize, f64)>,
+    pub estimate: Estimate,
+}
+
+impl BinningAnalysis {
+    pub fn new(series: &[f64]) -> Self {
+        let mut levels = Vec::new();
+        let mut bin_size = 1;
+        while series.len() / bin_size >= MIN_BINS.min(series.len()).max(2) {
+            levels.push((bin_size, naive_error(&bin_means(series, bin_size))));
+            bin_size *= 2;
+        }
+
+        let value = series.iter().sum::<f64>() / series.len() as f64;
+        let error = levels.last().map_or(f64::NAN, |(_, error)| *error);
+        Self {
+            levels,
+            estimate: Estimate { value, error },
+        }
+    }
+
+    // Integrated autocorrelation time implied by the growth of the error
+    pub fn autocorrelation_time(&self) -> f64 {
+        match (self.levels.first(), self.levels.last()) {
+            (Some((_, first)), Some((_, last))) if *first > 0.0 => 0.5 * (last / first).powi(2),
+            _ => f64::NAN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bin_means() {
+        assert_eq!(bin_means(&[1.0, 3.0, 2.0, 4.0, 9.0], 2), vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_binning_analysis() {
+        // Alternating series: bins of two are constant, so the error drops to zero
+        let series: Vec<f64> = (0..256).map(|i| (i % 2) as f64).collect();
+        let binning = BinningAnalysis::new(&series);
+        assert_eq!(binning.estimate.value, 0.5);
+        assert_eq!(binning.levels.len(), 4);
+        assert_eq!(binning.levels[1], (2, 0.0));
+        assert_eq!(binning.estimate.error, 0.0);
+
+        // Runs of 8 equal values: the error grows until the bins cover the runs
+        let series: Vec<f64> = (0..1024).map(|i| ((i / 8) % 3) as f64).collect();
+        let binning = BinningAnalysis::new(&series);
+        assert!(binning.levels[3].1 > 2.0 * binning.levels[0].1);
+        assert!(binning.autocorrelation_time() > 1.0);
+
+        assert!(BinningAnalysis::new(&[1.0]).estimate.error.is_nan());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::field::ising::IsingField;
+use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::geometry::utils::{lattice_to_position, position_to_lattice};
+use crate::output::checkpoint::{Checkpoint, CheckpointReader, CheckpointWriter};
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+use crate::statistics::jackknife::{Estimate, jackknife_mean};
+use crate::statistics::structure_factor::{Complex, lattice_fft};
+use std::io;
+
+// Correlation at one distance, one estimate per axis or a single one
+#[derive(Debug, PartialEq, Clone)]
+pub struct CorrelationRow {
+    pub distance: f64,
+    pub correlations: Vec<Estimate>,
+}
+
+// Two-point function G(r) = <s_x s_{x+r}> on the periodic lattice, averaged over all
+// origins x. Every measurement stores G for all displacements r, which are grouped
+// along the axes and by their minimum-image distance.
+#[derive(Debug, Clone)]
+pub struct Correlations {
+    // Lattice offset of every displacement
+    offsets: Vec<[usize; DIMENSIONS]>,
+    // Distance shell of every displacement
+    shells: Vec<usize>,
+    pub distances: Vec<f64>,
+    multiplicities: Vec<usize>,
+    samples: Vec<Vec<f64>>,
+}
+
+impl Default for Correlations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Correlations {
+    pub fn new() -> Self {
+        let sites = usize::pow(LATTICE_SIZE, DIMENSIONS as u32);
+        let offsets: Vec<[usize; DIMENSIONS]> = (0..sites).map(position_to_lattice).collect();
+
+        // Squared minimum-image distances are integers, so they identify the shells
+        let squared: Vec<usize> = offsets
+            .iter()
+            .map(|offset| {
+                offset
+                    .iter()
+                    .map(|x| x.min(&(LATTICE_SIZE - x)).pow(2))
+                    .sum()
+            })
+            .collect();
+        let mut distinct = squared.clone();
+        distinct.sort();
+        distinct.dedup();
+
+        let shells: Vec<usize> = squared
+            .iter()
+            .map(|d| distinct.binary_search(d).unwrap())
+            .collect();
+        let mut multiplicities = vec![0; distinct.len()];
+        shells.iter().for_each(|shell| multiplicities[*shell] += 1);
+
+        Self {
+            offsets,
+            shells,
+            distances: distinct.iter().map(|d| (*d as f64).sqrt()).collect(),
+            multiplicities,
+            samples: Vec::new(),
+        }
+    }
+
+    // Standard estimator from a single configuration
+    pub fn measure(&mut self, lattice: &Lattice) {
+        assert_eq!(
+            lattice.settings.boundary_conditions,
+            BoundaryConditions::Periodic,
+            "Correlations are measured on the periodic lattice"
+        );
+        let fields = lattice.get_fields();
+        self.measure_fields(&fields);
+    }
+
+    pub fn measure_fields(&mut self, fields: &[IsingField]) {
+        let values = fields.iter().map(|field| field.value()).collect();
+        self.samples
+            .push(autocorrelation(values, fields.len() as f64));
+    }
+
+    // Improved estimator of a Wolff cluster at zero field: <s_x s_y> is the probability
+    // that y joins the cluster of x, so G(r) = <|{x in C : x + r in C}| / |C|>
+    pub fn measure_cluster(&mut self, cluster: &[usize]) {
+        let mut in_cluster = vec![0.0; self.offsets.len()];
+        cluster.iter().for_each(|x| in_cluster[*x] = 1.0);
+        self.samples
+            .push(autocorrelation(in_cluster, cluster.len() as f64));
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    fn estimate<F: Fn(&[f64]) -> f64>(&self, observable: F, blocks: usize) -> Estimate {
+        let series: Vec<f64> = self
+            .samples
+            .iter()
+            .map(|sample| observable(sample))
+            .collect();
+        jackknife_mean(&series, blocks)
+    }
+
+    // G(r) along every axis for r = 0..=L/2
+    pub fn axis_rows(&self, blocks: usize) -> Vec<CorrelationRow> {
+        (0..=LATTICE_SIZE / 2)
+            .map(|r| CorrelationRow {
+                distance: r as f64,
+                correlations: (0..DIMENSIONS)
+                    .map(|dimension| {
+                        let mut offset = [0; DIMENSIONS];
+                        offset[dimension] = r;
+                        let displacement = lattice_to_position(offset);
+                        self.estimate(|sample| sample[displacement], blocks)
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    // G(r) averaged over all displacements at the same minimum-image distance
+    pub fn distance_rows(&self, blocks: usize) -> Vec<CorrelationRow> {
+        (0..self.distances.len())
+            .map(|shell| {
+                let average = |sample: &[f64]| {
+                    sample
+                        .iter()
+                        .zip(self.shells.iter())
+                        .filter(|(_, s)| **s == shell)
+                        .map(|(g, _)| g)
+                        .sum::<f64>()
+                        / self.multiplicities[shell] as f64
+                };
+                CorrelationRow {
+                    distance: self.distances[shell],
+                    correlations: vec![self.estimate(average, blocks)],
+                }
+            })
+            .collect()
+    }
+}
+
+// sum_x v_x v_{x+r} / norm for every displacement r, by the Wiener-Khinchin theorem:
+// |v(k)|^2 is real and even, so transforming it forward gives N times the sum. The
+// values are integers, and so are the sums once the rounding of the FFT is removed.
+fn autocorrelation(values: Vec<f64>, norm: f64) -> Vec<f64> {
+    let sites = values.len() as f64;
+    let transform = lattice_fft(values.into_iter().map(|v| Complex::new(v, 0.0)).collect());
+    let power = transform
+        .iter()
+        .map(|value| Complex::new(value.norm_squared(), 0.0))
+        .collect();
+    lattice_fft(power)
+        .iter()
+        .map(|value| (value.re / sites).round() / norm)
+        .collect()
+}
+
+// Only the samples are saved, the rest follows from the lattice geometry
+impl Checkpoint for Correlations {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.u64(self.samples.len() as u64);
+        self.samples.iter().for_each(|sample| writer.f64s(sample));
+    }
+
+    fn load(reader: &mut CheckpointReader) -> io::Result<Self> {
+        let samples = (0..reader.u64()?)
+            .map(|_| reader.f64s())
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            samples,
+            ..Self::new()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::initialisation::Initialisation;
+    use crate::settings::SettingsBuilder;
+
+    #[test]
+    fn test_correlations_uniform() {
+        let lattice = Lattice::new(SettingsBuilder::new().build()).unwrap();
+        let mut correlations = Correlations::new();
+        correlations.measure(&lattice);
+        correlations.measure(&lattice);
+
+        // The nearest neighbours form the first shell after the origin
+        assert_eq!(correlations.distances[0], 0.0);
+        assert_eq!(correlations.distances[1], 1.0);
+        assert_eq!(correlations.multiplicities[1], 2 * DIMENSIONS);
+
+        for row in correlations.axis_rows(2) {
+            assert_eq!(row.correlations.len(), DIMENSIONS);
+            assert!(
+                row.correlations
+                    .iter()
+                    .all(|g| g.value == 1.0 && g.error == 0.0)
+            );
+        }
+        assert_eq!(
+            correlations.distance_rows(2).len(),
+            correlations.distances.len()
+        );
+    }
+
+    #[test]
+    fn test_correlations_antiferromagnet() {
+        // Neel state: G(r) = (-1)^r along the axes
+        let fields: Vec<IsingField> = (0..usize::pow(LATTICE_SIZE, DIMENSIONS as u32))
+            .map(
+                |position| match position_to_lattice(position).iter().sum::<usize>() % 2 {
+                    0 => IsingField::Up,
+                    _ => IsingField::Down,
+                },
+            )
+            .collect();
+        let mut correlations = Correlations::new();
+        correlations.measure_fields(&fields);
+        for row in correlations.axis_rows(1) {
+            let expected = if (row.distance as usize).is_multiple_of(2) {
+                1.0
+            } else {
+                -1.0
+            };
+            assert!(row.correlations.iter().all(|g| g.value == expected));
+        }
+    }
+
+    #[test]
+    fn test_correlations_direct_sum() {
+        let lattice = Lattice::new(
+            SettingsBuilder::new()
+                .add_site_initialisation(Initialisation::Random)
+                .build(),
+        )
+        .unwrap();
+        let fields = lattice.get_fields();
+        let mut correlations = Correlations::new();
+        correlations.measure_fields(&fields);
+        for (displacement, offset) in correlations.offsets.iter().enumerate() {
+            let direct: f64 = (0..fields.len())
+                .map(|x| {
+                    let partner = lattice_to_position(
+                        position_to_lattice(x)
+                            .iter()
+                            .zip(offset)
+                            .map(|(x, offset)| (x + offset) % LATTICE_SIZE)
+                            .collect::<Vec<usize>>()
+                            .try_into()
+                            .unwrap(),
+                    );
+                    fields[x].value() * fields[partner].value()
+                })
+                .sum();
+            assert_eq!(
+                correlations.samples[0][displacement],
+                direct / fields.len() as f64
+            );
+        }
+    }
+
+    #[test]
+    fn test_correlations_improved_estimator() {
+        let mut correlations = Correlations::new();
+
+        // A single site only correlates with itself, the whole lattice with everything
+        correlations.measure_cluster(&[5]);
+        let rows = correlations.axis_rows(1);
+        assert_eq!(rows[0].correlations[0].value, 1.0);
+        assert_eq!(rows[1].correlations[0].value, 0.0);
+
+        let lattice = Lattice::new(
+            SettingsBuilder::new()
+                .add_site_initialisation(Initialisation::Random)
+                .build(),
+        )
+        .unwrap();
+        let mut correlations = Correlations::new();
+        correlations.measure_cluster(&(0..lattice.len()).collect::<Vec<usize>>());
+        assert!(
+            correlations
+                .distance_rows(1)
+                .iter()
+                .all(|row| row.correlations[0].value == 1.0)
+        );
+    }
+}
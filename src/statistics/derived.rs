@@ -0,0 +1,133 @@
+use crate::statistics::binning::bin_means;
+use crate::statistics::jackknife::{Estimate, jackknife_error};
+use rand::Rng;
+
+// Resampling of derived quantities, which are nonlinear in the primary averages
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum ErrorMethod {
+    Jackknife,
+    Bootstrap,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Resampling {
+    pub method: ErrorMethod,
+    // Blocks the series are cut into, longer than the autocorrelation time
+    pub blocks: usize,
+    pub bootstrap_samples: usize,
+}
+
+impl Resampling {
+    pub fn new(method: ErrorMethod, blocks: usize) -> Self {
+        Self {
+            method,
+            blocks,
+            bootstrap_samples: 200,
+        }
+    }
+
+    // Estimate of f applied to the means of the series. The series are binned into
+    // blocks, then the blocks are left out one at a time (jackknife) or drawn with
+    // replacement (bootstrap).
+    pub fn estimate<F: Fn(&[f64]) -> f64>(&self, series: &[&[f64]], f: F) -> Estimate {
+        let length = series.iter().map(|s| s.len()).min().unwrap_or(0);
+        let mean = |s: &[f64]| s.iter().sum::<f64>() / s.len() as f64;
+        let value = f(&series.iter().map(|s| mean(s)).collect::<Vec<f64>>());
+
+        let blocks = self.blocks.min(length);
+        if blocks < 2 {
+            return Estimate {
+                value,
+                error: f64::NAN,
+            };
+        }
+        let binned: Vec<Vec<f64>> = series
+            .iter()
+            .map(|s| {
+                let mut bins = bin_means(s, length / blocks);
+                bins.truncate(blocks);
+                bins
+            })
+            .collect();
+        // Means of the blocks with the given multiplicities
+        let resampled = |weights: &[f64]| {
+            let total = weights.iter().sum::<f64>();
+            let means: Vec<f64> = binned
+                .iter()
+                .map(|bins| bins.iter().zip(weights).map(|(b, w)| b * w).sum::<f64>() / total)
+                .collect();
+            f(&means)
+        };
+
+        let error = match self.method {
+            ErrorMethod::Jackknife => {
+                let samples: Vec<f64> = (0..blocks)
+                    .map(|left_out| {
+                        let weights: Vec<f64> = (0..blocks)
+                            .map(|block| (block != left_out) as u8 as f64)
+                            .collect();
+                        resampled(&weights)
+                    })
+                    .collect();
+                jackknife_error(&samples)
+            }
+            ErrorMethod::Bootstrap => {
+                let mut rng = rand::rng();
+                let samples: Vec<f64> = (0..self.bootstrap_samples)
+                    .map(|_| {
+                        let mut weights = vec![0.0; blocks];
+                        for _ in 0..blocks {
+                            weights[rng.random_range(0..blocks)] += 1.0;
+                        }
+                        resampled(&weights)
+                    })
+                    .collect();
+                let n = samples.len() as f64;
+                let mean = samples.iter().sum::<f64>() / n;
+                (samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+            }
+        };
+        Estimate { value, error }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resampling_mean() {
+        // For the plain mean the jackknife reproduces the standard error of the blocks
+        let series: Vec<f64> = (0..40).map(|i| ((i * 7) % 11) as f64).collect();
+        let resampling = Resampling::new(ErrorMethod::Jackknife, 40);
+        let estimate = resampling.estimate(&[&series], |means| means[0]);
+        let n = series.len() as f64;
+        let mean = series.iter().sum::<f64>() / n;
+        let variance = series.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        assert!((estimate.value - mean).abs() < 1e-12);
+        assert!((estimate.error - (variance / n).sqrt()).abs() < 1e-12);
+
+        // Bootstrap agrees within its own noise
+        let resampling = Resampling {
+            bootstrap_samples: 2000,
+            ..Resampling::new(ErrorMethod::Bootstrap, 40)
+        };
+        let bootstrap = resampling.estimate(&[&series], |means| means[0]);
+        assert!((bootstrap.error / estimate.error - 1.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_resampling_derived() {
+        // Variance <x^2> - <x>^2 of a series with no fluctuations between blocks
+        let x: Vec<f64> = (0..64).map(|i| (i % 2) as f64).collect();
+        let x2: Vec<f64> = x.iter().map(|x| x * x).collect();
+        for method in [ErrorMethod::Jackknife, ErrorMethod::Bootstrap] {
+            let estimate = Resampling::new(method, 8)
+                .estimate(&[&x, &x2], |means| means[1] - means[0] * means[0]);
+            assert!((estimate.value - 0.25).abs() < 1e-12);
+            assert!(estimate.error < 1e-12);
+        }
+        let estimate = Resampling::new(ErrorMethod::Jackknife, 1).estimate(&[&x], |m| m[0]);
+        assert!(estimate.error.is_nan());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::field::ising::IsingField;
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::geometry::utils::position_to_lattice;
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+
+// Nearest-neighbour bonds with opposite spins, the elements of the domain walls on the
+// dual lattice
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DomainWalls {
+    pub broken_bonds: [usize; DIMENSIONS],
+    pub bonds: [usize; DIMENSIONS],
+}
+
+impl DomainWalls {
+    // Counts the bonds to the next neighbours, which open and fixed boundaries leave
+    // out. Bonds across antiperiodic boundaries are broken between equal spins.
+    pub fn new(lattice: &Lattice) -> Self {
+        let mut broken_bonds = [0; DIMENSIONS];
+        let mut bonds = [0; DIMENSIONS];
+        for position in 0..lattice.len() {
+            let site = lattice.get(position);
+            let site = site.read().unwrap();
+            for (d, next) in site.next.iter().enumerate() {
+                if let Some(next) = next {
+                    bonds[d] += 1;
+                    let aligned = next.read().unwrap().field == site.field;
+                    if aligned != (site.next_coupling[d] > 0.0) {
+                        broken_bonds[d] += 1;
+                    }
+                }
+            }
+        }
+        Self {
+            broken_bonds,
+            bonds,
+        }
+    }
+
+    // Fraction of broken bonds along every axis
+    pub fn densities(&self) -> [f64; DIMENSIONS] {
+        let mut densities = [0.0; DIMENSIONS];
+        for (d, density) in densities.iter_mut().enumerate() {
+            *density = self.broken_bonds[d] as f64 / self.bonds[d] as f64;
+        }
+        densities
+    }
+
+    // Length (area in 3D) of the domain walls in lattice units
+    pub fn length(&self) -> usize {
+        self.broken_bonds.iter().sum()
+    }
+
+    // Characteristic domain size L = 1 / rho from the mean broken-bond density, which
+    // grows as t^(1/2) during coarsening after a quench
+    pub fn domain_size(&self) -> f64 {
+        let density = self.densities().iter().sum::<f64>() / DIMENSIONS as f64;
+        1.0 / density
+    }
+}
+
+// Interface perpendicular to an axis, as between fixed opposite boundaries or forced by
+// antiperiodic ones. The height of the interface on every line along the axis is the
+// number of up spins on it, which is exact for a single interface without overhangs
+// (and, with antiperiodic boundaries, away from the reversed bonds).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Interface {
+    // Mean height over the lines
+    pub position: f64,
+    // Width w = sqrt(<(h - <h>)^2>)
+    pub roughness: f64,
+}
+
+impl Interface {
+    pub fn new(fields: &[IsingField], axis: usize) -> Self {
+        assert!(
+            axis < DIMENSIONS,
+            "The interface axis must be below {}",
+            DIMENSIONS
+        );
+        let lines = fields.len() / LATTICE_SIZE;
+        let stride = usize::pow(LATTICE_SIZE, axis as u32);
+        let heights: Vec<f64> = (0..fields.len())
+            .filter(|position| position_to_lattice(*position)[axis] == 0)
+            .map(|start| {
+                (0..LATTICE_SIZE)
+                    .filter(|i| fields[start + i * stride] == IsingField::Up)
+                    .count() as f64
+            })
+            .collect();
+
+        let position = heights.iter().sum::<f64>() / lines as f64;
+        let variance = heights.iter().map(|h| (h - position).powi(2)).sum::<f64>() / lines as f64;
+        Self {
+            position,
+            roughness: variance.sqrt(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
+    use crate::settings::SettingsBuilder;
+
+    // Up spins below the height given for every position
+    fn lattice_with(boundary: BoundaryConditions, height: impl Fn(usize) -> usize) -> Lattice {
+        let mut lattice = Lattice::new(
+            SettingsBuilder::new()
+                .add_boundary_conditions(boundary)
+                .build(),
+        )
+        .unwrap();
+        for position in 0..lattice.len() {
+            lattice.get(position).write().unwrap().field =
+                match position_to_lattice(position)[0] < height(position) {
+                    true => IsingField::Up,
+                    false => IsingField::Down,
+                };
+        }
+        lattice.refresh_totals();
+        lattice
+    }
+
+    #[test]
+    fn test_domain_walls() {
+        let lattice = lattice_with(BoundaryConditions::Periodic, |_| 0);
+        let walls = DomainWalls::new(&lattice);
+        assert_eq!(walls.length(), 0);
+        assert_eq!(walls.domain_size(), f64::INFINITY);
+
+        // A slab has two walls of 16 bonds with periodic boundaries, one with open ones
+        let lattice = lattice_with(BoundaryConditions::Periodic, |_| 2);
+        let walls = DomainWalls::new(&lattice);
+        assert_eq!(walls.broken_bonds, [32, 0, 0]);
+        assert_eq!(walls.densities(), [0.5, 0.0, 0.0]);
+        assert_eq!(walls.domain_size(), 6.0);
+
+        let lattice = lattice_with(BoundaryConditions::Open, |_| 2);
+        let walls = DomainWalls::new(&lattice);
+        assert_eq!(walls.bonds, [48; DIMENSIONS]);
+        assert_eq!(walls.length(), 16);
+
+        // Antiperiodic boundaries break the reversed bonds of a uniform lattice
+        let lattice = lattice_with(BoundaryConditions::Antiperiodic, |_| 0);
+        let walls = DomainWalls::new(&lattice);
+        assert_eq!(walls.broken_bonds, [16, 0, 0]);
+        let lattice = lattice_with(BoundaryConditions::Antiperiodic, |_| 2);
+        assert_eq!(DomainWalls::new(&lattice).broken_bonds, [16, 0, 0]);
+    }
+
+    #[test]
+    fn test_interface() {
+        let lattice = lattice_with(BoundaryConditions::Fixed, |_| 2);
+        let interface = Interface::new(&lattice.get_fields(), 0);
+        assert_eq!(interface.position, 2.0);
+        assert_eq!(interface.roughness, 0.0);
+
+        // Steps of heights 1 and 3 along the second axis
+        let lattice = lattice_with(BoundaryConditions::Fixed, |position| {
+            1 + 2 * (position_to_lattice(position)[1] % 2)
+        });
+        let interface = Interface::new(&lattice.get_fields(), 0);
+        assert_eq!(interface.position, 2.0);
+        assert_eq!(interface.roughness, 1.0);
+        // Lines along the second axis do not cross a single interface
+        let interface = Interface::new(&lattice.get_fields(), 1);
+        assert!((interface.roughness - 2.0_f64.sqrt()).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,84 @@
+// Estimate of a quantity with its statistical error
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Estimate {
+    pub value: f64,
+    pub error: f64,
+}
+
+// Copy of a series without the given block, out of `blocks` blocks of equal length
+pub fn remove_block<T: Clone>(series: &[T], block: usize, blocks: usize) -> Vec<T> {
+    assert!(
+        blocks <= series.len(),
+        "Every block needs at least one element"
+    );
+    let length = series.len() / blocks;
+    let (from, to) = (block * length, (block + 1) * length);
+    series[..from]
+        .iter()
+        .chain(series[to..].iter())
+        .cloned()
+        .collect()
+}
+
+// Jackknife error from the estimates on the leave-one-block-out samples, unknown with
+// fewer than 2 of them
+pub fn jackknife_error(estimates: &[f64]) -> f64 {
+    if estimates.len() < 2 {
+        return f64::NAN;
+    }
+    let n = estimates.len() as f64;
+    let mean = estimates.iter().sum::<f64>() / n;
+    ((n - 1.0) / n * estimates.iter().map(|x| (x - mean).powi(2)).sum::<f64>()).sqrt()
+}
+
+// Mean of a series with the jackknife error of `blocks` blocks, which accounts for
+// autocorrelations shorter than a block. The error is unknown with fewer than 2 blocks.
+pub fn jackknife_mean(series: &[f64], blocks: usize) -> Estimate {
+    let mean = |series: &[f64]| series.iter().sum::<f64>() / series.len() as f64;
+    let blocks = blocks.min(series.len());
+    let error = match blocks {
+        0 | 1 => f64::NAN,
+        _ => jackknife_error(
+            &(0..blocks)
+                .map(|block| mean(&remove_block(series, block, blocks)))
+                .collect::<Vec<f64>>(),
+        ),
+    };
+    Estimate {
+        value: mean(series),
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_block() {
+        let series = [1, 2, 3, 4, 5, 6];
+        assert_eq!(remove_block(&series, 0, 3), vec![3, 4, 5, 6]);
+        assert_eq!(remove_block(&series, 2, 3), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_jackknife_error() {
+        // Jackknife of the mean reproduces the standard error of the mean
+        let series = [1.0, 2.0, 4.0, 7.0];
+        let estimates: Vec<f64> = (0..4)
+            .map(|i| remove_block(&series, i, 4).iter().sum::<f64>() / 3.0)
+            .collect();
+        let mean = series.iter().sum::<f64>() / 4.0;
+        let variance = series.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 3.0;
+        assert!((jackknife_error(&estimates) - (variance / 4.0).sqrt()).abs() < 1e-12);
+
+        let estimate = jackknife_mean(&series, 4);
+        assert_eq!(estimate.value, mean);
+        assert!((estimate.error - (variance / 4.0).sqrt()).abs() < 1e-12);
+        assert!(jackknife_mean(&series, 1).error.is_nan());
+
+        // Fewer measurements than blocks: one block per measurement
+        assert_eq!(jackknife_mean(&series, 10), estimate);
+        assert!(jackknife_error(&[1.0]).is_nan());
+    }
+}
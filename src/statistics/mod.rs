@@ -0,0 +1,12 @@
+pub mod autocorrelation;
+pub mod binning;
+pub mod correlations;
+pub mod derived;
+pub mod domain_walls;
+pub mod jackknife;
+pub mod percolation;
+pub mod reweighting;
+pub mod structure_factor;
+pub mod summary;
+pub mod thermalization;
+pub mod time_series;
@@ -0,0 +1,438 @@
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::output::checkpoint::{Checkpoint, CheckpointReader, CheckpointWriter};
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+use crate::statistics::jackknife::{Estimate, jackknife_mean};
+use rand::Rng;
+use std::io;
+
+#[derive(Debug, PartialEq, Clone, Copy, clap::ValueEnum)]
+pub enum ClusterKind {
+    // Nearest neighbours with equal spins
+    Geometric,
+    // Bonds between equal nearest neighbours occupied with probability 1 - exp(-2 beta)
+    FortuinKasteleyn,
+}
+
+// Union-find over the sites which keeps the unwrapped displacement of every site from
+// its parent. A bond closing a loop with a nonzero winding means the cluster wraps
+// around the periodic lattice.
+struct UnionFind {
+    parent: Vec<usize>,
+    offset: Vec<[isize; DIMENSIONS]>,
+    size: Vec<usize>,
+    wraps: Vec<[bool; DIMENSIONS]>,
+}
+
+impl UnionFind {
+    fn new(sites: usize) -> Self {
+        Self {
+            parent: (0..sites).collect(),
+            offset: vec![[0; DIMENSIONS]; sites],
+            size: vec![1; sites],
+            wraps: vec![[false; DIMENSIONS]; sites],
+        }
+    }
+
+    // Root of a site and the displacement of the site from it
+    fn find(&mut self, x: usize) -> (usize, [isize; DIMENSIONS]) {
+        let parent = self.parent[x];
+        if parent == x {
+            return (x, [0; DIMENSIONS]);
+        }
+        let (root, parent_offset) = self.find(parent);
+        for (offset, parent_offset) in self.offset[x].iter_mut().zip(parent_offset) {
+            *offset += parent_offset;
+        }
+        self.parent[x] = root;
+        (root, self.offset[x])
+    }
+
+    // Bond from x to y, with y displaced by `displacement` from x
+    fn union(&mut self, x: usize, y: usize, displacement: [isize; DIMENSIONS]) {
+        let (root_x, offset_x) = self.find(x);
+        let (root_y, offset_y) = self.find(y);
+        if root_x == root_y {
+            for d in 0..DIMENSIONS {
+                if offset_x[d] + displacement[d] != offset_y[d] {
+                    self.wraps[root_x][d] = true;
+                }
+            }
+            return;
+        }
+
+        // Displacement of the root of y from the root of x
+        let mut delta = [0; DIMENSIONS];
+        for d in 0..DIMENSIONS {
+            delta[d] = displacement[d] + offset_x[d] - offset_y[d];
+        }
+        let (root, child) = if self.size[root_x] >= self.size[root_y] {
+            (root_x, root_y)
+        } else {
+            delta = delta.map(|x| -x);
+            (root_y, root_x)
+        };
+        self.parent[child] = root;
+        self.offset[child] = delta;
+        self.size[root] += self.size[child];
+        for d in 0..DIMENSIONS {
+            self.wraps[root][d] |= self.wraps[child][d];
+        }
+    }
+}
+
+// Clusters of a single configuration
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClusterLabels {
+    // Cluster of every site, numbered in the order of the scan
+    pub labels: Vec<usize>,
+    pub sizes: Vec<usize>,
+    // Whether the cluster wraps around (periodic) or spans (open) along every axis
+    pub percolating: Vec<[bool; DIMENSIONS]>,
+}
+
+impl ClusterLabels {
+    // Hoshen-Kopelman labelling: a raster scan bonds every site to its previous
+    // neighbours, with the label equivalences resolved by union-find
+    pub fn new<R: Rng>(lattice: &Lattice, kind: ClusterKind, rng: &mut R) -> Self {
+        if kind == ClusterKind::FortuinKasteleyn {
+            let interactions = &lattice.settings.interactions;
+            assert!(
+                !interactions.has_diagonal()
+                    && !interactions.has_axial()
+                    && interactions.long_range.is_none(),
+                "Fortuin-Kasteleyn clusters need nearest-neighbour interactions"
+            );
+        }
+        let probability = 1.0 - (-2.0 * lattice.settings.beta).exp();
+
+        let fields = lattice.get_fields();
+        let mut union_find = UnionFind::new(fields.len());
+        for (position, field) in fields.iter().enumerate() {
+            let site = lattice.get(position);
+            let site = site.read().unwrap();
+            for (d, previous) in site.previous.iter().enumerate() {
+                // Open boundaries have no previous neighbour on the first layer
+                let Some(previous) = previous else {
+                    continue;
+                };
+                let neighbour = previous.read().unwrap().position;
+                let aligned = fields[neighbour] == *field;
+                let bonded = match kind {
+                    ClusterKind::Geometric => aligned,
+                    // Only satisfied bonds, which antiperiodic boundaries reverse
+                    ClusterKind::FortuinKasteleyn => {
+                        aligned == (site.previous_coupling[d] > 0.0)
+                            && rng.random::<f64>() < probability
+                    }
+                };
+                if bonded {
+                    let mut displacement = [0; DIMENSIONS];
+                    displacement[d] = -1;
+                    union_find.union(position, neighbour, displacement);
+                }
+            }
+        }
+        let settings = &lattice.settings;
+        let open = std::array::from_fn(|d| {
+            !settings
+                .boundary_conditions
+                .wraps(d, settings.boundary_axis)
+        });
+        Self::from_union_find(&mut union_find, open)
+    }
+
+    fn from_union_find(union_find: &mut UnionFind, open: [bool; DIMENSIONS]) -> Self {
+        let sites = union_find.parent.len();
+        let mut cluster_of_root = vec![usize::MAX; sites];
+        let mut labels = Vec::with_capacity(sites);
+        let mut sizes = Vec::new();
+        let mut percolating = Vec::new();
+        // Extent of every cluster in unwrapped coordinates
+        let mut extents: Vec<[(isize, isize); DIMENSIONS]> = Vec::new();
+
+        for position in 0..sites {
+            let (root, offset) = union_find.find(position);
+            if cluster_of_root[root] == usize::MAX {
+                cluster_of_root[root] = sizes.len();
+                sizes.push(union_find.size[root]);
+                percolating.push(union_find.wraps[root]);
+                extents.push([(isize::MAX, isize::MIN); DIMENSIONS]);
+            }
+            let cluster = cluster_of_root[root];
+            labels.push(cluster);
+            for (extent, x) in extents[cluster].iter_mut().zip(offset) {
+                *extent = (extent.0.min(x), extent.1.max(x));
+            }
+        }
+
+        // Along open axes clusters span when they touch both boundaries
+        for (percolating, extent) in percolating.iter_mut().zip(extents.iter()) {
+            for d in (0..DIMENSIONS).filter(|d| open[*d]) {
+                percolating[d] = extent[d].1 - extent[d].0 == LATTICE_SIZE as isize - 1;
+            }
+        }
+
+        Self {
+            labels,
+            sizes,
+            percolating,
+        }
+    }
+
+    pub fn largest(&self) -> usize {
+        self.sizes.iter().copied().max().unwrap_or(0)
+    }
+}
+
+// Cluster statistics averaged over configurations: the cluster-size distribution n_s,
+// the fraction of sites in the largest cluster, the probability of a percolating
+// cluster along every axis and the percolation strength, the fraction of sites in
+// percolating clusters
+#[derive(Debug, Clone)]
+pub struct Percolation {
+    pub kind: ClusterKind,
+    // Number of clusters of every size, summed over the measurements
+    size_counts: Vec<usize>,
+    sites: usize,
+    largest_fractions: Vec<f64>,
+    percolating: [Vec<f64>; DIMENSIONS],
+    strengths: Vec<f64>,
+}
+
+impl Percolation {
+    pub fn new(kind: ClusterKind) -> Self {
+        Self {
+            kind,
+            size_counts: Vec::new(),
+            sites: 0,
+            largest_fractions: Vec::new(),
+            percolating: [const { Vec::new() }; DIMENSIONS],
+            strengths: Vec::new(),
+        }
+    }
+
+    pub fn measure<R: Rng>(&mut self, lattice: &Lattice, rng: &mut R) {
+        self.measure_labels(&ClusterLabels::new(lattice, self.kind, rng));
+    }
+
+    pub fn measure_labels(&mut self, clusters: &ClusterLabels) {
+        let sites = clusters.labels.len();
+        self.sites = sites;
+        if self.size_counts.len() <= sites {
+            self.size_counts.resize(sites + 1, 0);
+        }
+        clusters
+            .sizes
+            .iter()
+            .for_each(|size| self.size_counts[*size] += 1);
+
+        self.largest_fractions
+            .push(clusters.largest() as f64 / sites as f64);
+        for (d, series) in self.percolating.iter_mut().enumerate() {
+            let percolates = clusters.percolating.iter().any(|axes| axes[d]);
+            series.push(percolates as u8 as f64);
+        }
+        let percolating_sites: usize = clusters
+            .sizes
+            .iter()
+            .zip(clusters.percolating.iter())
+            .filter(|(_, axes)| axes.iter().any(|percolates| *percolates))
+            .map(|(size, _)| size)
+            .sum();
+        self.strengths.push(percolating_sites as f64 / sites as f64);
+    }
+
+    pub fn len(&self) -> usize {
+        self.strengths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strengths.is_empty()
+    }
+
+    // Clusters of size s per site, for the sizes that occurred
+    pub fn size_distribution(&self) -> Vec<(usize, f64)> {
+        let normalisation = (self.len() * self.sites) as f64;
+        self.size_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(size, count)| (size, *count as f64 / normalisation))
+            .collect()
+    }
+
+    pub fn largest_cluster_fraction(&self, blocks: usize) -> Estimate {
+        jackknife_mean(&self.largest_fractions, blocks)
+    }
+
+    // Spanning probability with open boundaries, wrapping probability with periodic ones
+    pub fn percolation_probabilities(&self, blocks: usize) -> Vec<Estimate> {
+        self.percolating
+            .iter()
+            .map(|series| jackknife_mean(series, blocks))
+            .collect()
+    }
+
+    pub fn percolation_strength(&self, blocks: usize) -> Estimate {
+        jackknife_mean(&self.strengths, blocks)
+    }
+}
+
+impl Checkpoint for Percolation {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.u8(match self.kind {
+            ClusterKind::Geometric => 0,
+            ClusterKind::FortuinKasteleyn => 1,
+        });
+        writer.usizes(&self.size_counts);
+        writer.u64(self.sites as u64);
+        writer.f64s(&self.largest_fractions);
+        self.percolating
+            .iter()
+            .for_each(|series| writer.f64s(series));
+        writer.f64s(&self.strengths);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> io::Result<Self> {
+        let kind = match reader.u8()? {
+            0 => ClusterKind::Geometric,
+            1 => ClusterKind::FortuinKasteleyn,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid cluster kind in checkpoint",
+                ));
+            }
+        };
+        let size_counts = reader.usizes()?;
+        let sites = reader.u64()? as usize;
+        let largest_fractions = reader.f64s()?;
+        let mut percolating = [const { Vec::new() }; DIMENSIONS];
+        for series in percolating.iter_mut() {
+            *series = reader.f64s()?;
+        }
+        Ok(Self {
+            kind,
+            size_counts,
+            sites,
+            largest_fractions,
+            percolating,
+            strengths: reader.f64s()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::initialisation::Initialisation;
+    use crate::field::ising::IsingField;
+    use crate::geometry::lattice_geometry::boundary_conditions::BoundaryConditions;
+    use crate::geometry::utils::{chessboard, position_to_lattice};
+    use crate::settings::SettingsBuilder;
+
+    fn lattice_with(boundary: BoundaryConditions, spin: impl Fn(usize) -> IsingField) -> Lattice {
+        let mut lattice = Lattice::new(
+            SettingsBuilder::new()
+                .add_boundary_conditions(boundary)
+                .add_site_initialisation(Initialisation::Uniform)
+                .build(),
+        )
+        .unwrap();
+        for position in 0..lattice.len() {
+            lattice.get(position).write().unwrap().field = spin(position);
+        }
+        lattice.refresh_totals();
+        lattice
+    }
+
+    #[test]
+    fn test_uniform_clusters() {
+        let mut rng = rand::rng();
+        for boundary in [BoundaryConditions::Periodic, BoundaryConditions::Open] {
+            let lattice = lattice_with(boundary, |_| IsingField::Up);
+            let clusters = ClusterLabels::new(&lattice, ClusterKind::Geometric, &mut rng);
+            assert_eq!(clusters.sizes, vec![lattice.len()]);
+            assert_eq!(clusters.percolating, vec![[true; DIMENSIONS]]);
+        }
+
+        // No bonds at infinite temperature
+        let lattice = Lattice::new(SettingsBuilder::new().add_beta(0.0).build()).unwrap();
+        let clusters = ClusterLabels::new(&lattice, ClusterKind::FortuinKasteleyn, &mut rng);
+        assert_eq!(clusters.sizes, vec![1; lattice.len()]);
+        assert!(clusters.percolating.iter().all(|axes| !axes[0]));
+    }
+
+    #[test]
+    fn test_slab_clusters() {
+        // Slabs of two layers along the first axis
+        let slab = |position: usize| match position_to_lattice(position)[0] < 2 {
+            true => IsingField::Up,
+            false => IsingField::Down,
+        };
+        let mut rng = rand::rng();
+        let lattice = lattice_with(BoundaryConditions::Periodic, slab);
+        let clusters = ClusterLabels::new(&lattice, ClusterKind::Geometric, &mut rng);
+        assert_eq!(clusters.sizes, vec![32, 32]);
+        assert_eq!(clusters.labels[0], 0);
+        for axes in clusters.percolating.iter() {
+            assert_eq!(axes, &[false, true, true]);
+        }
+
+        // A wrapping line is not wrapping with open boundaries, but it spans
+        let line = |position: usize| match position_to_lattice(position)[1..] == [0, 0] {
+            true => IsingField::Up,
+            false => IsingField::Down,
+        };
+        for (boundary, expected) in [
+            (BoundaryConditions::Periodic, [true, false, false]),
+            (BoundaryConditions::Open, [true, false, false]),
+        ] {
+            let lattice = lattice_with(boundary, line);
+            let clusters = ClusterLabels::new(&lattice, ClusterKind::Geometric, &mut rng);
+            assert_eq!(clusters.sizes, vec![4, 60]);
+            assert_eq!(clusters.percolating[0], expected);
+        }
+
+        // A line of 3 sites neither wraps nor spans
+        let short = |position: usize| {
+            let x = position_to_lattice(position);
+            match x[0] < 3 && x[1..] == [0, 0] {
+                true => IsingField::Up,
+                false => IsingField::Down,
+            }
+        };
+        let lattice = lattice_with(BoundaryConditions::Periodic, short);
+        let clusters = ClusterLabels::new(&lattice, ClusterKind::Geometric, &mut rng);
+        assert_eq!(clusters.percolating[0], [false; DIMENSIONS]);
+    }
+
+    #[test]
+    fn test_percolation() {
+        let mut rng = rand::rng();
+        let mut percolation = Percolation::new(ClusterKind::Geometric);
+        let lattice = lattice_with(BoundaryConditions::Periodic, |_| IsingField::Down);
+        percolation.measure(&lattice, &mut rng);
+
+        // The chessboard has only isolated sites
+        let lattice = lattice_with(BoundaryConditions::Periodic, |position| {
+            match chessboard(position_to_lattice(position)) {
+                true => IsingField::Up,
+                false => IsingField::Down,
+            }
+        });
+        percolation.measure(&lattice, &mut rng);
+
+        assert_eq!(percolation.len(), 2);
+        assert_eq!(
+            percolation.size_distribution(),
+            vec![(1, 0.5), (64, 0.5 / 64.0)]
+        );
+        let largest = percolation.largest_cluster_fraction(2);
+        assert_eq!(largest.value, 0.5 + 0.5 / 64.0);
+        for probability in percolation.percolation_probabilities(2) {
+            assert_eq!(probability.value, 0.5);
+        }
+        assert_eq!(percolation.percolation_strength(2).value, 0.5);
+    }
+}
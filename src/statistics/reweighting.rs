@@ -0,0 +1,289 @@
+use crate::montecarlo::wang_landau::log_sum_exp;
+use crate::statistics::jackknife::{Estimate, jackknife_error, remove_block};
+use crate::statistics::time_series::TimeSeries;
+use rayon::prelude::*;
+
+// Convergence of the self-consistent free energies
+const TOLERANCE: f64 = 1e-10;
+const MAX_ITERATIONS: usize = 100000;
+// Grid points scanned before refining a peak
+const PEAK_GRID: usize = 64;
+
+// Canonical averages per site at a single beta
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ReweightedAverages {
+    pub beta: f64,
+    pub energy: f64,
+    pub magnetization: f64,
+    pub susceptibility: f64,
+    pub specific_heat: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Peak {
+    pub beta: Estimate,
+    pub height: Estimate,
+}
+
+// Multiple histogram reweighting (Ferrenberg-Swendsen, WHAM) of runs at different
+// betas. With a single run it reduces to single histogram reweighting.
+#[derive(Debug, Clone)]
+pub struct Reweighting {
+    pub runs: Vec<TimeSeries>,
+    // Dimensionless free energies f_k = -ln Z(beta_k), with f_0 = 0
+    pub free_energies: Vec<f64>,
+    // ln sum_j n_j exp(-beta_j E_s + f_j) for every sample s of every run
+    ln_denominators: Vec<f64>,
+}
+
+impl Reweighting {
+    pub fn new(runs: Vec<TimeSeries>) -> Self {
+        assert!(!runs.is_empty(), "Reweighting needs at least one run");
+        let mut reweighting = Self {
+            free_energies: vec![0.0; runs.len()],
+            ln_denominators: Vec::new(),
+            runs,
+        };
+        reweighting.solve();
+        reweighting
+    }
+
+    fn energies(&self) -> impl Iterator<Item = &f64> + Clone {
+        self.runs.iter().flat_map(|run| run.energies.iter())
+    }
+
+    fn magnetizations(&self) -> impl Iterator<Item = &f64> {
+        self.runs.iter().flat_map(|run| run.magnetizations.iter())
+    }
+
+    fn update_denominators(&mut self) {
+        let energies: Vec<f64> = self.energies().copied().collect();
+        self.ln_denominators = energies
+            .par_iter()
+            .map(|energy| {
+                log_sum_exp(self.runs.iter().zip(self.free_energies.iter()).map(
+                    |(run, free_energy)| (run.len() as f64).ln() - run.beta * energy + free_energy,
+                ))
+            })
+            .collect();
+    }
+
+    // Iterate the self-consistent equations for the free energies
+    fn solve(&mut self) {
+        for _ in 0..MAX_ITERATIONS {
+            self.update_denominators();
+            let mut free_energies: Vec<f64> = self
+                .runs
+                .iter()
+                .map(|run| -self.ln_partition_function(run.beta))
+                .collect();
+            let reference = free_energies[0];
+            free_energies.iter_mut().for_each(|f| *f -= reference);
+
+            let change = free_energies
+                .iter()
+                .zip(self.free_energies.iter())
+                .map(|(new, old)| (new - old).abs())
+                .fold(0.0, f64::max);
+            self.free_energies = free_energies;
+            if change < TOLERANCE {
+                break;
+            }
+        }
+        self.update_denominators();
+    }
+
+    fn ln_partition_function(&self, beta: f64) -> f64 {
+        log_sum_exp(
+            self.energies()
+                .zip(self.ln_denominators.iter())
+                .map(|(energy, ln_denominator)| -beta * energy - ln_denominator),
+        )
+    }
+
+    pub fn averages(&self, beta: f64) -> ReweightedAverages {
+        let sites = self.runs[0].sites as f64;
+        let ln_z = self.ln_partition_function(beta);
+
+        let (mut e, mut e2, mut m, mut m2) = (0.0, 0.0, 0.0, 0.0);
+        for ((energy, magnetization), ln_denominator) in self
+            .energies()
+            .zip(self.magnetizations())
+            .zip(self.ln_denominators.iter())
+        {
+            let weight = (-beta * energy - ln_denominator - ln_z).exp();
+            let (energy, magnetization) = (energy / sites, magnetization.abs() / sites);
+            e += weight * energy;
+            e2 += weight * energy * energy;
+            m += weight * magnetization;
+            m2 += weight * magnetization * magnetization;
+        }
+
+        ReweightedAverages {
+            beta,
+            energy: e,
+            magnetization: m,
+            susceptibility: beta * sites * (m2 - m * m),
+            specific_heat: beta * beta * sites * (e2 - e * e),
+        }
+    }
+
+    // Range of betas covered by the runs
+    pub fn beta_range(&self) -> (f64, f64) {
+        let betas = self.runs.iter().map(|run| run.beta);
+        (
+            betas.clone().fold(f64::INFINITY, f64::min),
+            betas.fold(f64::NEG_INFINITY, f64::max),
+        )
+    }
+
+    // Maximum of an observable in [from, to]: grid scan refined by golden section
+    pub fn find_peak<F: Fn(&ReweightedAverages) -> f64>(
+        &self,
+        observable: F,
+        from: f64,
+        to: f64,
+    ) -> (f64, f64) {
+        let value = |beta: f64| observable(&self.averages(beta));
+        let step = (to - from) / PEAK_GRID as f64;
+        let best = (0..=PEAK_GRID)
+            .map(|i| from + i as f64 * step)
+            .max_by(|a, b| value(*a).total_cmp(&value(*b)))
+            .unwrap();
+
+        let ratio = (5.0_f64.sqrt() - 1.0) / 2.0;
+        let (mut a, mut b) = ((best - step).max(from), (best + step).min(to));
+        while b - a > 1e-8 * step.max(f64::EPSILON) {
+            let (c, d) = (b - ratio * (b - a), a + ratio * (b - a));
+            if value(c) > value(d) {
+                b = d;
+            } else {
+                a = c;
+            }
+        }
+        let beta = 0.5 * (a + b);
+        (beta, value(beta))
+    }
+
+    // Reweightings of the runs with one block of every time series left out, with at
+    // most as many blocks as the shortest run has measurements
+    pub fn jackknife_samples(&self, blocks: usize) -> Vec<Reweighting> {
+        let blocks = self
+            .runs
+            .iter()
+            .map(|run| run.len())
+            .fold(blocks, usize::min);
+        (0..blocks)
+            .into_par_iter()
+            .map(|block| {
+                Reweighting::new(
+                    self.runs
+                        .iter()
+                        .map(|run| TimeSeries {
+                            energies: remove_block(&run.energies, block, blocks),
+                            magnetizations: remove_block(&run.magnetizations, block, blocks),
+                            ..run.clone()
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn peak_with_errors<F: Fn(&ReweightedAverages) -> f64 + Copy>(
+        &self,
+        observable: F,
+        from: f64,
+        to: f64,
+        blocks: usize,
+    ) -> Peak {
+        let (beta, height) = self.find_peak(observable, from, to);
+        let samples: Vec<(f64, f64)> = self
+            .jackknife_samples(blocks)
+            .iter()
+            .map(|sample| sample.find_peak(observable, from, to))
+            .collect();
+        let betas: Vec<f64> = samples.iter().map(|(beta, _)| *beta).collect();
+        let heights: Vec<f64> = samples.iter().map(|(_, height)| *height).collect();
+        Peak {
+            beta: Estimate {
+                value: beta,
+                error: jackknife_error(&betas),
+            },
+            height: Estimate {
+                value: height,
+                error: jackknife_error(&heights),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_level_run(beta: f64, samples: usize) -> TimeSeries {
+        // Exact samples of a two-level system with E = -1, +1 and M = E
+        let up = (samples as f64 / (1.0 + (-2.0 * beta).exp())).round() as usize;
+        let mut run = TimeSeries::new(beta, 1);
+        for i in 0..samples {
+            let energy = if i < up { -1.0 } else { 1.0 };
+            run.push(energy, energy);
+        }
+        run
+    }
+
+    #[test]
+    fn test_single_histogram_reweighting() {
+        let reweighting = Reweighting::new(vec![two_level_run(0.5, 10000)]);
+        assert_eq!(reweighting.free_energies, vec![0.0]);
+
+        // Reweighting to the same beta is a plain average
+        let run = &reweighting.runs[0];
+        let mean = run.energies.iter().sum::<f64>() / run.len() as f64;
+        assert!((reweighting.averages(0.5).energy - mean).abs() < 1e-12);
+
+        // And reproduces the exact average at a nearby beta
+        assert!((reweighting.averages(0.6).energy + 0.6_f64.tanh()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_multiple_histogram_reweighting() {
+        let reweighting =
+            Reweighting::new(vec![two_level_run(0.2, 10000), two_level_run(0.4, 10000)]);
+
+        // f_k = -ln Z(beta_k), relative to the first run
+        let ln_z = |beta: f64| (2.0 * beta.cosh()).ln();
+        let expected = -(ln_z(0.4) - ln_z(0.2));
+        assert!((reweighting.free_energies[1] - expected).abs() < 1e-3);
+        assert!((reweighting.averages(0.3).energy + 0.3_f64.tanh()).abs() < 1e-3);
+        assert_eq!(reweighting.beta_range(), (0.2, 0.4));
+    }
+
+    #[test]
+    fn test_find_peak() {
+        // The specific heat of the two-level system peaks at beta tanh(beta) = 1
+        let reweighting =
+            Reweighting::new(vec![two_level_run(1.0, 10000), two_level_run(1.4, 10000)]);
+        let (beta, _) = reweighting.find_peak(|averages| averages.specific_heat, 1.0, 1.4);
+        assert!((beta - 1.19967864).abs() < 1e-3);
+
+        let peak = reweighting.peak_with_errors(|averages| averages.specific_heat, 1.0, 1.4, 4);
+        assert_eq!(peak.beta.value, beta);
+        assert!(peak.beta.error >= 0.0);
+    }
+
+    #[test]
+    fn test_peak_errors_of_short_runs() {
+        // More blocks than measurements: one block per measurement
+        let reweighting = Reweighting::new(vec![two_level_run(1.0, 8), two_level_run(1.4, 4)]);
+        assert_eq!(reweighting.jackknife_samples(10).len(), 4);
+        let peak = reweighting.peak_with_errors(|averages| averages.energy, 1.0, 1.4, 10);
+        assert!(peak.height.error > 0.0);
+
+        // A single measurement leaves the error unknown
+        let reweighting = Reweighting::new(vec![two_level_run(1.0, 1)]);
+        let peak = reweighting.peak_with_errors(|averages| averages.energy, 1.0, 1.4, 10);
+        assert!(peak.height.error.is_nan());
+    }
+}
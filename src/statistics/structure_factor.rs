@@ -0,0 +1,324 @@
+use crate::field::ising::IsingField;
+use crate::geometry::lattice_geometry::lattice::Lattice;
+use crate::geometry::utils::position_to_lattice;
+use crate::output::checkpoint::{Checkpoint, CheckpointReader, CheckpointWriter};
+use crate::settings::{DIMENSIONS, LATTICE_SIZE};
+use crate::statistics::derived::Resampling;
+use crate::statistics::jackknife::Estimate;
+use std::f64::consts::PI;
+use std::io;
+use std::ops::{Add, Mul};
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    // exp(i phase)
+    pub fn phase(phase: f64) -> Self {
+        Self::new(phase.cos(), phase.sin())
+    }
+
+    pub fn norm_squared(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+// Mixed-radix Cooley-Tukey transform X_k = sum_x x_x exp(-2 pi i k x / n), falling
+// back to the plain sum for prime lengths
+pub fn fft(input: &[Complex]) -> Vec<Complex> {
+    let n = input.len();
+    if n <= 1 {
+        return input.to_vec();
+    }
+    let p = (2..=n).find(|p| n.is_multiple_of(*p)).unwrap();
+    let twiddle = |k: usize| Complex::phase(-2.0 * PI * k as f64 / n as f64);
+    if p == n {
+        return (0..n)
+            .map(|k| {
+                input
+                    .iter()
+                    .enumerate()
+                    .fold(Complex::default(), |sum, (x, value)| {
+                        sum + *value * twiddle(k * x % n)
+                    })
+            })
+            .collect();
+    }
+
+    // Transforms of the p interleaved subsequences of length n / p
+    let m = n / p;
+    let subsequences: Vec<Vec<Complex>> = (0..p)
+        .map(|j| fft(&input.iter().skip(j).step_by(p).copied().collect::<Vec<_>>()))
+        .collect();
+    (0..n)
+        .map(|k| {
+            subsequences
+                .iter()
+                .enumerate()
+                .fold(Complex::default(), |sum, (j, subsequence)| {
+                    sum + subsequence[k % m] * twiddle(j * k % n)
+                })
+        })
+        .collect()
+}
+
+// Fourier transform of the spins over the whole lattice
+pub fn fourier_transform(fields: &[IsingField]) -> Vec<Complex> {
+    lattice_fft(
+        fields
+            .iter()
+            .map(|field| Complex::new(field.value(), 0.0))
+            .collect(),
+    )
+}
+
+// Fourier transform of one value per site, one axis at a time
+pub fn lattice_fft(mut values: Vec<Complex>) -> Vec<Complex> {
+    for dimension in 0..DIMENSIONS {
+        let stride = usize::pow(LATTICE_SIZE, dimension as u32);
+        // Lines along the axis start at the positions with a zero coordinate on it
+        for start in (0..values.len()).filter(|x| position_to_lattice(*x)[dimension] == 0) {
+            let line: Vec<Complex> = (0..LATTICE_SIZE)
+                .map(|i| values[start + i * stride])
+                .collect();
+            for (i, value) in fft(&line).into_iter().enumerate() {
+                values[start + i * stride] = value;
+            }
+        }
+    }
+    values
+}
+
+// Magnetization at momentum 2 pi / L along one axis, the smallest nonzero momentum
+pub fn fourier_magnetization(fields: &[IsingField], dimension: usize) -> Complex {
+    let k = 2.0 * PI / LATTICE_SIZE as f64;
+    fields
+        .iter()
+        .enumerate()
+        .fold(Complex::default(), |sum, (position, field)| {
+            let x = position_to_lattice(position)[dimension] as f64;
+            sum + Complex::phase(-k * x) * Complex::new(field.value(), 0.0)
+        })
+}
+
+// Structure factor S(k) = <|m(k)|^2> / N over the Brillouin zone and the second-moment
+// correlation length xi_2 = sqrt(S(0) / S(k_min) - 1) / (2 sin(k_min / 2))
+#[derive(Debug, Clone, Default)]
+pub struct StructureFactor {
+    sums: Vec<f64>,
+    // S(0) and S(k_min) averaged over the axes, per measurement
+    zero_momentum: Vec<f64>,
+    minimal_momentum: Vec<f64>,
+}
+
+impl StructureFactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn measure(&mut self, lattice: &Lattice) {
+        self.measure_fields(&lattice.get_fields());
+    }
+
+    pub fn measure_fields(&mut self, fields: &[IsingField]) {
+        let sites = fields.len() as f64;
+        let transform = fourier_transform(fields);
+        if self.sums.is_empty() {
+            self.sums = vec![0.0; transform.len()];
+        }
+        for (sum, value) in self.sums.iter_mut().zip(transform.iter()) {
+            *sum += value.norm_squared() / sites;
+        }
+
+        // The smallest momenta along the axes sit at offsets L^d
+        self.zero_momentum.push(transform[0].norm_squared() / sites);
+        self.minimal_momentum.push(
+            (0..DIMENSIONS)
+                .map(|d| transform[usize::pow(LATTICE_SIZE, d as u32)].norm_squared() / sites)
+                .sum::<f64>()
+                / DIMENSIONS as f64,
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.zero_momentum.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zero_momentum.is_empty()
+    }
+
+    // Momentum of every entry folded into (-pi, pi], with the averaged S(k)
+    pub fn structure_factor(&self) -> Vec<([f64; DIMENSIONS], f64)> {
+        self.sums
+            .iter()
+            .enumerate()
+            .map(|(position, sum)| {
+                let momentum = position_to_lattice(position).map(|n| {
+                    let n = if 2 * n > LATTICE_SIZE {
+                        n as f64 - LATTICE_SIZE as f64
+                    } else {
+                        n as f64
+                    };
+                    2.0 * PI * n / LATTICE_SIZE as f64
+                });
+                (momentum, sum / self.len() as f64)
+            })
+            .collect()
+    }
+
+    fn correlation_length_of(zero_momentum: f64, minimal_momentum: f64) -> f64 {
+        let ratio = zero_momentum / minimal_momentum;
+        let k = 2.0 * PI / LATTICE_SIZE as f64;
+        // Fluctuations can push the ratio below 1 when xi is much smaller than a site
+        (ratio - 1.0).max(0.0).sqrt() / (2.0 * (k / 2.0).sin())
+    }
+
+    // Second-moment correlation length, nonlinear in S(0) and S(k_min)
+    pub fn correlation_length(&self, resampling: &Resampling) -> Estimate {
+        resampling.estimate(&[&self.zero_momentum, &self.minimal_momentum], |means| {
+            Self::correlation_length_of(means[0], means[1])
+        })
+    }
+}
+
+impl Checkpoint for StructureFactor {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.f64s(&self.sums);
+        writer.f64s(&self.zero_momentum);
+        writer.f64s(&self.minimal_momentum);
+    }
+
+    fn load(reader: &mut CheckpointReader) -> io::Result<Self> {
+        Ok(Self {
+            sums: reader.f64s()?,
+            zero_momentum: reader.f64s()?,
+            minimal_momentum: reader.f64s()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::initialisation::Initialisation;
+    use crate::settings::SettingsBuilder;
+    use crate::statistics::derived::ErrorMethod;
+
+    fn dft(input: &[Complex]) -> Vec<Complex> {
+        let n = input.len();
+        (0..n)
+            .map(|k| {
+                input
+                    .iter()
+                    .enumerate()
+                    .fold(Complex::default(), |sum, (x, v)| {
+                        sum + *v * Complex::phase(-2.0 * PI * (k * x) as f64 / n as f64)
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fft() {
+        // Composite and prime lengths agree with the plain transform
+        for n in [1, 4, 6, 7, 12, 20] {
+            let input: Vec<Complex> = (0..n)
+                .map(|x| Complex::new((x * x % 5) as f64, x as f64 * 0.5))
+                .collect();
+            for (a, b) in fft(&input).iter().zip(dft(&input)) {
+                assert!((a.re - b.re).abs() < 1e-9 && (a.im - b.im).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_structure_factor() {
+        let lattice = Lattice::new(
+            SettingsBuilder::new()
+                .add_site_initialisation(Initialisation::Random)
+                .build(),
+        )
+        .unwrap();
+        let fields = lattice.get_fields();
+        let transform = fourier_transform(&fields);
+
+        // m(0) is the magnetization and the smallest momenta match the direct sums
+        assert!((transform[0].re - lattice.get_magnetization()).abs() < 1e-9);
+        for d in 0..DIMENSIONS {
+            let direct = fourier_magnetization(&fields, d);
+            let fft = transform[usize::pow(LATTICE_SIZE, d as u32)];
+            assert!((direct.re - fft.re).abs() < 1e-9 && (direct.im - fft.im).abs() < 1e-9);
+        }
+
+        // Parseval: the structure factor sums to N
+        let mut structure_factor = StructureFactor::new();
+        structure_factor.measure(&lattice);
+        let total: f64 = structure_factor
+            .structure_factor()
+            .iter()
+            .map(|(_, s)| s)
+            .sum();
+        assert!((total - lattice.len() as f64).abs() < 1e-9);
+        assert_eq!(structure_factor.structure_factor()[0].0, [0.0; DIMENSIONS]);
+    }
+
+    #[test]
+    fn test_correlation_length() {
+        // All the weight at k = 0: the correlation length diverges, up to rounding
+        let mut structure_factor = StructureFactor::new();
+        let lattice = Lattice::new(SettingsBuilder::new().build()).unwrap();
+        structure_factor.measure(&lattice);
+        assert!(
+            structure_factor
+                .correlation_length(&Resampling::new(ErrorMethod::Jackknife, 1))
+                .value
+                > 1e12
+        );
+
+        // Equal weights at k = 0 and k_min: no correlations
+        let mut structure_factor = StructureFactor {
+            zero_momentum: vec![1.0, 2.0, 1.0, 2.0],
+            minimal_momentum: vec![1.0, 2.0, 1.0, 2.0],
+            ..StructureFactor::new()
+        };
+        assert_eq!(
+            structure_factor
+                .correlation_length(&Resampling::new(ErrorMethod::Jackknife, 2))
+                .value,
+            0.0
+        );
+
+        // S(0) / S(k_min) = 1 + 4 sin^2(k_min / 2) gives xi = 1
+        let k = 2.0 * PI / LATTICE_SIZE as f64;
+        structure_factor.zero_momentum = vec![1.0 + 4.0 * (k / 2.0).sin().powi(2); 4];
+        structure_factor.minimal_momentum = vec![1.0; 4];
+        let xi = structure_factor.correlation_length(&Resampling::new(ErrorMethod::Jackknife, 2));
+        assert!((xi.value - 1.0).abs() < 1e-12);
+        assert!(xi.error.abs() < 1e-12);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::statistics::binning::BinningAnalysis;
+use crate::statistics::derived::Resampling;
+use crate::statistics::jackknife::Estimate;
+use crate::statistics::structure_factor::StructureFactor;
+use crate::statistics::time_series::TimeSeries;
+
+// Averages per site of a run with binning errors, and the derived quantities with
+// resampling errors
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Summary {
+    pub energy: Estimate,
+    pub magnetization: Estimate,
+    pub absolute_magnetization: Estimate,
+    pub susceptibility: Estimate,
+    pub specific_heat: Estimate,
+    // U4 = 1 - <m^4> / (3 <m^2>^2)
+    pub binder_cumulant: Estimate,
+    // Second-moment correlation length xi_2, when the structure factor is measured
+    pub correlation_length: Option<Estimate>,
+}
+
+impl Summary {
+    pub fn new(
+        time_series: &TimeSeries,
+        structure_factor: Option<&StructureFactor>,
+        resampling: &Resampling,
+    ) -> Self {
+        let sites = time_series.sites as f64;
+        let beta = time_series.beta;
+        let per_site = |series: &[f64]| -> Vec<f64> { series.iter().map(|x| x / sites).collect() };
+        let energy = per_site(&time_series.energies);
+        let magnetization = per_site(&time_series.magnetizations);
+        let absolute: Vec<f64> = magnetization.iter().map(|m| m.abs()).collect();
+        let power =
+            |series: &[f64], n: i32| -> Vec<f64> { series.iter().map(|x| x.powi(n)).collect() };
+        let (energy2, magnetization2, magnetization4) = (
+            power(&energy, 2),
+            power(&magnetization, 2),
+            power(&magnetization, 4),
+        );
+
+        Self {
+            energy: BinningAnalysis::new(&energy).estimate,
+            magnetization: BinningAnalysis::new(&magnetization).estimate,
+            absolute_magnetization: BinningAnalysis::new(&absolute).estimate,
+            susceptibility: resampling.estimate(&[&absolute, &magnetization2], |m| {
+                beta * sites * (m[1] - m[0] * m[0])
+            }),
+            specific_heat: resampling.estimate(&[&energy, &energy2], |m| {
+                beta * beta * sites * (m[1] - m[0] * m[0])
+            }),
+            binder_cumulant: resampling.estimate(&[&magnetization2, &magnetization4], |m| {
+                1.0 - m[1] / (3.0 * m[0] * m[0])
+            }),
+            correlation_length: structure_factor
+                .map(|structure_factor| structure_factor.correlation_length(resampling)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statistics::derived::ErrorMethod;
+
+    #[test]
+    fn test_summary() {
+        // Perfectly ordered run: no fluctuations, U4 = 2/3
+        let mut time_series = TimeSeries::new(0.5, 4);
+        for i in 0..64 {
+            let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+            time_series.push(-8.0, 4.0 * sign);
+        }
+        let resampling = Resampling::new(ErrorMethod::Jackknife, 8);
+        let summary = Summary::new(&time_series, None, &resampling);
+        assert_eq!(summary.energy.value, -2.0);
+        assert_eq!(summary.energy.error, 0.0);
+        assert_eq!(summary.magnetization.value, 0.0);
+        assert_eq!(summary.absolute_magnetization.value, 1.0);
+        assert_eq!(summary.susceptibility.value, 0.0);
+        assert_eq!(summary.specific_heat.value, 0.0);
+        assert!((summary.binder_cumulant.value - 2.0 / 3.0).abs() < 1e-12);
+        assert!(summary.binder_cumulant.error < 1e-12);
+        assert_eq!(summary.correlation_length, None);
+
+        // The correlation length is resampled like the other derived quantities
+        let mut structure_factor = StructureFactor::new();
+        for i in 0..64 {
+            let fields = crate::field::initialisation::Initialisation::Random
+                .fields(i)
+                .unwrap();
+            structure_factor.measure_fields(&fields);
+        }
+        let summary = Summary::new(&time_series, Some(&structure_factor), &resampling);
+        let xi = summary.correlation_length.unwrap();
+        assert_eq!(xi, structure_factor.correlation_length(&resampling));
+        assert!(xi.error.is_finite());
+    }
+}
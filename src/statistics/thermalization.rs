@@ -0,0 +1,121 @@
+use crate::statistics::binning::bin_means;
+
+// Batch size of the MSER-5 rule
+pub const MSER_BATCH: usize = 5;
+// Fewest batches before a series is tested
+const MIN_BATCHES: usize = 10;
+
+// Marginal standard error rule: the truncation point d minimising the squared standard
+// error of the mean of the remaining samples, sum_{i >= d} (x_i - mean_d)^2 / (n - d)^2,
+// computed on batch means and searched in the first half of the series. Returns None
+// while the transient has not been resolved yet.
+pub fn mser_truncation(series: &[f64], batch: usize) -> Option<usize> {
+    let batches = bin_means(series, batch);
+    let n = batches.len();
+    if n < MIN_BATCHES {
+        return None;
+    }
+
+    // Suffix sums give the statistic of every truncation point in O(n)
+    let (mut sum, mut sum_squared) = (0.0, 0.0);
+    let mut statistics = vec![0.0; n];
+    for d in (0..n).rev() {
+        sum += batches[d];
+        sum_squared += batches[d] * batches[d];
+        let remaining = (n - d) as f64;
+        statistics[d] = (sum_squared - sum * sum / remaining).max(0.0) / (remaining * remaining);
+    }
+
+    // The statistic still decreasing at the boundary means no stationary phase yet
+    let (d, _) = statistics[..=n / 2]
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap();
+    (d < n / 2).then_some(d * batch)
+}
+
+// Collects observables from the start of a run until the MSER rule finds the end of
+// the initial transient in all of them
+#[derive(Debug, Clone, Default)]
+pub struct EquilibrationDetector {
+    pub series: Vec<Vec<f64>>,
+}
+
+impl EquilibrationDetector {
+    pub fn new(observables: usize) -> Self {
+        Self {
+            series: vec![Vec::new(); observables],
+        }
+    }
+
+    pub fn push(&mut self, values: &[f64]) {
+        for (series, value) in self.series.iter_mut().zip(values) {
+            series.push(*value);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.series.first().map_or(0, |series| series.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Sweeps of the transient, once it is over in every observable
+    pub fn truncation(&self) -> Option<usize> {
+        self.series
+            .iter()
+            .map(|series| mser_truncation(series, MSER_BATCH))
+            .try_fold(0, |d, truncation| truncation.map(|t| d.max(t)))
+    }
+
+    pub fn is_equilibrated(&self) -> bool {
+        !self.series.is_empty() && self.truncation().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    // Exponential relaxation from 10 towards 0 with noise
+    fn relaxation(length: usize, time: f64) -> Vec<f64> {
+        let mut rng = rand::rng();
+        (0..length)
+            .map(|t| 10.0 * (-(t as f64) / time).exp() + rng.random_range(-0.5..0.5))
+            .collect()
+    }
+
+    #[test]
+    fn test_mser_truncation() {
+        let series = relaxation(2000, 50.0);
+        let truncation = mser_truncation(&series, MSER_BATCH).unwrap();
+        assert!((100..1000).contains(&truncation));
+
+        // Too short to resolve the transient
+        assert_eq!(mser_truncation(&relaxation(40, 50.0), MSER_BATCH), None);
+        let series = relaxation(300, 50.0);
+        let truncation = mser_truncation(&series, MSER_BATCH);
+        assert!(truncation.is_none() || truncation.unwrap() >= 100);
+    }
+
+    #[test]
+    fn test_equilibration_detector() {
+        let mut detector = EquilibrationDetector::new(2);
+        let (slow, fast) = (relaxation(4000, 200.0), relaxation(4000, 10.0));
+        for (a, b) in slow.iter().zip(fast.iter()).take(60) {
+            detector.push(&[*a, *b]);
+        }
+        assert!(!detector.is_equilibrated());
+
+        for (a, b) in slow.iter().zip(fast.iter()).skip(60) {
+            detector.push(&[*a, *b]);
+        }
+        assert_eq!(detector.len(), 4000);
+        // The slowest observable sets the truncation
+        assert!(detector.truncation().unwrap() >= 400);
+    }
+}
@@ -0,0 +1,32 @@
+// Energy and magnetization measurements of a run at a single beta
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSeries {
+    pub beta: f64,
+    pub sites: usize,
+    pub energies: Vec<f64>,
+    pub magnetizations: Vec<f64>,
+}
+
+impl TimeSeries {
+    pub fn new(beta: f64, sites: usize) -> Self {
+        Self {
+            beta,
+            sites,
+            energies: Vec::new(),
+            magnetizations: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, energy: f64, magnetization: f64) {
+        self.energies.push(energy);
+        self.magnetizations.push(magnetization);
+    }
+
+    pub fn len(&self) -> usize {
+        self.energies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.energies.is_empty()
+    }
+}